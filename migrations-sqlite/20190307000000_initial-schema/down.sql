@@ -0,0 +1,10 @@
+DROP TABLE configs;
+DROP TABLE modules;
+DROP TABLE enabled_modules;
+
+DROP TABLE irc_channels;
+DROP TABLE irc_configs;
+DROP TABLE irc_permissions;
+
+DROP TABLE dis_configs;
+DROP TABLE dis_permissions;
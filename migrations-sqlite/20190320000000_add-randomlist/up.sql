@@ -0,0 +1,5 @@
+CREATE TABLE mod_randomlist (
+	category TEXT NOT NULL,
+	string TEXT NOT NULL,
+	PRIMARY KEY (category, string)
+);
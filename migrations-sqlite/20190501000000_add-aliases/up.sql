@@ -0,0 +1,5 @@
+CREATE TABLE aliases (
+	name TEXT NOT NULL PRIMARY KEY,
+	target TEXT NOT NULL,
+	transform TEXT
+);
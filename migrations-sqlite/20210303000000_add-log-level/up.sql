@@ -0,0 +1 @@
+ALTER TABLE modules ADD COLUMN log_level TEXT CHECK (log_level IN ('error', 'warn', 'info', 'debug', 'trace'));
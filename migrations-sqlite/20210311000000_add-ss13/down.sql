@@ -0,0 +1,3 @@
+DROP TABLE ss13_servers;
+DROP TABLE ss13_server_names;
+DROP TABLE ss13_server_channels;
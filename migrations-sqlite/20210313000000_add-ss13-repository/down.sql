@@ -0,0 +1 @@
+DROP TABLE ss13_repositories;
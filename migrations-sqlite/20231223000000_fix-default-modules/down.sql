@@ -0,0 +1,3 @@
+INSERT INTO modules (name, enabled) VALUES ('core', true);
+INSERT INTO enabled_modules VALUES ('irc', 'core');
+INSERT INTO enabled_modules VALUES ('discord', 'core');
@@ -0,0 +1,2 @@
+DELETE FROM enabled_modules WHERE name = 'core';
+DELETE FROM modules WHERE name = 'core';
@@ -0,0 +1,2 @@
+DELETE FROM cmdchars WHERE config_id = 'console';
+DELETE FROM configs WHERE id = 'console';
@@ -0,0 +1 @@
+-- nothing to undo; see up.sql
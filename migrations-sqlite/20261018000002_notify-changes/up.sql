@@ -0,0 +1,2 @@
+-- SQLite has no change notifications; the bot invalidates its own caches when it writes, and
+-- anything else that changes the database should be followed by a `reload`.
@@ -0,0 +1,30 @@
+-- grants can't be converted back to flags in general, so the old tables come back empty
+CREATE TABLE irc_permissions (
+	config_id TEXT NOT NULL,
+	nick TEXT NOT NULL,
+	username TEXT NOT NULL,
+	host TEXT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, nick, username, host),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+CREATE TABLE dis_permissions (
+	config_id TEXT NOT NULL,
+	user_id BIGINT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, user_id),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+CREATE TABLE matrix_permissions (
+	config_id TEXT NOT NULL,
+	user_id TEXT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, user_id),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+DROP TABLE command_perms;
+DROP TABLE perm_groups;
+DROP TABLE grants;
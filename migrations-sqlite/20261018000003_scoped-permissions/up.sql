@@ -0,0 +1,39 @@
+-- GRANTS
+-- subject, config_id and channel are glob patterns; permission is a name, or '*' for all of them
+CREATE TABLE grants (
+	subject TEXT NOT NULL,
+	permission TEXT NOT NULL,
+	config_id TEXT NOT NULL DEFAULT '*',
+	channel TEXT NOT NULL DEFAULT '*',
+	PRIMARY KEY (subject, permission, config_id, channel)
+);
+
+-- GROUPS
+-- member is a glob pattern matched against subjects, including other groups' 'group:name'
+CREATE TABLE perm_groups (
+	name TEXT NOT NULL,
+	member TEXT NOT NULL,
+	PRIMARY KEY (name, member)
+);
+
+-- COMMAND OVERRIDES
+-- a NULL permission lets anyone run the command; disabled commands can't be run at all
+CREATE TABLE command_perms (
+	command TEXT NOT NULL,
+	config_id TEXT NOT NULL DEFAULT '*',
+	channel TEXT NOT NULL DEFAULT '*',
+	permission TEXT,
+	enabled BOOLEAN NOT NULL DEFAULT true,
+	PRIMARY KEY (command, config_id, channel)
+);
+
+-- carry over the old per-network flags
+WITH bits (bit, name) AS (VALUES (1, 'admin'), (2, 'raw'), (4, 'database'), (8, 'eval'), (16, 'modules'))
+INSERT INTO grants (subject, permission, config_id)
+SELECT 'irc:' || nick || '!' || username || '@' || host, name, config_id FROM irc_permissions JOIN bits ON flags & bit != 0
+UNION SELECT 'dis:' || user_id, name, config_id FROM dis_permissions JOIN bits ON flags & bit != 0
+UNION SELECT 'mx:' || user_id, name, config_id FROM matrix_permissions JOIN bits ON flags & bit != 0;
+
+DROP TABLE irc_permissions;
+DROP TABLE dis_permissions;
+DROP TABLE matrix_permissions;
@@ -0,0 +1,12 @@
+-- SCHEDULED JOBS
+-- schedule is as parsed by rustbot::schedule::Schedule; next_run is a Unix timestamp
+CREATE TABLE scheduled_jobs (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	handler TEXT NOT NULL,
+	config_id TEXT NOT NULL,
+	channel TEXT NOT NULL,
+	payload TEXT NOT NULL DEFAULT '',
+	schedule TEXT NOT NULL,
+	next_run INTEGER NOT NULL
+);
+CREATE INDEX scheduled_jobs_next_run ON scheduled_jobs (next_run);
@@ -0,0 +1,10 @@
+-- only the aliases that applied everywhere fit the old table
+CREATE TABLE aliases_global (
+	name TEXT NOT NULL PRIMARY KEY,
+	target TEXT NOT NULL,
+	transform TEXT
+);
+INSERT INTO aliases_global (name, target, transform)
+SELECT name, target, transform FROM aliases WHERE config_id = '*' AND channel = '*';
+DROP TABLE aliases;
+ALTER TABLE aliases_global RENAME TO aliases;
@@ -0,0 +1,14 @@
+-- ALIASES
+-- config_id and channel are glob patterns, as for grants; target can list several commands,
+-- separated by ';'
+CREATE TABLE aliases_scoped (
+	name TEXT NOT NULL,
+	config_id TEXT NOT NULL DEFAULT '*',
+	channel TEXT NOT NULL DEFAULT '*',
+	target TEXT NOT NULL,
+	transform TEXT,
+	PRIMARY KEY (name, config_id, channel)
+);
+INSERT INTO aliases_scoped (name, target, transform) SELECT name, target, transform FROM aliases;
+DROP TABLE aliases;
+ALTER TABLE aliases_scoped RENAME TO aliases;
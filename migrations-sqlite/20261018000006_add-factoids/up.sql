@@ -0,0 +1,13 @@
+-- FACTOIDS
+-- every change adds a row, so edits can be reverted; the latest row for a name in a channel (or
+-- '*' for all of the config's channels) is the current text, or NULL once it's been forgotten
+CREATE TABLE mod_factoids (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	name TEXT NOT NULL,
+	config_id TEXT NOT NULL,
+	channel TEXT NOT NULL DEFAULT '*',
+	text TEXT,
+	author TEXT NOT NULL,
+	changed BIGINT NOT NULL
+);
+CREATE INDEX mod_factoids_name ON mod_factoids (config_id, name, channel);
@@ -0,0 +1,5 @@
+DROP TABLE matrix_rooms;
+DROP TABLE matrix_permissions;
+
+DELETE FROM cmdchars WHERE config_id = 'matrix';
+DELETE FROM configs WHERE id = 'matrix';
@@ -0,0 +1,11 @@
+DROP TRIGGER notify_change ON modules;
+DROP TRIGGER notify_change ON enabled_modules;
+DROP TRIGGER notify_change ON cmdchars;
+DROP TRIGGER notify_change ON aliases;
+DROP TRIGGER notify_change ON irc_permissions;
+DROP TRIGGER notify_change ON dis_permissions;
+DROP TRIGGER notify_change ON matrix_permissions;
+DROP TRIGGER notify_change ON mod_bridge;
+DROP TRIGGER notify_change ON mod_debridge;
+
+DROP FUNCTION rustbot_notify_change();
@@ -0,0 +1,17 @@
+-- Tell the bot whenever a table it caches changes, so it can reload it.
+CREATE FUNCTION rustbot_notify_change() RETURNS trigger AS $$
+BEGIN
+	PERFORM pg_notify('rustbot_changes', TG_TABLE_NAME);
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON modules FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON enabled_modules FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON cmdchars FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON aliases FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON irc_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON dis_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON matrix_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON mod_bridge FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON mod_debridge FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
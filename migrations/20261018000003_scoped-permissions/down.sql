@@ -0,0 +1,34 @@
+-- grants can't be converted back to flags in general, so the old tables come back empty
+CREATE TABLE irc_permissions (
+	config_id TEXT NOT NULL,
+	nick TEXT NOT NULL,
+	username TEXT NOT NULL,
+	host TEXT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, nick, username, host),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+CREATE TABLE dis_permissions (
+	config_id TEXT NOT NULL,
+	user_id BIGINT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, user_id),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+CREATE TABLE matrix_permissions (
+	config_id TEXT NOT NULL,
+	user_id TEXT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, user_id),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+DROP TABLE command_perms;
+DROP TABLE perm_groups;
+DROP TABLE grants;
+
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON irc_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON dis_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
+CREATE TRIGGER notify_change AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON matrix_permissions FOR EACH STATEMENT EXECUTE PROCEDURE rustbot_notify_change();
@@ -0,0 +1,12 @@
+-- SCHEDULED JOBS
+-- schedule is as parsed by rustbot::schedule::Schedule; next_run is a Unix timestamp
+CREATE TABLE scheduled_jobs (
+	id BIGSERIAL PRIMARY KEY,
+	handler TEXT NOT NULL,
+	config_id TEXT NOT NULL,
+	channel TEXT NOT NULL,
+	payload TEXT NOT NULL DEFAULT '',
+	schedule TEXT NOT NULL,
+	next_run BIGINT NOT NULL
+);
+CREATE INDEX scheduled_jobs_next_run ON scheduled_jobs (next_run);
@@ -0,0 +1,6 @@
+-- only the aliases that applied everywhere fit the old table
+DELETE FROM aliases WHERE config_id != '*' OR channel != '*';
+ALTER TABLE aliases DROP CONSTRAINT aliases_pkey;
+ALTER TABLE aliases DROP COLUMN channel;
+ALTER TABLE aliases DROP COLUMN config_id;
+ALTER TABLE aliases ADD PRIMARY KEY (name);
@@ -0,0 +1,7 @@
+-- ALIASES
+-- config_id and channel are glob patterns, as for grants; target can list several commands,
+-- separated by ';'
+ALTER TABLE aliases ADD COLUMN config_id TEXT NOT NULL DEFAULT '*';
+ALTER TABLE aliases ADD COLUMN channel TEXT NOT NULL DEFAULT '*';
+ALTER TABLE aliases DROP CONSTRAINT aliases_pkey;
+ALTER TABLE aliases ADD PRIMARY KEY (name, config_id, channel);
@@ -16,11 +16,14 @@ fi
 
 name="$(date +%Y%m%d000000)_$name"
 
-mkdir "$name"
-touch "$name/up.sql" "$name/down.sql"
+# every migration needs a SQLite version too, even if it's identical
+sqlite="../migrations-sqlite/$name"
+
+mkdir "$name" "$sqlite"
+touch "$name/up.sql" "$name/down.sql" "$sqlite/up.sql" "$sqlite/down.sql"
 
 if [[ "${EDITOR:-vim}" == vim ]]; then
-	vim -o "$name/up.sql" "$name/down.sql"
+	vim -o "$name/up.sql" "$name/down.sql" "$sqlite/up.sql" "$sqlite/down.sql"
 else
-	"${EDITOR:-vim}" "$name/up.sql" "$name/down.sql"
+	"${EDITOR:-vim}" "$name/up.sql" "$name/down.sql" "$sqlite/up.sql" "$sqlite/down.sql"
 fi
@@ -4,20 +4,76 @@ mod bash;
 mod db;
 mod raw;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("raw", Command::new(raw::raw).req_perms(Perms::Raw));
-    meta.cmd("join", Command::new(raw::join).req_perms(Perms::Raw));
-    meta.cmd("part", Command::new(raw::part).req_perms(Perms::Raw));
-    meta.cmd("dmsg", Command::new(raw::dmsg).req_perms(Perms::Raw));
-    meta.cmd("imsg", Command::new(raw::imsg).req_perms(Perms::Raw));
+    meta.cmd(
+        "raw",
+        Command::new(raw::raw)
+            .req_perms(Perms::Raw)
+            .summary("sends a raw line to an IRC connection")
+            .usage("<config_id> <line...>")
+            .args(raw::RawArgs::describe_expected().into_owned()),
+    );
+    meta.cmd(
+        "join",
+        Command::new(raw::join)
+            .req_perms(Perms::Raw)
+            .summary("joins an IRC channel, and rejoins it on reconnecting")
+            .usage("<config_id> <channel>")
+            .args(raw::ChannelArgs::describe_expected().into_owned()),
+    );
+    meta.cmd(
+        "part",
+        Command::new(raw::part)
+            .req_perms(Perms::Raw)
+            .summary("leaves an IRC channel")
+            .usage("<config_id> <channel>")
+            .args(raw::ChannelArgs::describe_expected().into_owned()),
+    );
+    meta.cmd(
+        "dmsg",
+        Command::new(raw::dmsg)
+            .req_perms(Perms::Raw)
+            .summary("sends a message to a Discord channel")
+            .args(raw::dmsg_args()),
+    );
+    meta.cmd(
+        "imsg",
+        Command::new(raw::imsg)
+            .req_perms(Perms::Raw)
+            .summary("sends a message to an IRC channel")
+            .usage("<config_id> <channel> <message...>")
+            .args(raw::ImsgArgs::describe_expected().into_owned()),
+    );
 
-    meta.cmd("q", Command::new(db::query).req_perms(Perms::Database));
+    meta.cmd(
+        "q",
+        Command::new(db::query)
+            .req_perms(Perms::Database)
+            .summary("runs an SQL statement")
+            .usage("<sql>"),
+    );
 
-    meta.cmd("whoami", Command::new(whoami));
+    meta.cmd(
+        "whoami",
+        Command::new(whoami).summary("shows who the bot thinks you are"),
+    );
 
-    meta.cmd("bash", Command::new(bash::bash).req_perms(Perms::Eval));
-    meta.cmd("bashl", Command::new(bash::bashl).req_perms(Perms::Eval));
+    meta.cmd(
+        "bash",
+        Command::new(bash::bash)
+            .req_perms(Perms::Eval)
+            .summary("runs a shell command, showing its output on one line")
+            .usage("<command...>"),
+    );
+    meta.cmd(
+        "bashl",
+        Command::new(bash::bashl)
+            .req_perms(Perms::Eval)
+            .summary("runs a shell command, showing its output as it is")
+            .usage("<command...>"),
+    );
 }
 
 fn whoami(ctx: &dyn Context, _: &str) -> Result<()> {
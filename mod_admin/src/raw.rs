@@ -1,5 +1,15 @@
 use rustbot::prelude::*;
 
+// For help; matches what dmsg parses.
+pub fn dmsg_args() -> String {
+    describe_args! {
+        config: Atom,
+        guild: Atom,
+        channel: Atom,
+        message: Rest,
+    }
+}
+
 pub fn dmsg(ctx: &dyn Context, args: &str) -> Result<()> {
     parse_args! {args,
         config: Atom,
@@ -16,53 +26,64 @@ pub fn dmsg(ctx: &dyn Context, args: &str) -> Result<()> {
     ctx.bot().dis_send_message(&config, &guild, channel, &message, true)
 }
 
+#[derive(Args)]
+pub struct ImsgArgs {
+    config_id: Atom,
+    channel: Atom,
+    message: Rest,
+}
+
 pub fn imsg(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(3, ' ').collect();
-    if args.len() != 3 {
-        bail_user!("usage: imsg <config_id> <channel> <message...>")
-    }
+    let ImsgArgs {
+        config_id,
+        channel,
+        message,
+    } = ImsgArgs::parse_full(args)?;
+
+    ctx.bot().irc_send_privmsg(&config_id, &channel, &message)
+}
 
-    ctx.bot().irc_send_privmsg(args[0], args[1], args[2])
+#[derive(Args)]
+pub struct RawArgs {
+    config_id: Atom,
+    line: Rest,
 }
 
 pub fn raw(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(2, ' ').collect();
-    if args.len() != 2 {
-        bail_user!("usage: raw <config_id> <message...>")
-    }
+    let RawArgs { config_id, line } = RawArgs::parse_full(args)?;
 
-    ctx.bot().irc_send_raw(args[0], args[1])
+    ctx.bot().irc_send_raw(&config_id, &line)
+}
+
+#[derive(Args)]
+pub struct ChannelArgs {
+    config_id: Atom,
+    channel: Atom,
 }
 
 pub fn join(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(3, ' ').collect();
-    if args.len() != 2 {
-        bail_user!("usage: join <config_id> <channel>")
-    }
+    let ChannelArgs { config_id, channel } = ChannelArgs::parse_full(args)?;
 
     {
-        let mut db = ctx.bot().sql().lock();
+        let mut db = ctx.bot().sql()?;
         db.execute(
             "INSERT INTO irc_channels (config_id, channel) VALUES ($1, $2) ON CONFLICT (config_id, channel) DO NOTHING",
-            &[&args[0], &args[1]],
+            &[&*config_id, &*channel],
         )?;
     }
-    ctx.bot().irc_send_raw(args[0], &format!("JOIN {}", args[1]))?;
+    ctx.bot().irc_send_raw(&config_id, &format!("JOIN {}", *channel))?;
     ctx.say("done")
 }
 
 pub fn part(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(3, ' ').collect();
-    if args.len() != 2 {
-        bail_user!("usage: part <config_id> <channel>")
-    }
+    let ChannelArgs { config_id, channel } = ChannelArgs::parse_full(args)?;
     {
-        let mut db = ctx.bot().sql().lock();
+        let mut db = ctx.bot().sql()?;
         db.execute(
             "DELETE FROM irc_channels WHERE channel = $1 AND config_id = $2",
-            &[&args[0], &args[1]],
+            &[&*channel, &*config_id],
         )?;
     }
-    ctx.bot().irc_send_raw(args[0], &format!("part {}", args[1]))?;
+    ctx.bot().irc_send_raw(&config_id, &format!("part {}", *channel))?;
     ctx.say("done")
 }
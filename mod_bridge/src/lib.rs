@@ -1,5 +1,6 @@
 use lazy_static::lazy_static;
 use regex::Regex;
+use rustbot::cache::Cached;
 use rustbot::prelude::*;
 use std::borrow::Cow;
 
@@ -7,19 +8,32 @@ mod format;
 #[cfg(tests)]
 mod tests;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("bridge", Command::new(bridge).req_perms(Perms::Admin));
+    meta.cmd(
+        "bridge",
+        Command::new(bridge)
+            .req_perms(Perms::Admin)
+            .summary("relays messages between channels with the same key; shows this channel's, or sets it")
+            .usage("[key]")
+            .sub(
+                "none",
+                Command::new(clear_bridge).summary("clears this channel's bridge key"),
+            ),
+    );
 
     meta.handle(HandleType::All, Box::new(do_bridge));
 }
 
 lazy_static! {
     static ref ANTIPING_RE: Regex = Regex::new(r"\b[a-zA-Z0-9]").unwrap();
+    // (config_id, channel_id, bridge_key)
+    static ref BRIDGES: Cached<Vec<(String, String, String)>> = Cached::new(&["mod_bridge"]);
 }
 
 fn bridge(ctx: &dyn Context, args: &str) -> Result<()> {
-    let mut db = ctx.bot().sql().lock();
+    let mut db = ctx.bot().sql()?;
     if args.is_empty() {
         let key = db.query(
             "SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
@@ -46,37 +60,51 @@ fn bridge(ctx: &dyn Context, args: &str) -> Result<()> {
             key.get(0).unwrap().get::<_, String>(0),
             chans_str
         ))
-    } else if args == "none" {
-        let n = db.execute(
-            "DELETE FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
-            &[&ctx.config_id(), &ctx.source().channel_string()],
-        )?;
-        if n != 1 {
-            ctx.say("there is no bridge key to clear")
-        } else {
-            ctx.say("bridge key cleared")
-        }
     } else {
         db.execute(
             "INSERT INTO mod_bridge (config_id, channel_id, bridge_key) VALUES ($1, $2, $3) ON CONFLICT (config_id, channel_id) DO UPDATE SET bridge_key = $3",
             &[&ctx.config_id(), &ctx.source().channel_string(), &args],
         )?;
+        ctx.bot().invalidate(Some("mod_bridge"));
 
         ctx.say(&format!("bridge key set to '{args}'"))
     }
 }
 
+fn clear_bridge(ctx: &dyn Context, _args: &str) -> Result<()> {
+    let n = ctx.bot().sql()?.execute(
+        "DELETE FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
+        &[&ctx.config_id(), &ctx.source().channel_string()],
+    )?;
+    ctx.bot().invalidate(Some("mod_bridge"));
+    if n != 1 {
+        ctx.say("there is no bridge key to clear")
+    } else {
+        ctx.say("bridge key cleared")
+    }
+}
+
 fn do_bridge(ctx: &dyn Context, _typ: HandleType, msg: &str) -> Result<()> {
     let conf = ctx.config_id();
     let chan = ctx.source().channel_string();
 
-    let chans = {
-        let mut db = ctx.bot().sql().lock();
-
-        db.query(
-            "SELECT config_id, channel_id FROM mod_bridge WHERE bridge_key = (SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2) AND (config_id != $1 OR channel_id != $2)",
-            &[&conf, &chan]
-        )?
+    let chans: Vec<(String, String)> = {
+        let bridges = BRIDGES.get(ctx.bot(), |db| {
+            Ok(db
+                .query("SELECT config_id, channel_id, bridge_key FROM mod_bridge", &[])?
+                .iter()
+                .map(|row| (row.get(0), row.get(1), row.get(2)))
+                .collect())
+        })?;
+
+        match bridges.iter().find(|(c, ch, _)| c == conf && *ch == chan) {
+            Some((_, _, key)) => bridges
+                .iter()
+                .filter(|(c, ch, k)| k == key && (c != conf || *ch != chan))
+                .map(|(c, ch, _)| (c.clone(), ch.clone()))
+                .collect(),
+            None => vec![],
+        }
     };
     if chans.is_empty() {
         return Ok(());
@@ -110,20 +138,17 @@ fn do_bridge(ctx: &dyn Context, _typ: HandleType, msg: &str) -> Result<()> {
             (&|user| span!(Format::Bold; "<{}>", user), spans! {msg})
         };
 
-    for row in &chans {
-        let tconf = row.get::<_, String>(0);
-        let tchan = row.get::<_, String>(1);
-
+    for (tconf, tchan) in &chans {
         if tchan.starts_with("irc:") {
             let user_pretty = ctx.source().user_pretty();
 
             let user_pretty = ANTIPING_RE.replace_all(&user_pretty, "$0\u{feff}");
 
             let msg = Message::Prefixed(spans! {user(user_pretty), " "}, spans.clone());
-            ctx.bot().send_message(&tconf, &tchan, msg)?;
+            ctx.bot().send_message(tconf, tchan, msg)?;
         } else {
             let msg = Message::Prefixed(spans! {user(ctx.source().user_pretty()), " "}, spans.clone());
-            ctx.bot().send_message(&tconf, &tchan, msg)?;
+            ctx.bot().send_message(tconf, tchan, msg)?;
         }
     }
     Ok(())
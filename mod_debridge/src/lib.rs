@@ -1,58 +1,130 @@
 use lazy_static::lazy_static;
 use regex::Regex;
+use rustbot::cache::{like, Cached};
 use rustbot::prelude::*;
 use std::borrow::Cow;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("isbridge", Command::new(isbridge).req_perms(Perms::Admin));
+    // only useful for relayed messages, like the ones bridge sends on
+    meta.depends("bridge", "0.1");
+
+    meta.cmd(
+        "isbridge",
+        Command::group()
+            .req_perms(Perms::Admin)
+            .summary("treats users as bridges, showing who really sent their messages")
+            .sub(
+                "set",
+                Command::new(set)
+                    .summary("treats a user as a bridge")
+                    .args(SetArgs::describe_expected().into_owned()),
+            )
+            .sub(
+                "clear",
+                Command::new(clear)
+                    .summary("stops treating a user as a bridge")
+                    .args(ClearArgs::describe_expected().into_owned()),
+            )
+            .sub(
+                "list",
+                Command::new(list)
+                    .summary("lists the users treated as bridges")
+                    .usage("[config_id]"),
+            ),
+    );
 
     meta.handle(HandleType::All, Box::new(do_debridge));
 }
 
-fn isbridge(ctx: &dyn Context, args: &str) -> Result<()> {
-    let mut db = ctx.bot().sql().lock();
+#[derive(Args)]
+struct SetArgs {
+    config_id: Atom,
+    user: Atom,
+    spec: Rest,
+}
 
-    let args: Vec<&str> = args.splitn(3, char::is_whitespace).collect();
+fn set(ctx: &dyn Context, args: &str) -> Result<()> {
+    let SetArgs { config_id, user, spec } = SetArgs::parse_full(args)?;
 
-    let config = args[0];
-    let user = *args.get(1).unwrap_or(&"");
-    let spec = *args.get(2).unwrap_or(&"");
+    ctx.bot().sql()?.execute(
+        "INSERT INTO mod_debridge (config_id, source_user, spec) VALUES ($1, $2, $3) ON CONFLICT (config_id, source_user) DO UPDATE SET spec = $3",
+        &[&*config_id, &*user, &*spec],
+    )?;
+    ctx.bot().invalidate(Some("mod_debridge"));
 
-    if user == "" {
-        return ctx.reply(Message::Simple("todo".to_string()));
-    }
+    ctx.reply(Message::Simple("done".to_string()))
+}
+
+#[derive(Args)]
+struct ClearArgs {
+    config_id: Atom,
+    user: Atom,
+}
 
-    if spec == "" {
-        db.execute(
-            "DELETE FROM mod_debridge WHERE config_id = $1 AND source_user = $2",
-            &[&config, &user],
-        )?;
+fn clear(ctx: &dyn Context, args: &str) -> Result<()> {
+    let ClearArgs { config_id, user } = ClearArgs::parse_full(args)?;
+
+    let n = ctx.bot().sql()?.execute(
+        "DELETE FROM mod_debridge WHERE config_id = $1 AND source_user = $2",
+        &[&*config_id, &*user],
+    )?;
+    ctx.bot().invalidate(Some("mod_debridge"));
+
+    if n == 0 {
+        ctx.reply(Message::Simple(format!("{} isn't a bridge on {}", *user, *config_id)))
     } else {
-        db.execute(
-            "INSERT INTO mod_debridge (config_id, source_user, spec) VALUES ($1, $2, $3) ON CONFLICT (config_id, source_user) DO UPDATE SET spec = $3",
-            &[&config, &user, &spec],
-        )?;
+        ctx.reply(Message::Simple("done".to_string()))
     }
+}
 
-    ctx.reply(Message::Simple("done".to_string()))
+fn list(ctx: &dyn Context, args: &str) -> Result<()> {
+    let config_id = Some(args.trim()).filter(|id| !id.is_empty());
+
+    let rows = ctx.bot().sql()?.query(
+        "SELECT config_id, source_user FROM mod_debridge ORDER BY config_id, source_user",
+        &[],
+    )?;
+    let items: Vec<Cow<str>> = rows
+        .iter()
+        .map(|row| (row.get::<_, String>(0), row.get::<_, String>(1)))
+        .filter(|(c, _)| config_id.is_none_or(|id| id == *c))
+        .map(|(c, user)| format!("{c}:{user}").into())
+        .collect();
+    if items.is_empty() {
+        return ctx.say("no bridges");
+    }
+
+    ctx.reply(Message::List {
+        prefix: "bridges: ".into(),
+        sep: ", ".into(),
+        items,
+    })
 }
 
 lazy_static! {
     static ref DEBRIDGE_RE: Regex = Regex::new(r"<([^.]+)> (.*)").unwrap();
+    // (config_id, source_user pattern, spec)
+    static ref DEBRIDGES: Cached<Vec<(String, String, String)>> = Cached::new(&["mod_debridge"]);
 }
 
 fn do_debridge(ctx: &dyn Context, _typ: HandleType, msg: &str) -> Result<()> {
     let user = ctx.source().user_string();
 
     let spec: Option<String> = {
-        let mut db = ctx.bot().sql().lock();
+        let debridges = DEBRIDGES.get(ctx.bot(), |db| {
+            Ok(db
+                .query("SELECT config_id, source_user, spec FROM mod_debridge", &[])?
+                .iter()
+                .map(|row| (row.get(0), row.get(1), row.get(2)))
+                .collect())
+        })?;
 
-        db.query_opt(
-            "SELECT spec FROM mod_debridge WHERE config_id = $1 AND $2 LIKE source_user",
-            &[&ctx.config_id(), &user],
-        )?
-        .map(|row| row.get(0))
+        debridges
+            .iter()
+            .find(|(config_id, pattern, _)| config_id == ctx.config_id() && like(pattern, &user))
+            .map(|(_, _, spec)| spec.clone())
     };
 
     if spec.is_some() {
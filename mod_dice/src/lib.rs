@@ -3,13 +3,45 @@ use rand::thread_rng;
 mod dice;
 mod swrpg;
 
+#[cfg(test)]
+mod tests;
+
 use rustbot::prelude::*;
+use rustbot::services;
+
+rustbot::module!(get_meta);
 
-#[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("dice", Command::new(cmd_dice));
-    meta.cmd("swrpg", Command::new(cmd_swrpg));
-    meta.cmd("space", Command::new(cmd_space));
+    meta.cmd(
+        "dice",
+        Command::new(cmd_dice)
+            .summary("rolls dice")
+            .usage("<roll>")
+            .example("1d6")
+            .example("2d20H1")
+            .example("2d6>7"),
+    );
+    meta.cmd(
+        "swrpg",
+        Command::new(cmd_swrpg)
+            .summary("rolls Star Wars RPG narrative dice")
+            .usage("<dice>"),
+    );
+    meta.cmd(
+        "space",
+        Command::new(cmd_space)
+            .summary("rolls dice, with a description")
+            .usage("<dice> [<description>...]"),
+    );
+
+    meta.provide("dice.roll", Box::new(roll) as services::DiceRoll);
+}
+
+fn roll(expr: &str) -> Result<Vec<Span<'static>>> {
+    let v = dice::Command::new(expr).map_err(UserError::new)?;
+    let mut limit = dice::limits::Limiter::new(10000);
+    let result = v.eval(&mut limit, &mut thread_rng()).map_err(UserError::new)?;
+    Ok(result.into_iter().map(Span::into_owned).collect())
 }
 
 fn cmd_dice(ctx: &dyn Context, args: &str) -> Result<()> {
@@ -18,10 +50,7 @@ fn cmd_dice(ctx: &dyn Context, args: &str) -> Result<()> {
             "Usage: dice <roll>; try '1d6', '2d20H1', '2d6>7'".to_string(),
         ));
     }
-    let v = dice::Command::new(args).map_err(UserError::new)?;
-    let mut limit = dice::limits::Limiter::new(10000);
-    let result = v.eval(&mut limit, &mut thread_rng()).map_err(UserError::new)?;
-    ctx.reply(Message::Spans(result))
+    ctx.reply(Message::Spans(roll(args)?))
 }
 
 fn cmd_swrpg(ctx: &dyn Context, args: &str) -> Result<()> {
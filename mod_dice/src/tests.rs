@@ -0,0 +1,34 @@
+use rustbot::prelude::*;
+use rustbot::services::DiceRoll;
+use rustbot::testing::{MockContext, TestMeta};
+
+use super::get_meta;
+
+#[test]
+fn test_dice_command() {
+    let meta = TestMeta::load(get_meta);
+    let ctx = MockContext::new();
+
+    meta.call(&ctx, "dice", "").unwrap();
+    assert_eq!(
+        ctx.take_replies(),
+        vec!["Usage: dice <roll>; try '1d6', '2d20H1', '2d6>7'"]
+    );
+
+    // a single one-sided die can only roll a 1
+    meta.call(&ctx, "dice", "3d1").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["[1, 1, 1]: 3d1:[1, 1, 1]"]);
+
+    let err = meta.call(&ctx, "dice", "1d6 +").unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+    assert!(ctx.take_replies().is_empty());
+}
+
+#[test]
+fn test_dice_roll_service() {
+    let meta = TestMeta::load(get_meta);
+    let roll = meta.service::<DiceRoll>("dice.roll").unwrap();
+
+    assert_eq!(spans_to_raw_string(roll("2d1").unwrap()), "[1, 1]: 2d1:[1, 1]");
+    assert!(roll("1d6 +").unwrap_err().downcast_ref::<UserError>().is_some());
+}
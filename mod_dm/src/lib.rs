@@ -5,17 +5,36 @@ use std::hash::Hasher;
 use std::io::prelude::*;
 use std::process::Command as ProcessCommand;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("dm", Command::new(|ctx, args| dm(ctx, args, false, false)));
-    meta.cmd("dml", Command::new(|ctx, args| dm(ctx, args, false, true)));
+    meta.cmd(
+        "dm",
+        Command::new(|ctx, args| dm(ctx, args, false, false))
+            .summary("runs DM code, showing its output on one line")
+            .usage("<code>")
+            .example("1 + 2")
+            .example("var/x = 3;; x * x"),
+    );
+    meta.cmd(
+        "dml",
+        Command::new(|ctx, args| dm(ctx, args, false, true))
+            .summary("runs DM code, showing its output as it is")
+            .usage("<code>"),
+    );
     meta.cmd(
         "dms",
-        Command::new(|ctx, args| dm(ctx, args, true, false)).req_perms(Perms::Eval),
+        Command::new(|ctx, args| dm(ctx, args, true, false))
+            .req_perms(Perms::Eval)
+            .summary("runs DM code, allowing includes and ##")
+            .usage("<code>"),
     );
     meta.cmd(
         "dmsl",
-        Command::new(|ctx, args| dm(ctx, args, true, true)).req_perms(Perms::Eval),
+        Command::new(|ctx, args| dm(ctx, args, true, true))
+            .req_perms(Perms::Eval)
+            .summary("runs DM code, allowing includes and ##, showing its output as it is")
+            .usage("<code>"),
     );
 }
 
@@ -0,0 +1,320 @@
+// Factoids: short texts anyone can look up with "?name", for a channel's rules, links and the like.
+// They're per config, optionally limited to one channel, where they take precedence over ones
+// known everywhere. Every change is kept, so edits can be reverted.
+
+use std::time::Duration;
+
+use rustbot::prelude::*;
+use rustbot::schedule;
+use rustbot::sql::Connection;
+
+#[cfg(test)]
+mod tests;
+
+rustbot::module!(get_meta);
+
+const EDIT: &str = "factoids.edit";
+// the channel of factoids known in every channel
+const EVERYWHERE: &str = "*";
+// how many past revisions info lists
+const INFO_REVISIONS: usize = 5;
+
+pub fn get_meta(meta: &mut dyn Meta) {
+    meta.permission(EDIT, "teach, change, forget and revert factoids");
+    meta.cmd(
+        "learn",
+        Command::new(learn)
+            .req_perm(EDIT)
+            .summary("teaches a factoid, to be shown with ?name; --here keeps it to this channel")
+            .usage("[--here] <name> <text>")
+            .example("rules Be nice, and **no spam**.")
+            .example("--here hi Welcome to $channel, $nick!")
+            .args(LearnArgs::describe_expected().into_owned()),
+    );
+    meta.cmd(
+        "forget",
+        Command::new(forget)
+            .req_perm(EDIT)
+            .summary("forgets a factoid")
+            .usage("[--here] <name>")
+            .args(ForgetArgs::describe_expected().into_owned()),
+    );
+    meta.cmd(
+        "info",
+        Command::new(info)
+            .summary("shows who changed a factoid and when")
+            .usage("<name>"),
+    );
+    meta.cmd(
+        "revert",
+        Command::new(revert)
+            .req_perm(EDIT)
+            .summary("puts a factoid back as it was at a revision listed by info")
+            .usage("<name> <revision>")
+            .args(RevertArgs::describe_expected().into_owned()),
+    );
+
+    meta.handle(HandleType::All, Box::new(lookup));
+}
+
+struct Revision {
+    id: i64,
+    channel: String,
+    // None when it was forgotten
+    text: Option<String>,
+    author: String,
+    // Unix timestamp
+    changed: i64,
+}
+
+impl Revision {
+    fn describe(&self) -> String {
+        let what = if self.text.is_some() { "" } else { "forgotten " };
+        format!("#{} {}by {} {} ago", self.id, what, self.author, ago(self.changed))
+    }
+}
+
+fn ago(t: i64) -> String {
+    format_duration(Duration::from_secs((schedule::now() - t).max(0) as u64))
+}
+
+// Factoid names are single words, looked up regardless of case.
+fn check_name(name: &str) -> Result<String> {
+    if !valid_name(name) {
+        bail_user!("factoid names are up to 32 letters, digits, '-', '_' or '.'");
+    }
+    Ok(name.to_lowercase())
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().count() <= 32 && name.chars().all(|c| c.is_alphanumeric() || "-_.".contains(c))
+}
+
+fn revisions(
+    db: &mut dyn Connection,
+    config_id: &str,
+    channel: &str,
+    name: &str,
+    limit: usize,
+) -> Result<Vec<Revision>> {
+    Ok(db
+        .query(
+            "SELECT id, channel, text, author, changed FROM mod_factoids WHERE config_id = $1 AND channel = $2 AND name = $3 ORDER BY id DESC LIMIT $4",
+            &[&config_id, &channel, &name, &(limit as i64)],
+        )?
+        .iter()
+        .map(|row| Revision {
+            id: row.get(0),
+            channel: row.get(1),
+            text: row.get(2),
+            author: row.get(3),
+            changed: row.get(4),
+        })
+        .collect())
+}
+
+// The latest revision of a factoid in exactly the given channel, or everywhere for EVERYWHERE.
+fn latest(db: &mut dyn Connection, config_id: &str, channel: &str, name: &str) -> Result<Option<Revision>> {
+    Ok(revisions(db, config_id, channel, name, 1)?.pop())
+}
+
+// The factoid as it's seen in the context's channel: the channel's own if it has one, else the
+// one known everywhere.
+fn current(ctx: &dyn Context, db: &mut dyn Connection, name: &str) -> Result<Option<Revision>> {
+    for channel in [&*ctx.source().channel_string(), EVERYWHERE] {
+        if let Some(r) = latest(db, ctx.config_id(), channel, name)? {
+            if r.text.is_some() {
+                return Ok(Some(r));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn add_revision(
+    ctx: &dyn Context,
+    db: &mut dyn Connection,
+    channel: &str,
+    name: &str,
+    text: Option<&str>,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO mod_factoids (name, config_id, channel, text, author, changed) VALUES ($1, $2, $3, $4, $5, $6)",
+        &[
+            &name,
+            &ctx.config_id(),
+            &channel,
+            &text,
+            &&*ctx.source().user_pretty(),
+            &schedule::now(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn scope(ctx: &dyn Context, here: bool) -> String {
+    if here {
+        ctx.source().channel_string().into_owned()
+    } else {
+        EVERYWHERE.to_string()
+    }
+}
+
+#[derive(Args)]
+struct LearnArgs {
+    #[arg(flag)]
+    here: bool,
+    name: Atom,
+    text: Rest,
+}
+
+fn learn(ctx: &dyn Context, args: &str) -> Result<()> {
+    let LearnArgs { here, name, text } = LearnArgs::parse_full(args)?;
+    let name = check_name(&name)?;
+    let channel = scope(ctx, here);
+
+    let mut db = ctx.bot().sql()?;
+    let old = latest(&mut **db, ctx.config_id(), &channel, &name)?.filter(|r| r.text.is_some());
+    add_revision(ctx, &mut **db, &channel, &name, Some(&text))?;
+
+    match old {
+        Some(old) => ctx.say(&format!("updated ?{} (revert {} {} to undo)", name, name, old.id)),
+        None => ctx.say(&format!("learned ?{name}")),
+    }
+}
+
+#[derive(Args)]
+struct ForgetArgs {
+    #[arg(flag)]
+    here: bool,
+    name: Atom,
+}
+
+fn forget(ctx: &dyn Context, args: &str) -> Result<()> {
+    let ForgetArgs { here, name } = ForgetArgs::parse_full(args)?;
+    let name = check_name(&name)?;
+    let channel = scope(ctx, here);
+
+    let mut db = ctx.bot().sql()?;
+    let old = match latest(&mut **db, ctx.config_id(), &channel, &name)? {
+        Some(r) if r.text.is_some() => r,
+        _ if here => bail_user!("there's no ?{} just for this channel", name),
+        _ => bail_user!("there's no ?{} for every channel; try forget --here {}", name, name),
+    };
+    add_revision(ctx, &mut **db, &channel, &name, None)?;
+
+    ctx.say(&format!("forgot ?{} (revert {} {} to undo)", name, name, old.id))
+}
+
+fn info(ctx: &dyn Context, args: &str) -> Result<()> {
+    let name = check_name(args.trim())?;
+
+    let mut db = ctx.bot().sql()?;
+    let channel = ctx.source().channel_string();
+    // the history shown is that of the factoid seen here, or failing that, of a forgotten one
+    let shown = match current(ctx, &mut **db, &name)? {
+        Some(r) => r.channel,
+        None if latest(&mut **db, ctx.config_id(), &channel, &name)?.is_some() => channel.into_owned(),
+        None if latest(&mut **db, ctx.config_id(), EVERYWHERE, &name)?.is_some() => EVERYWHERE.to_string(),
+        None => bail_user!("I don't know ?{}", name),
+    };
+
+    let revisions = revisions(&mut **db, ctx.config_id(), &shown, &name, INFO_REVISIONS)?;
+    let where_ = if shown == EVERYWHERE {
+        "every channel"
+    } else {
+        "this channel"
+    };
+    let items: Vec<String> = revisions.iter().map(Revision::describe).collect();
+    ctx.say(&format!("?{} ({}): {}", name, where_, items.join(", ")))
+}
+
+#[derive(Args)]
+struct RevertArgs {
+    name: Atom,
+    revision: Atom,
+}
+
+fn revert(ctx: &dyn Context, args: &str) -> Result<()> {
+    let RevertArgs { name, revision } = RevertArgs::parse_full(args)?;
+    let name = check_name(&name)?;
+    let id: i64 = match revision.trim_start_matches('#').parse() {
+        Ok(id) => id,
+        Err(_) => bail_user!("invalid revision {:?}", *revision),
+    };
+
+    let mut db = ctx.bot().sql()?;
+    let channel = ctx.source().channel_string();
+    let rows = db.query(
+        "SELECT channel, text FROM mod_factoids WHERE id = $1 AND config_id = $2 AND name = $3 AND channel IN ($4, $5)",
+        &[&id, &ctx.config_id(), &name, &&*channel, &EVERYWHERE],
+    )?;
+    let (channel, text): (String, Option<String>) = match rows.first() {
+        Some(row) => (row.get(0), row.get(1)),
+        None => bail_user!("?{} has no revision #{} here", name, id),
+    };
+    add_revision(ctx, &mut **db, &channel, &name, text.as_deref())?;
+
+    ctx.say(&format!("reverted ?{name} to #{id}"))
+}
+
+// Answers "?name" with the factoid, if there is one; anything else is left alone.
+fn lookup(ctx: &dyn Context, typ: HandleType, msg: &str) -> Result<()> {
+    if !typ.contains(HandleType::PlainMsg) {
+        return Ok(());
+    }
+    let name = match msg.strip_prefix('?').and_then(|m| m.split(char::is_whitespace).next()) {
+        Some(name) if valid_name(name) => name.to_lowercase(),
+        _ => return Ok(()),
+    };
+
+    let mut db = ctx.bot().sql()?;
+    if let Some(Revision { text: Some(text), .. }) = current(ctx, &mut **db, &name)? {
+        ctx.reply(Message::Spans(render(&text, ctx.source())))?;
+    }
+    Ok(())
+}
+
+// Turns a factoid's text into spans, with **bold**, *italic* and __underline__ as on Discord and a
+// backslash escaping the next character, and fills in $nick and $channel.
+fn render(text: &str, source: &dyn Source) -> Vec<Span<'static>> {
+    let mut spans = vec![];
+    let mut cur = String::new();
+    let mut format = Format::None;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let toggle = match c {
+            '\\' => {
+                cur.extend(chars.next());
+                continue;
+            }
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                Format::Bold
+            }
+            '*' => Format::Italic,
+            '_' if chars.peek() == Some(&'_') => {
+                chars.next();
+                Format::Underline
+            }
+            c => {
+                cur.push(c);
+                continue;
+            }
+        };
+        if !cur.is_empty() {
+            spans.push(span!(format; substitute(&std::mem::take(&mut cur), source)));
+        }
+        format.toggle(toggle);
+    }
+    if !cur.is_empty() {
+        spans.push(span!(format; substitute(&cur, source)));
+    }
+    spans
+}
+
+fn substitute(text: &str, source: &dyn Source) -> String {
+    text.replace("$nick", &source.user_pretty())
+        .replace("$channel", &source.channel_string())
+}
@@ -0,0 +1,146 @@
+use rustbot::prelude::*;
+use rustbot::sql::{self, sqlite::SqliteConnection};
+use rustbot::testing::{MockBot, MockContext, MockSource, TestMeta};
+
+use super::{get_meta, render};
+
+const SCHEMA: &str = include_str!("../../migrations-sqlite/20261018000006_add-factoids/up.sql");
+
+// One database shared between contexts, so they can see each other's factoids.
+fn pool() -> sql::Pool {
+    let pool = sql::single_connection_pool(|| Ok(Box::new(SqliteConnection::open_in_memory()?))).unwrap();
+    pool.get().unwrap().batch_execute(SCHEMA).unwrap();
+    pool
+}
+
+fn editor(pool: &sql::Pool, source: MockSource) -> MockContext {
+    MockContext::new()
+        .with_bot(MockBot::new().with_sql(pool.clone()))
+        .with_source(source)
+        .with_perm("factoids.edit")
+}
+
+fn ask(meta: &TestMeta, ctx: &MockContext, msg: &str) -> Vec<String> {
+    meta.run_handlers(ctx, HandleType::PlainMsg | HandleType::Public, msg)
+        .unwrap();
+    ctx.take_replies()
+}
+
+#[test]
+fn test_learn_and_lookup() {
+    let meta = TestMeta::load(get_meta);
+    let pool = pool();
+    let ctx = editor(&pool, MockSource::default());
+
+    meta.call(&ctx, "learn", "Rules Be nice, $nick. **No spam** in $channel.")
+        .unwrap();
+    assert_eq!(ctx.take_replies(), vec!["learned ?rules"]);
+
+    assert_eq!(
+        ask(&meta, &ctx, "?rules please"),
+        vec!["Be nice, tester. No spam in irc:#test."]
+    );
+    assert_eq!(
+        ask(&meta, &ctx, "?RULES"),
+        vec!["Be nice, tester. No spam in irc:#test."]
+    );
+    // not lookups, or nothing to look up
+    assert!(ask(&meta, &ctx, "? rules").is_empty());
+    assert!(ask(&meta, &ctx, "rules?").is_empty());
+    assert!(ask(&meta, &ctx, "?nothing").is_empty());
+
+    meta.call(&ctx, "learn", "rules Be nicer.").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["updated ?rules (revert rules 1 to undo)"]);
+    assert_eq!(ask(&meta, &ctx, "?rules"), vec!["Be nicer."]);
+
+    let err = meta.call(&ctx, "learn", "bad/name text").unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+
+    // teaching needs the permission
+    let user = MockContext::new().with_bot(MockBot::new().with_sql(pool.clone()));
+    meta.call(&user, "learn", "rules Anything goes.").unwrap();
+    assert!(user.take_replies().is_empty());
+    assert_eq!(ask(&meta, &user, "?rules"), vec!["Be nicer."]);
+}
+
+#[test]
+fn test_channel_scoping() {
+    let meta = TestMeta::load(get_meta);
+    let pool = pool();
+    let here = editor(&pool, MockSource::irc(Some("#test"), "tester"));
+    let there = editor(&pool, MockSource::irc(Some("#other"), "someone"));
+
+    meta.call(&here, "learn", "hi Hello, everyone.").unwrap();
+    meta.call(&here, "learn", "--here hi Welcome to #test!").unwrap();
+    here.take_replies();
+
+    assert_eq!(ask(&meta, &here, "?hi"), vec!["Welcome to #test!"]);
+    assert_eq!(ask(&meta, &there, "?hi"), vec!["Hello, everyone."]);
+
+    // forgetting the channel's own brings back the one known everywhere
+    meta.call(&here, "forget", "--here hi").unwrap();
+    assert_eq!(here.take_replies(), vec!["forgot ?hi (revert hi 2 to undo)"]);
+    assert_eq!(ask(&meta, &here, "?hi"), vec!["Hello, everyone."]);
+    assert!(meta.call(&here, "forget", "--here hi").is_err());
+
+    meta.call(&there, "forget", "hi").unwrap();
+    there.take_replies();
+    assert!(ask(&meta, &here, "?hi").is_empty());
+}
+
+#[test]
+fn test_info_and_revert() {
+    let meta = TestMeta::load(get_meta);
+    let pool = pool();
+    let ctx = editor(&pool, MockSource::default());
+
+    meta.call(&ctx, "learn", "link https://example.com").unwrap();
+    meta.call(&ctx, "learn", "link https://example.org").unwrap();
+    meta.call(&ctx, "forget", "link").unwrap();
+    ctx.take_replies();
+
+    meta.call(&ctx, "info", "link").unwrap();
+    let info = ctx.take_replies().remove(0);
+    assert!(
+        info.starts_with("?link (every channel): #3 forgotten by tester "),
+        "{}",
+        info
+    );
+    assert!(
+        info.contains(", #2 by tester ") && info.contains(", #1 by tester "),
+        "{}",
+        info
+    );
+
+    meta.call(&ctx, "revert", "link #1").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["reverted ?link to #1"]);
+    assert_eq!(ask(&meta, &ctx, "?link"), vec!["https://example.com"]);
+
+    assert!(meta.call(&ctx, "revert", "link 99").is_err());
+    assert!(meta.call(&ctx, "revert", "other 1").is_err());
+    assert!(meta.call(&ctx, "info", "nothing").is_err());
+}
+
+#[test]
+fn test_render() {
+    let source = MockSource::discord(Some(1), 2, 3, "someone");
+    let spans = render(r"**bold** *it* __under__ 2\*3 snake_case, $nick", &source);
+    let parts: Vec<(String, Format)> = spans
+        .into_iter()
+        .map(|s| match s {
+            Span::Text { text, format, .. } => (text.into_owned(), format),
+            Span::DiscordEmoji(..) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        parts,
+        vec![
+            ("bold".to_string(), Format::Bold),
+            (" ".to_string(), Format::None),
+            ("it".to_string(), Format::Italic),
+            (" ".to_string(), Format::None),
+            ("under".to_string(), Format::Underline),
+            (" 2*3 snake_case, someone".to_string(), Format::None),
+        ]
+    );
+}
@@ -3,9 +3,17 @@ use rustbot::prelude::*;
 #[cfg(test)]
 mod tests;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("mpg", Command::new(mpg).req_perms(Perms::Admin));
+    meta.cmd(
+        "mpg",
+        Command::new(mpg)
+            .req_perms(Perms::Admin)
+            .summary("records a fill-up, and the fuel economy since the last full tank")
+            .usage("<mileage> <litres> <price> [full]")
+            .args(MpgArgs::describe_expected().into_owned()),
+    );
 }
 
 struct MpgEntry {
@@ -18,7 +26,7 @@ struct MpgEntry {
 const TANK_SIZE: f64 = 65.0;
 
 fn load_entries(ctx: &dyn Context) -> Result<Vec<MpgEntry>> {
-    let res = ctx.bot().sql().lock().query(
+    let res = ctx.bot().sql()?.query(
         "SELECT mileage, fill_litres, fill_price, result_price
         FROM mpg
         WHERE mileage >= (SELECT max(mileage) FROM mpg WHERE result_price IS NOT NULL)
@@ -40,26 +48,30 @@ fn load_entries(ctx: &dyn Context) -> Result<Vec<MpgEntry>> {
     Ok(entries)
 }
 
+#[derive(Args)]
+enum Fill {
+    Full,
+}
+
+#[derive(Args)]
+struct MpgArgs {
+    mileage: i32,
+    litres: f64,
+    price: f64,
+    #[arg(default)]
+    fill: Option<Fill>,
+}
+
 fn mpg(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(4, ' ').collect();
-    let (mileage, litres, price, full) = match args[..] {
-        [m, l, p] => (m, l, p, false),
-        [m, l, p, "full"] => (m, l, p, true),
-        _ => bail_user!("usage: mpg <mileage> <litres> <price> [\"full\"]"),
-    };
-
-    let mileage = mileage
-        .parse::<i32>()
-        .map_err(|_| UserError::new("mileage must be an int"))?;
-    let litres = litres
-        .parse::<f64>()
-        .map_err(|_| UserError::new("litres must be a float"))?;
-    let price = price
-        .parse::<f64>()
-        .map_err(|_| UserError::new("price must be a float"))?;
-
-    if !full {
-        ctx.bot().sql().lock().query(
+    let MpgArgs {
+        mileage,
+        litres,
+        price,
+        fill,
+    } = MpgArgs::parse_full(args)?;
+
+    if fill.is_none() {
+        ctx.bot().sql()?.query(
             "INSERT INTO mpg (mileage, fill_litres, fill_price, result_price)
             VALUES ($1, $2, $3, NULL)",
             &[&mileage, &litres, &price],
@@ -86,7 +98,7 @@ fn mpg(ctx: &dyn Context, args: &str) -> Result<()> {
         "since last fill: {total_mileage} miles, {used_litres:.2} litres, £{used_cost:.2}\nstats: {mpg:.2} mpg, {mpg_us:.2} mpg(US), {lp100km:.2} l/100km\navg cost: £{perlitre:.3}/litre, £{permile:.3}/mile, £{perkm:.3}/km"
     )))?;
 
-    ctx.bot().sql().lock().query(
+    ctx.bot().sql()?.query(
         "INSERT INTO mpg (mileage, fill_litres, fill_price, result_price)
         VALUES ($1, $2, $3, $4)",
         &[&mileage, &litres, &price, &result_price],
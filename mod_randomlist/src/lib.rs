@@ -1,27 +1,49 @@
 use rustbot::prelude::*;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("8ball", Command::new(|ctx, args| randomlist("eightball", ctx, args)));
-    meta.cmd("kitty", Command::new(|ctx, args| randomlist("kitty", ctx, args)));
-    meta.cmd("fox", Command::new(|ctx, args| randomlist("fox", ctx, args)));
-    meta.cmd("snek", Command::new(|ctx, args| randomlist("snek", ctx, args)));
-    meta.cmd("otter", Command::new(|ctx, args| randomlist("otter", ctx, args)));
-    meta.cmd("doggo", Command::new(|ctx, args| randomlist("doggo", ctx, args)));
-    meta.cmd("possum", Command::new(|ctx, args| randomlist("possum", ctx, args)));
-    meta.cmd("lizard", Command::new(|ctx, args| randomlist("lizard", ctx, args)));
-    meta.cmd("delrand", Command::new(delrand).req_perms(Perms::Admin));
+    meta.cmd("8ball", list("eightball", "answer"));
+    meta.cmd("kitty", list("kitty", "cat"));
+    meta.cmd("fox", list("fox", "fox"));
+    meta.cmd("snek", list("snek", "snake"));
+    meta.cmd("otter", list("otter", "otter"));
+    meta.cmd("doggo", list("doggo", "dog"));
+    meta.cmd("possum", list("possum", "possum"));
+    meta.cmd("lizard", list("lizard", "lizard"));
+    meta.cmd(
+        "delrand",
+        Command::new(delrand)
+            .req_perms(Perms::Admin)
+            .summary("removes something from a random list")
+            .usage("<category> <string>")
+            .args(DelrandArgs::describe_expected().into_owned()),
+    );
+}
+
+fn list(category: &'static str, what: &str) -> Command {
+    Command::new(move |ctx, _| randomlist(category, ctx))
+        .summary(&format!("shows a random {what}"))
+        .sub(
+            "add",
+            Command::new(move |ctx, args| add(category, ctx, args))
+                .summary(&format!("adds a {what} to the list"))
+                .usage("<string>"),
+        )
+}
+
+#[derive(Args)]
+struct DelrandArgs {
+    category: Atom,
+    string: Rest,
 }
 
 fn delrand(ctx: &dyn Context, args: &str) -> Result<()> {
-    let parts: Vec<&str> = args.splitn(2, ' ').collect();
-    if parts.len() != 2 {
-        return ctx.say("usage: delrand <category> <string>");
-    }
+    let DelrandArgs { category, string } = DelrandArgs::parse_full(args)?;
 
-    let n = ctx.bot().sql().lock().execute(
+    let n = ctx.bot().sql()?.execute(
         "DELETE FROM mod_randomlist WHERE category = $1 AND string = $2",
-        &[&parts[0], &parts[1]],
+        &[&*category, &*string],
     )?;
     if n != 1 {
         ctx.say(&format!("{n} rows removed"))
@@ -30,22 +52,25 @@ fn delrand(ctx: &dyn Context, args: &str) -> Result<()> {
     }
 }
 
-fn randomlist(what: &str, ctx: &dyn Context, args: &str) -> Result<()> {
-    let parts: Vec<&str> = args.splitn(2, ' ').collect();
-    if parts.len() == 2 && parts[0] == "add" {
-        let n = ctx.bot().sql().lock().execute(
-            "INSERT INTO mod_randomlist (category, string) VALUES ($1, $2) ON CONFLICT (category, string) DO NOTHING",
-            &[&what, &parts[1]],
-        )?;
-        if n == 0 {
-            return ctx.say("That's already on the list.");
-        }
-        return ctx.say("Added.");
+fn add(what: &str, ctx: &dyn Context, args: &str) -> Result<()> {
+    let string = Rest::parse_full(args)?;
+
+    let n = ctx.bot().sql()?.execute(
+        "INSERT INTO mod_randomlist (category, string) VALUES ($1, $2) ON CONFLICT (category, string) DO NOTHING",
+        &[&what, &*string],
+    )?;
+    if n == 0 {
+        return ctx.say("That's already on the list.");
     }
+    ctx.say("Added.")
+}
 
-    let mut db = ctx.bot().sql().lock();
-    let rows = db
-            .query("SELECT string FROM mod_randomlist WHERE category = $1 LIMIT 1 OFFSET FLOOR(RANDOM() * GREATEST((SELECT COUNT(*) FROM mod_randomlist WHERE category = $1), 1) )", &[&what])?;
+fn randomlist(what: &str, ctx: &dyn Context) -> Result<()> {
+    let mut db = ctx.bot().sql()?;
+    let rows = db.query(
+        "SELECT string FROM mod_randomlist WHERE category = $1 ORDER BY RANDOM() LIMIT 1",
+        &[&what],
+    )?;
     if rows.is_empty() {
         return ctx.say(&format!("I don't have anything to give you for '{what}'"));
     }
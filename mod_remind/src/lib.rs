@@ -0,0 +1,191 @@
+// Reminders, kept as scheduled jobs so they survive restarts, and delivered to the channel they
+// were set in.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use rustbot::prelude::*;
+use rustbot::schedule;
+use rustbot::services;
+
+#[cfg(test)]
+mod tests;
+
+rustbot::module!(get_meta);
+
+const HANDLER: &str = "remind.deliver";
+
+const USAGE: &str = "usage: remind <duration or time and timezone> <text>, e.g. \"remind 2h check the server\" or \"remind tomorrow 09:00 Europe/London stand-up\"";
+
+// A job's payload.
+#[derive(Serialize, Deserialize)]
+struct Reminder {
+    // user_string of whoever set it; only they can list or cancel it
+    user: String,
+    // user_pretty, to address them by
+    nick: String,
+    text: String,
+    // Unix timestamp
+    set: i64,
+}
+
+pub fn get_meta(meta: &mut dyn Meta) {
+    meta.cmd(
+        "remind",
+        Command::new(remind)
+            .summary("reminds you of something later, here")
+            .usage("<duration or time and timezone> <text>")
+            .example("2h check the server")
+            .example("tomorrow 09:00 Europe/London stand-up"),
+    );
+    meta.cmd("reminders", Command::new(reminders).summary("lists your reminders"));
+    meta.cmd(
+        "unremind",
+        Command::new(unremind)
+            .summary("cancels one of your reminders")
+            .usage("<id>"),
+    );
+
+    meta.schedule(HANDLER, Box::new(deliver));
+}
+
+fn remind(ctx: &dyn Context, args: &str) -> Result<()> {
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let now = schedule::now();
+    let (at, text) = match parse_when(ctx.bot(), &words, now)? {
+        Some((at, text)) if !text.is_empty() => (at, text),
+        _ => bail_user!("{}", USAGE),
+    };
+    if at <= now {
+        bail_user!("that's in the past");
+    }
+
+    let reminder = Reminder {
+        user: ctx.source().user_string().into_owned(),
+        nick: ctx.source().user_pretty().into_owned(),
+        text,
+        set: now,
+    };
+    let job = Job::new(
+        HANDLER,
+        ctx.config_id(),
+        &target(ctx.source())?,
+        &serde_json::to_string(&reminder)?,
+        Schedule::At(at),
+    )?;
+    let id = ctx.bot().schedule(job)?;
+
+    ctx.say(&format!("#{}: will remind you in {}", id, until(at, now)))
+}
+
+// Splits the time off the front of a reminder: either a duration, e.g. "2h30m", or a date and time
+// as for the time command followed by its timezone, e.g. "tomorrow 09:00 UTC". Returns None if
+// there's neither.
+fn parse_when(bot: &(dyn Bot + Sync), words: &[&str], now: i64) -> Result<Option<(i64, String)>> {
+    let first = match words.first() {
+        Some(first) => first,
+        None => return Ok(None),
+    };
+    match parse_duration(first) {
+        Ok(d) => match i64::try_from(d.as_secs()).ok().and_then(|secs| now.checked_add(secs)) {
+            Some(at) => return Ok(Some((at, words[1..].join(" ")))),
+            None => bail_user!("that's too far in the future"),
+        },
+        Err(e) if e.is::<TooLong>() => bail_user!("that's too far in the future"),
+        Err(_) => {}
+    }
+
+    // times need the time module's services; without them, only durations can be given
+    if bot.service("time.timezone", |_: &services::Timezone| Ok(())).is_err() {
+        return Ok(None);
+    }
+    // the time runs up to the first word that's a timezone
+    for (i, tz) in words.iter().enumerate().skip(1) {
+        if bot.service("time.timezone", |f: &services::Timezone| Ok(f(tz).is_ok()))? {
+            let at = bot.service("time.parse", |f: &services::ParseTime| f(&words[..i].join(" "), tz))?;
+            return Ok(Some((at, words[i + 1..].join(" "))));
+        }
+    }
+    Ok(None)
+}
+
+// Where to deliver a reminder. Ones set in an IRC query go back to whoever set it; Discord DMs
+// can't be sent to by channel.
+fn target(source: &dyn Source) -> Result<String> {
+    let channel = source.channel_string();
+    if channel == "irc:query" {
+        return Ok(format!("irc:{}", source.user_pretty()));
+    }
+    if channel.starts_with("dis:none:") {
+        bail_user!("reminders can only be set in a server's channels, not in direct messages");
+    }
+    Ok(channel.into_owned())
+}
+
+fn deliver(_bot: &dyn Bot, job: &Job) -> Result<Option<Message<'static>>> {
+    let r: Reminder = serde_json::from_str(&job.payload)?;
+    let ago = Duration::from_secs((schedule::now() - r.set).max(0) as u64);
+    Ok(Some(Message::Simple(format!(
+        "{}: reminder from {} ago: {}",
+        r.nick,
+        format_duration(ago),
+        r.text
+    ))))
+}
+
+fn reminders(ctx: &dyn Context, _args: &str) -> Result<()> {
+    let now = schedule::now();
+    let items: Vec<Cow<str>> = own(ctx)?
+        .into_iter()
+        .map(|(job, r)| format!("#{} in {}: {}", job.id, until(job.next_run, now), r.text).into())
+        .collect();
+
+    if items.is_empty() {
+        return ctx.say("you have no reminders");
+    }
+    ctx.reply(Message::List {
+        prefix: "your reminders: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn unremind(ctx: &dyn Context, args: &str) -> Result<()> {
+    let id: i64 = match args.trim().trim_start_matches('#').parse() {
+        Ok(id) => id,
+        Err(_) => bail_user!("usage: unremind <id>, as listed by reminders"),
+    };
+    if !own(ctx)?.iter().any(|(job, _)| job.id == id) {
+        bail_user!("you have no reminder #{}", id);
+    }
+    ctx.bot().unschedule(id)?;
+    ctx.say("cancelled")
+}
+
+// The caller's reminders, soonest first.
+fn own(ctx: &dyn Context) -> Result<Vec<(Job, Reminder)>> {
+    let user = ctx.source().user_string();
+    Ok(ctx
+        .bot()
+        .scheduled(HANDLER)?
+        .into_iter()
+        .filter(|job| job.config_id == ctx.config_id())
+        .filter_map(|job| {
+            let r: Reminder = serde_json::from_str(&job.payload).ok()?;
+            if r.user == user {
+                Some((job, r))
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+// How long until `t`, to the minute once it's that far off, so that "2h" isn't shown as "1h59m59s".
+fn until(t: i64, now: i64) -> String {
+    let secs = (t - now).max(0) as u64;
+    let secs = if secs >= 60 { secs.div_ceil(60) * 60 } else { secs };
+    format_duration(Duration::from_secs(secs))
+}
@@ -0,0 +1,135 @@
+use rustbot::prelude::*;
+use rustbot::schedule;
+use rustbot::services::{ParseTime, Timezone};
+use rustbot::testing::{message_text, MockBot, MockContext, MockSource, TestMeta};
+
+use super::get_meta;
+
+// 2100-01-01 00:00 UTC
+const CENTURY: i64 = 4102444800;
+
+fn bot_with_time() -> MockBot {
+    MockBot::new()
+        .provide(
+            "time.timezone",
+            Box::new(|tz: &str| match tz {
+                "UTC" => Ok("UTC".to_string()),
+                _ => bail_user!("bad tz"),
+            }) as Timezone,
+        )
+        .provide(
+            "time.parse",
+            Box::new(|time: &str, _tz: &str| match time {
+                "2100-01-01 00:00" => Ok(CENTURY),
+                _ => bail_user!("no valid timestamp parsed"),
+            }) as ParseTime,
+        )
+}
+
+#[test]
+fn test_remind_in() {
+    let meta = TestMeta::load(get_meta);
+    let ctx = MockContext::new();
+
+    let before = schedule::now();
+    meta.call(&ctx, "remind", "2h check the server").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["#1: will remind you in 2h"]);
+
+    let jobs = ctx.bot.jobs();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].handler, "remind.deliver");
+    assert_eq!(
+        (jobs[0].config_id.as_str(), jobs[0].channel.as_str()),
+        ("test", "irc:#test")
+    );
+    assert!(jobs[0].next_run >= before + 7200 && jobs[0].next_run <= schedule::now() + 7200);
+
+    let message = message_text(&meta.run_job(&ctx.bot, &jobs[0]).unwrap().unwrap());
+    assert!(message.starts_with("tester: reminder from "), "{}", message);
+    assert!(message.ends_with("s ago: check the server"), "{}", message);
+
+    // too long for a timestamp, or for a duration at all
+    for args in ["200000000000000d hi", "999999999999999d hi"] {
+        let err = meta.call(&ctx, "remind", args).unwrap_err();
+        assert_eq!(err.to_string(), "that's too far in the future", "{}", args);
+    }
+    assert_eq!(ctx.bot.jobs().len(), 1);
+}
+
+#[test]
+fn test_remind_at() {
+    let meta = TestMeta::load(get_meta);
+    let ctx = MockContext::new().with_bot(bot_with_time());
+
+    meta.call(&ctx, "remind", "2100-01-01 00:00 UTC new century").unwrap();
+    let jobs = ctx.bot.jobs();
+    assert_eq!(jobs[0].next_run, CENTURY);
+    assert_eq!(ctx.take_replies().len(), 1);
+
+    // no timezone, no text, or an unparseable time
+    for args in [
+        "",
+        "2h",
+        "someday soon",
+        "2100-01-01 00:00 UTC",
+        "next tuesday UTC party",
+    ] {
+        let err = meta.call(&ctx, "remind", args).unwrap_err();
+        assert!(err.downcast_ref::<UserError>().is_some(), "{:?}: {:?}", args, err);
+    }
+    assert_eq!(ctx.bot.jobs().len(), 1);
+
+    // without the time module, a time is just a bad duration
+    let ctx = MockContext::new();
+    let err = meta
+        .call(&ctx, "remind", "2100-01-01 00:00 UTC new century")
+        .unwrap_err();
+    assert_eq!(err.to_string(), super::USAGE);
+}
+
+#[test]
+fn test_remind_targets() {
+    let meta = TestMeta::load(get_meta);
+
+    // queries go back to the user
+    let ctx = MockContext::new().with_source(MockSource::irc(None, "alice"));
+    meta.call(&ctx, "remind", "10m tea").unwrap();
+    assert_eq!(ctx.bot.jobs()[0].channel, "irc:alice");
+
+    let ctx = MockContext::new().with_source(MockSource::discord(Some(1), 2, 3, "bob"));
+    meta.call(&ctx, "remind", "10m tea").unwrap();
+    assert_eq!(ctx.bot.jobs()[0].channel, "dis:1:2");
+
+    let ctx = MockContext::new().with_source(MockSource::discord(None, 2, 3, "bob"));
+    let err = meta.call(&ctx, "remind", "10m tea").unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+    assert!(ctx.bot.jobs().is_empty());
+}
+
+#[test]
+fn test_reminders_are_per_user() {
+    let meta = TestMeta::load(get_meta);
+    let mut ctx = MockContext::new().with_source(MockSource::irc(Some("#test"), "alice"));
+
+    meta.call(&ctx, "remind", "1d water plants").unwrap();
+    meta.call(&ctx, "remind", "1h tea").unwrap();
+    ctx.take_replies();
+
+    meta.call(&ctx, "reminders", "").unwrap();
+    assert_eq!(
+        ctx.take_replies(),
+        vec!["your reminders: #2 in 1h: tea, #1 in 1d: water plants"]
+    );
+
+    // bob sees, and can cancel, none of them
+    ctx = ctx.with_source(MockSource::irc(Some("#test"), "bob"));
+    meta.call(&ctx, "reminders", "").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["you have no reminders"]);
+    let err = meta.call(&ctx, "unremind", "1").unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+
+    ctx = ctx.with_source(MockSource::irc(Some("#test"), "alice"));
+    meta.call(&ctx, "unremind", "#1").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["cancelled"]);
+    assert_eq!(ctx.bot.jobs().iter().map(|j| j.id).collect::<Vec<_>>(), vec![2]);
+}
@@ -1,21 +1,47 @@
 use rustbot::prelude::*;
+use rustbot::services;
 
 mod status;
 mod updates;
 mod utils;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
+// A command about a server, which defaults to the channel's.
+fn server(f: fn(&dyn Context, &str) -> Result<()>, summary: &str) -> Command {
+    Command::new(f).summary(summary).usage("[server|byond://address]")
+}
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("address", Command::new(status::address));
-    meta.cmd("admins", Command::new(status::admins));
-    meta.cmd("manifest", Command::new(status::manifest));
-    meta.cmd("mode", Command::new(status::mode));
-    meta.cmd("players", Command::new(status::players));
-    meta.cmd("revision", Command::new(status::revision));
-    meta.cmd("status", Command::new(status::status));
+    meta.cmd("address", server(status::address, "shows a server's address"));
+    meta.cmd("admins", server(status::admins, "lists the admins on a server"));
+    meta.cmd("manifest", server(status::manifest, "shows a server's crew manifest"));
+    meta.cmd("mode", server(status::mode, "shows a server's game mode"));
+    meta.cmd("players", server(status::players, "lists the players on a server"));
+    meta.cmd(
+        "revision",
+        server(status::revision, "shows the revision a server is running"),
+    );
+    meta.cmd("status", server(status::status, "shows a server's status"));
+
+    meta.cmd(
+        "update?",
+        server(updates::check_update, "checks whether a server is out of date"),
+    );
+    meta.cmd(
+        "ss13pullrepo",
+        server(updates::pull_repo, "pulls a server's repository"),
+    );
 
-    meta.cmd("update?", Command::new(updates::check_update));
-    meta.cmd("ss13pullrepo", Command::new(updates::pull_repo));
+    meta.provide(
+        "ss13.topic",
+        Box::new(|address: &str, query: &str| {
+            Ok(utils::get_topic_map(address, query.as_bytes())?
+                .into_iter()
+                .map(|(k, v)| (k, v.into_owned()))
+                .collect())
+        }) as services::TopicQuery,
+    );
 }
 
 #[macro_export]
@@ -140,7 +140,7 @@ pub(crate) fn resolve_server<'a>(ctx: &dyn Context, args: &'a str) -> Result<Res
     let addr = if args.is_empty() {
         let channel = format!("{}:{}", ctx.config_id(), ctx.source().channel_string());
 
-        let addr = ctx.bot().sql().lock().query(
+        let addr = ctx.bot().sql()?.query(
             "SELECT id, addr, repo_url, branch FROM ss13_servers JOIN ss13_server_channels USING (id) LEFT JOIN ss13_repositories USING (id) WHERE $1 LIKE channel ORDER BY channel DESC LIMIT 1",
             &[&channel],
         )?;
@@ -149,7 +149,7 @@ pub(crate) fn resolve_server<'a>(ctx: &dyn Context, args: &'a str) -> Result<Res
         }
         addr
     } else {
-        let addr = ctx.bot().sql().lock().query(
+        let addr = ctx.bot().sql()?.query(
             "SELECT id, addr, repo_url, branch FROM ss13_servers JOIN ss13_server_names USING (id) LEFT JOIN ss13_repositories USING (id) WHERE name = $1",
             &[&args],
         )?;
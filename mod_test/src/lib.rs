@@ -2,12 +2,25 @@ use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use rustbot::prelude::*;
 use rustbot::{span, spans};
 
-#[no_mangle]
+#[cfg(test)]
+mod tests;
+
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
+    // requests the server has answered, kept across reloads
+    let served = Arc::new(AtomicU64::new(meta.restore().unwrap_or(0)));
+    {
+        let served = Arc::clone(&served);
+        meta.keep(move || served.load(Ordering::SeqCst));
+    }
+
     meta.cmd(
         "test",
         Command::new(|ctx, args| {
@@ -32,12 +45,24 @@ pub fn get_meta(meta: &mut dyn Meta) {
         }),
     );
 
-    meta.cmd("test2", Command::new(test2));
+    meta.cmd(
+        "test2",
+        Command::new(test2)
+            .summary("echoes its parsed arguments")
+            .args(describe_args! {
+                a: u64,
+                b: Atom,
+                c: String,
+            }),
+    );
 
     thread!(meta, async {
         let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
 
-        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(hello_world)) });
+        let make_svc = make_service_fn(move |_conn| {
+            let served = Arc::clone(&served);
+            async move { Ok::<_, Infallible>(service_fn(move |req| hello_world(req, Arc::clone(&served)))) }
+        });
 
         let server = Server::bind(&addr).serve(make_svc);
 
@@ -55,7 +80,8 @@ fn test2(ctx: &dyn Context, args: &str) -> Result<()> {
     ctx.reply(Message::Simple(format!("You passed {:?}", (a, b, c))))
 }
 
-async fn hello_world(_req: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
-    info!("hello world server called");
+async fn hello_world(_req: Request<Body>, served: Arc<AtomicU64>) -> std::result::Result<Response<Body>, Infallible> {
+    let n = served.fetch_add(1, Ordering::SeqCst) + 1;
+    info!("hello world server called ({} times)", n);
     Ok(Response::new("Hello, World".into()))
 }
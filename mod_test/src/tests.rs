@@ -0,0 +1,24 @@
+use rustbot::prelude::*;
+use rustbot::testing::{MockContext, MockSource, TestMeta};
+
+use super::get_meta;
+
+#[test]
+fn test_commands() {
+    let meta = TestMeta::load(get_meta);
+    assert_eq!(meta.commands.keys().collect::<Vec<_>>(), vec!["test", "test2"]);
+    assert_eq!(meta.threads.len(), 1);
+
+    let ctx = MockContext::new()
+        .with_source(MockSource::discord(Some(1), 2, 3, "tester"))
+        .with_perms(Perms::Admin);
+
+    meta.call(&ctx, "test", "some args").unwrap();
+    let replies = ctx.take_replies();
+    assert_eq!(replies[0], "beep boop Admin");
+    assert_eq!(replies[1], "you passed: some args");
+    assert!(replies[2].starts_with("simple bold italic"));
+
+    meta.call(&ctx, "test2", "1 two \"three four\"").unwrap();
+    assert_eq!(ctx.take_replies(), vec![r#"You passed (1, Atom("two"), "three four")"#]);
+}
@@ -1,11 +1,33 @@
 use rustbot::prelude::*;
+use rustbot::services;
 
 use chrono::{DateTime, Datelike, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use chrono_tz::Tz;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("time", Command::new(time));
+    meta.cmd(
+        "time",
+        Command::new(time)
+            .summary("shows the time in a timezone, or converts a time between timezones")
+            .usage("[time] [from-timezone] <timezone>")
+            .example("Europe/London")
+            .example("2024-03-01 14:30 UTC")
+            .example("14:30 UTC America/New_York"),
+    );
+
+    meta.provide(
+        "time.timezone",
+        Box::new(|tz: &str| Ok(parse_tz(tz)?.name().to_string())) as services::Timezone,
+    );
+    meta.provide(
+        "time.parse",
+        Box::new(|time: &str, tz: &str| {
+            let parts: Vec<&str> = time.split_whitespace().collect();
+            Ok(parse_time(&parts, parse_tz(tz)?)?.timestamp())
+        }) as services::ParseTime,
+    );
 }
 
 fn usage(ctx: &dyn Context) -> Result<()> {
@@ -2,9 +2,20 @@ use rustbot::prelude::*;
 use std::borrow::Cow;
 use std::process::Command as StdCommand;
 
-#[no_mangle]
+rustbot::module!(get_meta);
+
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("units", Command::new(units));
+    meta.cmd(
+        "units",
+        Command::new(units)
+            .summary("converts between units")
+            .usage("[<from> to] <to>")
+            .example("10 miles to km")
+            .args(describe_args! {
+                from: Option<FromUnits>,
+                to: rustbot::args::Rest,
+            }),
+    );
 }
 
 struct FromUnits(String);
@@ -11,10 +11,16 @@ struct Module {
     appid: String,
 }
 
-#[no_mangle]
+rustbot::module!(config: get_meta_conf);
+
 pub fn get_meta_conf(meta: &mut dyn Meta, config: toml::Value) -> Result<()> {
     let m: Module = config.try_into()?;
-    meta.cmd("weather", Command::new(move |ctx, args| m.weather(ctx, args)));
+    meta.cmd(
+        "weather",
+        Command::new(move |ctx, args| m.weather(ctx, args))
+            .summary("shows the weather somewhere")
+            .usage("<place or airport code>"),
+    );
     Ok(())
 }
 
@@ -0,0 +1,43 @@
+// Works out an ID for this exact build of the library, for rustbot::abi::BUILD_ID: modules are
+// only safe to load into a bot built from the same source, by the same compiler, with the same
+// settings, against the same versions of its dependencies.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::process::Command;
+
+fn hash_dir(dir: &Path, hasher: &mut DefaultHasher) {
+    let mut entries: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            hash_dir(&path, hasher);
+        } else {
+            path.strip_prefix(dir).unwrap().hash(hasher);
+            fs::read(&path).unwrap().hash(hasher);
+        }
+    }
+}
+
+fn main() {
+    let mut hasher = DefaultHasher::new();
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc).arg("-vV").output().unwrap().stdout;
+    version.hash(&mut hasher);
+    for var in &["CARGO_PKG_VERSION", "TARGET", "PROFILE", "OPT_LEVEL", "DEBUG"] {
+        env::var(var).ok().hash(&mut hasher);
+    }
+    hash_dir(Path::new("src/lib"), &mut hasher);
+    // dependencies' types cross into modules too, e.g. anyhow::Error; the workspace's lock file
+    // pins their versions
+    fs::read("../Cargo.lock").ok().hash(&mut hasher);
+
+    println!("cargo:rustc-env=RUSTBOT_BUILD_ID={:016x}", hasher.finish());
+    println!("cargo:rerun-if-changed=src/lib");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=../Cargo.lock");
+}
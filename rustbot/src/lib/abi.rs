@@ -0,0 +1,116 @@
+// The interface between the bot and module libraries.
+//
+// Each module exports a `ModuleDecl` (see the `module!` macro). Its layout is fixed, so the bot
+// can always read it, whatever the module was built with; everything past it uses the Rust ABI
+// and the types in this crate, which are only safe to share with a module built against exactly
+// the same build of this crate, so the bot checks the declaration before calling anything.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::prelude::*;
+
+// Bump this whenever the layout of ModuleDecl changes.
+pub const ABI_VERSION: u32 = 1;
+
+// Identifies this build of this crate, including the compiler and profile used; NUL-terminated.
+pub const BUILD_ID: &str = concat!(env!("RUSTBOT_BUILD_ID"), "\0");
+
+pub const DECL_SYMBOL: &[u8] = b"RUSTBOT_MODULE\0";
+
+pub type Config = toml::Value;
+
+// Registers the module's commands and handlers; `config` is the module's section of the bot
+// config, if there is one.
+pub type GetMetaFn = fn(meta: &mut dyn Meta, config: Option<Config>) -> Result<()>;
+
+#[repr(C)]
+pub struct ModuleDecl {
+    pub abi_version: u32,
+    // NUL-terminated strings
+    pub build_id: *const c_char,
+    pub name: *const c_char,
+    // only safe to call once `check` has passed
+    pub get_meta: GetMetaFn,
+}
+
+// The pointers are all to static strings.
+unsafe impl Sync for ModuleDecl {}
+
+impl ModuleDecl {
+    /// Checks the module was built against this build of rustbot, returning its entry point.
+    ///
+    /// # Safety
+    /// `decl` must point to a module declaration of any ABI version.
+    pub unsafe fn check(decl: *const Self) -> Result<GetMetaFn> {
+        let abi_version = std::ptr::read(&(*decl).abi_version);
+        if abi_version != ABI_VERSION {
+            bail!(
+                "module uses module ABI version {}, but this bot uses version {}; rebuild the module against this bot",
+                abi_version,
+                ABI_VERSION
+            );
+        }
+
+        let name = CStr::from_ptr((*decl).name).to_string_lossy();
+        let build_id = CStr::from_ptr((*decl).build_id).to_string_lossy();
+        let ours = BUILD_ID.trim_end_matches('\0');
+        if build_id != ours {
+            bail!(
+                "module {} was built against rustbot build {}, but this bot is build {}; rebuild the module with the same compiler and rustbot source as the bot",
+                name,
+                build_id,
+                ours
+            );
+        }
+
+        Ok((*decl).get_meta)
+    }
+}
+
+// Declares a module's entry point, so the bot can load it. Modules without config call it with
+// their `get_meta` function:
+//
+//     rustbot::module!(get_meta);
+//
+// and modules that need a config section pass a function that also takes the config:
+//
+//     rustbot::module!(config: get_meta_conf);
+#[macro_export]
+macro_rules! module {
+    ($get_meta:path) => {
+        $crate::module!(@decl |meta, _config| {
+            $get_meta(meta);
+            Ok(())
+        });
+    };
+    (config: $get_meta_conf:path) => {
+        $crate::module!(@decl |meta, config| match config {
+            Some(config) => $get_meta_conf(meta, config),
+            None => $crate::prelude::bail!("required config not passed"),
+        });
+    };
+    (@decl |$meta:ident, $config:ident| $body:expr) => {
+        #[no_mangle]
+        pub static RUSTBOT_MODULE: $crate::abi::ModuleDecl = {
+            fn __rustbot_module_entry(
+                $meta: &mut dyn $crate::types::Meta,
+                $config: ::std::option::Option<$crate::abi::Config>,
+            ) -> $crate::error::Result<()> {
+                $crate::types::Meta::module(
+                    $meta,
+                    env!("CARGO_PKG_NAME").trim_start_matches("mod_"),
+                    env!("CARGO_PKG_VERSION"),
+                );
+                $body
+            }
+
+            $crate::abi::ModuleDecl {
+                abi_version: $crate::abi::ABI_VERSION,
+                build_id: $crate::abi::BUILD_ID.as_ptr() as *const _,
+                name: concat!(env!("CARGO_PKG_NAME"), "\0").as_ptr() as *const _,
+                get_meta: __rustbot_module_entry,
+            }
+        };
+    };
+}
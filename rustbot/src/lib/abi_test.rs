@@ -0,0 +1,50 @@
+use crate::abi::{ModuleDecl, ABI_VERSION, BUILD_ID};
+use crate::prelude::*;
+use crate::testing::TestMeta;
+
+fn get_meta(meta: &mut dyn Meta) {
+    meta.cmd("ping", Command::new(|ctx, _args| ctx.say("pong")));
+}
+
+crate::module!(get_meta);
+
+#[test]
+fn test_module_decl_check() {
+    let get_meta = unsafe { ModuleDecl::check(&RUSTBOT_MODULE) }.unwrap();
+    let mut meta = TestMeta::default();
+    get_meta(&mut meta, None).unwrap();
+    assert!(meta.command("ping").is_some());
+    assert_eq!(
+        meta.module,
+        Some(("rustbot".to_string(), env!("CARGO_PKG_VERSION").to_string()))
+    );
+
+    let old = ModuleDecl {
+        abi_version: ABI_VERSION + 1,
+        ..RUSTBOT_MODULE
+    };
+    let err = unsafe { ModuleDecl::check(&old) }.unwrap_err().to_string();
+    assert!(
+        err.starts_with(&format!(
+            "module uses module ABI version {}, but this bot uses version {}",
+            ABI_VERSION + 1,
+            ABI_VERSION
+        )),
+        "{}",
+        err
+    );
+
+    let other = ModuleDecl {
+        build_id: "0123456789abcdef\0".as_ptr() as *const _,
+        ..RUSTBOT_MODULE
+    };
+    let err = unsafe { ModuleDecl::check(&other) }.unwrap_err().to_string();
+    assert!(
+        err.starts_with(&format!(
+            "module rustbot was built against rustbot build 0123456789abcdef, but this bot is build {}",
+            BUILD_ID.trim_end_matches('\0')
+        )),
+        "{}",
+        err
+    );
+}
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::time::Duration;
 
 use super::prelude::*;
 
@@ -10,9 +11,7 @@ macro_rules! parse_args {
         let ($($name),*) = match <($($ty),*) as Arg>::parse_full_no_pfx($args) {
             Ok(v) => v,
             Err(e) => return Err(UserError::new(format!("parsing {}: {}",
-                format!("({})", [$(
-                    format!("{}: {}", stringify!($name), <$ty as Arg>::describe_expected())
-                ),*].join(", ")),
+                $crate::describe_args! {$($name: $ty,)*},
                 e,
             )).into())
         };
@@ -20,6 +19,32 @@ macro_rules! parse_args {
 }
 pub use crate::parse_args;
 
+// The signature parse_args! describes its arguments with, for Command::args, e.g.
+// "(user: atom, message: rest-of-input)".
+#[macro_export]
+macro_rules! describe_args {
+    ($(
+        $name:ident: $ty:ty,
+    )*) => {
+        format!("({})", [$(
+            format!("{}: {}", stringify!($name), <$ty as $crate::args::Arg>::describe_expected())
+        ),*].join(", "))
+    }
+}
+pub use crate::describe_args;
+
+// Implements Arg for a struct, parsing its fields in order, or for an enum of keywords, parsing
+// one of its variants' names in kebab-case (e.g. `WarnAll` is "warn-all"). Struct fields can be
+// marked with:
+//  #[arg(flag)]            a bool, true if `--name` is given
+//  #[arg(long)]            given as `--name <value>` rather than positionally
+//  #[arg(default)]         Default::default() if not given
+//  #[arg(default = expr)]  `expr` if not given
+//  #[arg(name = "...")]    called this in help and errors, or as a flag; also works on variants
+// Flags and long arguments can come before any positional argument. Parse one with
+// Arg::parse_full, and describe it for Command::args with Arg::describe_expected.
+pub use rustbot_derive::Args;
+
 pub trait Arg: Sized {
     fn parse_from(input: &str) -> Result<(Self, Option<&str>)>;
 
@@ -59,6 +84,18 @@ macro_rules! ws_terminated {
             }
         }
     };
+    ($ty:ty, $desc:literal, |$n:ident| $conv:expr) => {
+        impl Arg for $ty {
+            fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+                let (Atom($n), rest) = Atom::parse_from(input)?;
+                Ok(($conv, rest))
+            }
+
+            fn describe_expected() -> Cow<'static, str> {
+                Cow::Borrowed($desc)
+            }
+        }
+    };
 }
 
 macro_rules! impl_ws_with_parse {
@@ -75,9 +112,25 @@ macro_rules! impl_ws_with_parse {
 impl_ws_with_parse! {
     i8, i16, i32, i64, isize,
     u8, u16, u32, u64, usize,
+    f32, f64,
     bool
 }
 
+ws_terminated!(Duration, "duration", |this| match parse_duration(&this) {
+    Ok(d) => d,
+    Err(e) if e.is::<TooLong>() => bail_user!("{:?} is too long a duration", this),
+    Err(e) => bail_user!("failed to parse {:?} as a duration: {}", this, e),
+});
+
+ws_terminated!(
+    chrono_tz::Tz,
+    "timezone",
+    |this| match chrono_tz::Tz::from_str_insensitive(&this) {
+        Ok(tz) => tz,
+        Err(_) => bail_user!("unknown timezone {:?}", this),
+    }
+);
+
 // More complex: strings
 
 // Atom: a single non-quoted segment
@@ -116,29 +169,39 @@ impl Arg for String {
             bail_user!("missing argument")
         }
 
-        let (this, rest) = if &input[0..1] == "\"" {
-            // Quoted string
-            match input[1..].split_once('"') {
-                Some((this, rest)) => {
-                    if rest == "" {
-                        (this, None)
-                    } else if rest.chars().next().unwrap().is_whitespace() {
-                        (this, Some(&rest[1..]))
-                    } else {
-                        bail_user!("bad quoted string");
-                    }
+        let (this, rest) = if let Some(quoted) = input.strip_prefix('"') {
+            // Quoted string, in which \" and \\ are " and \
+            let mut this = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 1,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => this.push(c),
+                        Some((_, c)) => {
+                            this.push('\\');
+                            this.push(c);
+                        }
+                        None => bail_user!("bad quoted string"),
+                    },
+                    Some((_, c)) => this.push(c),
+                    None => bail_user!("bad quoted string"),
                 }
-                _ => bail_user!("bad quoted string"),
+            };
+            match quoted[end..].chars().next() {
+                None => (this, None),
+                Some(c) if c.is_whitespace() => (this, Some(&quoted[end + c.len_utf8()..])),
+                Some(_) => bail_user!("bad quoted string"),
             }
         } else {
             // Non-quoted string; take everything up to the next whitespace
             match input.split_once(char::is_whitespace) {
-                Some((this, rest)) => (this, Some(rest)),
-                None => (input, None),
+                Some((this, rest)) => (this.to_string(), Some(rest)),
+                None => (input.to_string(), None),
             }
         };
 
-        Ok((this.into(), rest))
+        Ok((this, rest))
     }
 
     fn describe_expected() -> Cow<'static, str> {
@@ -170,6 +233,162 @@ impl Arg for Rest {
     }
 }
 
+// Comma: a comma-separated list, e.g. "a,b,c", with no spaces
+#[derive(Debug, PartialEq, Eq)]
+pub struct Comma<T>(pub Vec<T>);
+impl<T> std::ops::Deref for Comma<T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: Arg> Arg for Comma<T> {
+    fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+        let (Atom(list), rest) = Atom::parse_from(input)?;
+        let mut items = vec![];
+        for item in list.split(',') {
+            match T::parse_full_no_pfx(item) {
+                Ok(v) => items.push(v),
+                Err(e) => bail_user!("item {:?}: {}", item, e),
+            }
+        }
+        Ok((Comma(items), rest))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Owned(format!("{},...", T::describe_expected()))
+    }
+}
+
+// KeyValue: `key=value`, e.g. for options; the value can be quoted if it's a String
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeyValue<T> {
+    pub key: String,
+    pub value: T,
+}
+
+impl<T: Arg> Arg for KeyValue<T> {
+    fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+        if input.is_empty() {
+            bail_user!("missing argument")
+        }
+
+        let (key, value) = match input.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => (key, value),
+            _ => bail_user!(
+                "expected key=value, got {:?}",
+                input.split_whitespace().next().unwrap_or("")
+            ),
+        };
+        let (value, rest) = match T::parse_from(value) {
+            Ok(v) => v,
+            Err(e) => bail_user!("{}: {}", key, e),
+        };
+        Ok((
+            KeyValue {
+                key: key.to_string(),
+                value,
+            },
+            rest,
+        ))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Owned(format!("key={}", T::describe_expected()))
+    }
+}
+
+// Mention: a user, as a Discord mention (`<@id>` or `<@!id>`), `@name`, or just a name
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mention {
+    Id(u64),
+    Name(String),
+}
+
+impl Mention {
+    // The user as Source::user_string gives them, as found on the context's network; a user error
+    // if there's no such user there.
+    pub fn user_string(&self, ctx: &dyn Context) -> Result<String> {
+        match ctx.find_user(self)? {
+            Some(user) => Ok(user),
+            None => bail_user!("can't find user {}", self.pretty(ctx.source())),
+        }
+    }
+
+    // How to refer to the user in a reply on the source's network.
+    pub fn pretty(&self, source: &dyn Source) -> String {
+        match self {
+            Mention::Id(id) if source.get_discord_params().is_some() => format!("<@{id}>"),
+            Mention::Id(id) => id.to_string(),
+            Mention::Name(name) => name.clone(),
+        }
+    }
+}
+
+impl Arg for Mention {
+    fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+        let (Atom(this), rest) = Atom::parse_from(input)?;
+        let id = this
+            .strip_prefix("<@")
+            .and_then(|id| id.strip_suffix('>'))
+            .map(|id| id.trim_start_matches('!'));
+        let mention = match id {
+            Some(id) => match id.parse() {
+                Ok(id) => Mention::Id(id),
+                Err(_) => bail_user!("bad mention {:?}", this),
+            },
+            None => Mention::Name(this.strip_prefix('@').unwrap_or(&this).to_string()),
+        };
+        Ok((mention, rest))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Borrowed("user")
+    }
+}
+
+// ChannelRef: a channel, as a Discord mention (`<#id>`) or a name like `#chan`
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChannelRef {
+    Id(u64),
+    // including the leading # or other prefix
+    Name(String),
+}
+
+impl ChannelRef {
+    // The channel as Source::channel_string gives it, as found on the context's network; a user
+    // error if there's no such channel there.
+    pub fn channel_string(&self, ctx: &dyn Context) -> Result<String> {
+        match ctx.find_channel(self)? {
+            Some(channel) => Ok(channel),
+            None => match self {
+                ChannelRef::Id(id) => bail_user!("can't find channel <#{}>", id),
+                ChannelRef::Name(name) => bail_user!("can't find channel {}", name),
+            },
+        }
+    }
+}
+
+impl Arg for ChannelRef {
+    fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+        let (Atom(this), rest) = Atom::parse_from(input)?;
+        let channel = match this.strip_prefix("<#").and_then(|id| id.strip_suffix('>')) {
+            Some(id) => match id.parse() {
+                Ok(id) => ChannelRef::Id(id),
+                Err(_) => bail_user!("bad channel mention {:?}", this),
+            },
+            None if this.starts_with(|c| "#&!+".contains(c)) => ChannelRef::Name(this),
+            None => bail_user!("expected a channel, got {:?}", this),
+        };
+        Ok((channel, rest))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Borrowed("channel")
+    }
+}
+
 // Combining args: tuples
 macro_rules! tuple_impls {
     ( $head:ident, $($tail:ident,)* ) => {
@@ -226,3 +445,52 @@ impl<T: Arg> Arg for Option<T> {
         Cow::Owned(format!("optional {}", T::describe_expected()))
     }
 }
+
+// Repeated args: Vec<T>, one or more of them
+impl<T: Arg> Arg for Vec<T> {
+    fn parse_from(input: &str) -> Result<(Self, Option<&str>)> {
+        let (first, mut rest) = T::parse_from(input)?;
+        let mut items = vec![first];
+        while let Some(input) = derive::rest(rest) {
+            match T::parse_from(input) {
+                Ok((this, r)) => {
+                    items.push(this);
+                    rest = r;
+                }
+                Err(_) => return Ok((items, Some(input))),
+            }
+        }
+        Ok((items, None))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Owned(format!("{}...", T::describe_expected()))
+    }
+}
+
+// What the code #[derive(Args)] generates uses.
+#[doc(hidden)]
+pub mod derive {
+    use super::*;
+
+    // Treats an empty remainder as there being nothing left.
+    pub fn rest(input: Option<&str>) -> Option<&str> {
+        input.map(str::trim_start).filter(|i| !i.is_empty())
+    }
+
+    // Splits `--name` off the start of the input.
+    pub fn flag(input: &str) -> Option<(&str, Option<&str>)> {
+        let input = input.strip_prefix("--")?;
+        Some(match input.split_once(char::is_whitespace) {
+            Some((name, r)) => (name, rest(Some(r))),
+            None => (input, None),
+        })
+    }
+
+    pub fn field<'a, T: Arg>(name: &str, input: Option<&'a str>) -> Result<(T, Option<&'a str>)> {
+        match T::parse_from(input.unwrap_or("")) {
+            Ok((this, r)) => Ok((this, rest(r))),
+            Err(e) => bail_user!("{}: {}", name, e),
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use crate::args::{Arg, Atom};
+use crate::args::{Arg, Args, Atom, ChannelRef, Comma, KeyValue, Mention, Rest};
+use crate::prelude::*;
+use crate::testing::{MockContext, MockSource};
 use std::borrow::Cow;
+use std::time::Duration;
 
 #[test]
 fn test_arg_parse_full() {
@@ -24,6 +27,15 @@ fn test_arg_parse_full() {
 
         "1 foo 3" => (u32, Option<u32>, Atom, u32) => (1, None, Atom("foo".to_string()), 3);
         "1 2 foo 3" => (u32, Option<u32>, Atom, u32) => (1, Some(2), Atom("foo".to_string()), 3);
+
+        "1.5 1h30m" => (f64, Duration) => (1.5, Duration::from_secs(5400));
+        "europe/london" => (chrono_tz::Tz,) => (chrono_tz::Europe::London,);
+        "\"say \\\"hi\\\" \\\\o/\" x" => (String, Atom) => ("say \"hi\" \\o/".to_string(), Atom("x".to_string()));
+        "1,2,3 4" => (Comma<u32>, u32) => (Comma(vec![1, 2, 3]), 4);
+        "n=3 msg=\"a b\"" => (KeyValue<u32>, KeyValue<String>) => (
+            KeyValue { key: "n".to_string(), value: 3 },
+            KeyValue { key: "msg".to_string(), value: "a b".to_string() },
+        );
     );
 
     macro_rules! parse_err {
@@ -41,5 +53,211 @@ fn test_arg_parse_full() {
         "1 2 3" => (u32, u32) => "parsing (u32, u32): extra arguments at end: \"3\"";
         "1 \"foo bar\" 3" => (u32, Atom, u32) => "parsing (u32, atom, u32): failed to parse \"bar\\\"\" as u32: invalid digit found in string";
         "foo bar 2" => (Option<u32>, Atom, u32) => "parsing (optional u32, atom, u32): failed to parse \"bar\" as u32: invalid digit found in string";
+
+        "1x" => Duration => "parsing duration: failed to parse \"1x\" as a duration: unexpected input at 1x";
+        "999999999999999d" => Duration => "parsing duration: \"999999999999999d\" is too long a duration";
+        "mars/olympus" => (chrono_tz::Tz,) => "parsing (timezone): unknown timezone \"mars/olympus\"";
+        "\"foo \\\"" => String => "parsing string: bad quoted string";
+        "1,x" => (Comma<u32>,) => "parsing (u32,...): item \"x\": failed to parse \"x\" as u32: invalid digit found in string";
+        "n3" => (KeyValue<u32>,) => "parsing (key=u32): expected key=value, got \"n3\"";
+        "n=x" => (KeyValue<u32>,) => "parsing (key=u32): n: failed to parse \"x\" as u32: invalid digit found in string";
+    );
+}
+
+#[test]
+fn test_mentions() {
+    let discord = MockSource::discord(Some(1), 2, 3, "someone");
+    let irc = MockSource::irc(Some("#chan"), "someone");
+    let ctx = MockContext::new()
+        .with_source(MockSource::irc(Some("#chan"), "someone"))
+        .with_user("bob", "bob!b@example.com")
+        .with_channel("#rust", "irc:#rust");
+
+    let user = Mention::parse_full("<@!42>").unwrap();
+    assert_eq!(user, Mention::Id(42));
+    assert_eq!(user.pretty(&discord), "<@42>");
+    assert_eq!(user.user_string(&ctx).unwrap_err().to_string(), "can't find user 42");
+
+    let user = Mention::parse_full("@bob").unwrap();
+    assert_eq!(user, Mention::Name("bob".to_string()));
+    assert_eq!(user.pretty(&irc), "bob");
+    assert_eq!(user.user_string(&ctx).unwrap(), "bob!b@example.com");
+    let err = Mention::parse_full("@alice").unwrap().user_string(&ctx).unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+
+    let channel = ChannelRef::parse_full("<#5>").unwrap();
+    assert_eq!(channel, ChannelRef::Id(5));
+    assert_eq!(
+        channel.channel_string(&ctx).unwrap_err().to_string(),
+        "can't find channel <#5>"
+    );
+
+    let channel = ChannelRef::parse_full("#rust").unwrap();
+    assert_eq!(channel.channel_string(&ctx).unwrap(), "irc:#rust");
+
+    assert_eq!(
+        ChannelRef::parse_full("rust").unwrap_err().to_string(),
+        "parsing channel: expected a channel, got \"rust\""
+    );
+}
+
+#[test]
+fn test_derive_args() {
+    #[derive(Args, Debug, PartialEq)]
+    enum Level {
+        Error,
+        WarnAll,
+        #[arg(name = "all")]
+        Everything,
+    }
+
+    #[derive(Args, Debug, PartialEq)]
+    struct Opts {
+        #[arg(flag)]
+        global: bool,
+        #[arg(long, default = 5)]
+        max_count: u32,
+        config: Atom,
+        #[arg(default = Level::Error)]
+        level: Level,
+        #[arg(default)]
+        modules: Vec<Atom>,
+    }
+
+    assert_eq!(
+        Opts::describe_expected(),
+        "([--global], [--max-count u32], config: atom, [level: error|warn-all|all], [modules: atom...])"
+    );
+
+    let atoms = |s: &[&str]| s.iter().map(|s| Atom(s.to_string())).collect::<Vec<_>>();
+    assert_eq!(
+        Opts::parse_full("--global irc warn-all a b").unwrap(),
+        Opts {
+            global: true,
+            max_count: 5,
+            config: Atom("irc".to_string()),
+            level: Level::WarnAll,
+            modules: atoms(&["a", "b"]),
+        }
+    );
+    assert_eq!(
+        Opts::parse_full("irc --max-count 2 ALL").unwrap(),
+        Opts {
+            global: false,
+            max_count: 2,
+            config: Atom("irc".to_string()),
+            level: Level::Everything,
+            modules: vec![],
+        }
+    );
+
+    let err = |input| Opts::parse_full(input).unwrap_err().to_string();
+    let prefix = format!("parsing {}: ", Opts::describe_expected());
+    assert_eq!(err(""), prefix.clone() + "config: missing argument");
+    assert_eq!(err("--local irc"), prefix.clone() + "unknown flag --local");
+    assert_eq!(
+        err("irc --max-count x"),
+        prefix.clone() + "--max-count: failed to parse \"x\" as u32: invalid digit found in string"
+    );
+    assert_eq!(
+        err("irc warn"),
+        prefix + "level: expected error|warn-all|all, got \"warn\""
+    );
+
+    assert_eq!(<Vec<u32>>::parse_full("1 2  3").unwrap(), vec![1, 2, 3]);
+    assert_eq!(
+        <Vec<u32>>::parse_full("1 x").unwrap_err().to_string(),
+        "parsing u32...: extra arguments at end: \"x\""
+    );
+}
+
+#[test]
+fn test_command_help() {
+    fn parse(_ctx: &dyn Context, args: &str) -> Result<()> {
+        parse_args! {args,
+            count: u32,
+            text: Rest,
+        }
+        let _ = (count, text);
+        Ok(())
+    }
+
+    let signature = describe_args! {count: u32, text: Rest,};
+    assert_eq!(signature, "(count: u32, text: rest-of-input)");
+    let err = parse(&crate::testing::MockContext::new(), "x").unwrap_err().to_string();
+    assert!(err.starts_with(&format!("parsing {signature}: ")), "{}", err);
+
+    let bare = Command::new(parse);
+    assert_eq!(bare.help.describe("echo"), "echo: no description");
+
+    let cmd = bare.summary("repeats some text").args(signature);
+    assert_eq!(
+        cmd.help.describe("echo"),
+        "echo (count: u32, text: rest-of-input): repeats some text"
+    );
+
+    let cmd = cmd.usage("<count> <text>").example("3 hello").example("1 bye");
+    assert_eq!(
+        cmd.help.describe("echo"),
+        "echo <count> <text>: repeats some text\n\
+         arguments: (count: u32, text: rest-of-input)\n\
+         e.g. echo 3 hello; echo 1 bye"
+    );
+}
+
+#[test]
+fn test_subcommands() {
+    let echo = |prefix: &'static str| Command::new(move |ctx, args| ctx.say(&format!("{prefix} {args}")));
+    let cmd = echo("list")
+        .summary("shows the list")
+        .sub("add", echo("add").summary("adds to the list").usage("<item>"))
+        .sub(
+            "admin",
+            Command::group()
+                .req_perms(Perms::Admin)
+                .sub("clear", echo("clear"))
+                .sub("check", echo("check")),
+        );
+
+    let call = crate::testing::call;
+    let ctx = crate::testing::MockContext::new();
+    call(&ctx, "list", &cmd, "").unwrap();
+    call(&ctx, "list", &cmd, "add  foo bar").unwrap();
+    call(&ctx, "list", &cmd, "other").unwrap();
+    // not allowed to run admin's subcommands
+    call(&ctx, "list", &cmd, "admin clear").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["list ", "add foo bar", "list other"]);
+
+    let ctx = crate::testing::MockContext::new().with_perms(Perms::Admin);
+    call(&ctx, "list", &cmd, "admin clear now").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["clear now"]);
+    assert_eq!(
+        call(&ctx, "list", &cmd, "admin").unwrap_err().to_string(),
+        "missing subcommand; try check, clear"
+    );
+    assert_eq!(
+        call(&ctx, "list", &cmd, "admin cl").unwrap_err().to_string(),
+        "unknown subcommand; try clear, check"
+    );
+
+    // subcommands are checked by their full names, as cmdperm overrides them
+    let mut checked = vec![];
+    let (name, _, args) = cmd
+        .resolve_permitted("list", "admin clear now", |name, _| {
+            checked.push(name.to_string());
+            Ok(true)
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!((name.as_str(), args), ("list.admin.clear", "now"));
+    assert_eq!(checked, vec!["list", "list.admin", "list.admin.clear"]);
+
+    assert_eq!(
+        cmd.help.describe("list"),
+        "list: shows the list\nsubcommands: add, admin"
+    );
+    assert_eq!(
+        cmd.help.subcommands["add"].describe("list add"),
+        "list add <item>: adds to the list"
     );
 }
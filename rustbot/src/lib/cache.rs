@@ -0,0 +1,116 @@
+// Caching of data loaded from the database, so things that are looked up for every message don't
+// need a query each time.
+//
+// The bot keeps a generation number for each table, which changes whenever that table might have
+// changed; a `Cached` value remembers the generations of the tables it was loaded from, and loads
+// itself again when any of them move on.
+
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::sql::Connection;
+use crate::types::Bot;
+
+// Generation numbers are unique across all bots in the process, so a cache that outlives one bot
+// can never mistake another's data for its own.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::SeqCst)
+}
+
+pub struct Generations {
+    // tables not in the map are at the base generation
+    base: AtomicU64,
+    tables: RwLock<BTreeMap<String, u64>>,
+}
+
+impl Generations {
+    pub fn new() -> Self {
+        Self {
+            base: AtomicU64::new(next_generation()),
+            tables: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn get(&self, table: &str) -> u64 {
+        let tables = self.tables.read();
+        match tables.get(table) {
+            Some(g) => *g,
+            None => self.base.load(Ordering::SeqCst),
+        }
+    }
+
+    // Marks the given table as changed, or every table if None.
+    pub fn bump(&self, table: Option<&str>) {
+        let mut tables = self.tables.write();
+        match table {
+            Some(table) => {
+                tables.insert(table.to_string(), next_generation());
+            }
+            None => {
+                tables.clear();
+                self.base.store(next_generation(), Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl Default for Generations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Cached<T> {
+    tables: &'static [&'static str],
+    value: RwLock<Option<(Vec<u64>, Arc<T>)>>,
+}
+
+impl<T> Cached<T> {
+    // `tables` must list every table `load` reads from.
+    pub fn new(tables: &'static [&'static str]) -> Self {
+        Self {
+            tables,
+            value: RwLock::new(None),
+        }
+    }
+
+    pub fn get<F>(&self, bot: &dyn Bot, load: F) -> Result<Arc<T>>
+    where
+        F: FnOnce(&mut dyn Connection) -> Result<T>,
+    {
+        // read the generations before loading, so a change made while we load is picked up next time
+        let generations: Vec<u64> = self.tables.iter().map(|t| bot.generation(t)).collect();
+
+        if let Some((g, value)) = &*self.value.read() {
+            if *g == generations {
+                return Ok(Arc::clone(value));
+            }
+        }
+
+        let value = Arc::new(load(&mut **bot.sql()?)?);
+        *self.value.write() = Some((generations, Arc::clone(&value)));
+        Ok(value)
+    }
+}
+
+// Matches `s` against a SQL LIKE pattern, case-sensitively, as Postgres does by default.
+pub fn like(pattern: &str, s: &str) -> bool {
+    fn matches(p: &[char], s: &[char]) -> bool {
+        match p.split_first() {
+            None => s.is_empty(),
+            Some(('%', rest)) => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+            Some(('_', rest)) => !s.is_empty() && matches(rest, &s[1..]),
+            Some(('\\', rest)) if !rest.is_empty() => s.first() == Some(&rest[0]) && matches(&rest[1..], &s[1..]),
+            Some((c, rest)) => s.first() == Some(c) && matches(rest, &s[1..]),
+        }
+    }
+
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    matches(&p, &s)
+}
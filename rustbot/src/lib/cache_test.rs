@@ -0,0 +1,44 @@
+use crate::cache::{like, Cached};
+use crate::testing::MockBot;
+use crate::types::Bot;
+
+#[test]
+fn test_like() {
+    assert!(like("irc:#foo", "irc:#foo"));
+    assert!(!like("irc:#foo", "irc:#Foo"));
+    assert!(like("irc:%", "irc:#foo"));
+    assert!(like("%", ""));
+    assert!(like("irc:#f_o", "irc:#foo"));
+    assert!(!like("irc:#f_o", "irc:#fo"));
+    assert!(like("%!%@example.com", "nick!user@example.com"));
+    assert!(!like("%!%@example.com", "nick!user@example.org"));
+    assert!(like("100\\%", "100%"));
+    assert!(!like("100\\%", "1000"));
+}
+
+#[test]
+fn test_cached_reloads_on_invalidate() {
+    let bot = MockBot::new().with_sqlite();
+    bot.sql()
+        .unwrap()
+        .batch_execute("CREATE TABLE t (v INT8 NOT NULL); INSERT INTO t VALUES (1);")
+        .unwrap();
+
+    let cached: Cached<i64> = Cached::new(&["t"]);
+    let load = |db: &mut dyn crate::sql::Connection| Ok(db.query_one("SELECT v FROM t", &[])?.get(0));
+
+    assert_eq!(*cached.get(&bot, load).unwrap(), 1);
+
+    bot.sql().unwrap().execute("UPDATE t SET v = 2", &[]).unwrap();
+    assert_eq!(*cached.get(&bot, load).unwrap(), 1);
+
+    bot.invalidate(Some("other"));
+    assert_eq!(*cached.get(&bot, load).unwrap(), 1);
+
+    bot.invalidate(Some("t"));
+    assert_eq!(*cached.get(&bot, load).unwrap(), 2);
+
+    bot.sql().unwrap().execute("UPDATE t SET v = 3", &[]).unwrap();
+    bot.invalidate(None);
+    assert_eq!(*cached.get(&bot, load).unwrap(), 3);
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::time::Duration;
 
 use crate::prelude::*;
@@ -9,7 +10,8 @@ use nom::{
 };
 use nom::{error::Error as NomError, Finish, IResult};
 
-fn _parse_duration(i: &str) -> IResult<&str, Duration> {
+// None if the duration is too long to represent.
+fn _parse_duration(i: &str) -> IResult<&str, Option<Duration>> {
     let (i, d) = opt(terminated(unumber, tag("d")))(i)?;
     let (i, h) = opt(terminated(unumber, tag("h")))(i)?;
     let (i, m) = opt(terminated(unumber, tag("m")))(i)?;
@@ -21,7 +23,12 @@ fn _parse_duration(i: &str) -> IResult<&str, Duration> {
     let m = m.unwrap_or(0);
     let s = s.unwrap_or(0);
 
-    Ok((i, Duration::from_secs(s + 60 * (m + 60 * (h + 24 * d)))))
+    let secs = d
+        .checked_mul(24)
+        .and_then(|n| n.checked_add(h)?.checked_mul(60))
+        .and_then(|n| n.checked_add(m)?.checked_mul(60))
+        .and_then(|n| n.checked_add(s));
+    Ok((i, secs.map(Duration::from_secs)))
 }
 
 fn unumber(i: &str) -> IResult<&str, u64> {
@@ -29,8 +36,42 @@ fn unumber(i: &str) -> IResult<&str, u64> {
 }
 
 pub fn parse_duration(s: &str) -> Result<Duration> {
-    _parse_duration(s)
-        .finish()
-        .map(|(_, d)| d)
-        .map_err(|NomError { input, .. }| UserError::new(format!("unexpected input at {input}")).into())
+    match _parse_duration(s).finish() {
+        Ok((_, Some(d))) => Ok(d),
+        Ok((_, None)) => Err(TooLong.into()),
+        Err(NomError { input, .. }) => bail_user!("unexpected input at {}", input),
+    }
+}
+
+// What parse_duration fails with for a duration too long to represent, so that callers can say
+// what it was too long for; unlike its other errors, it isn't a UserError.
+#[derive(Debug)]
+pub struct TooLong;
+
+impl fmt::Display for TooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "duration is too long")
+    }
+}
+
+impl std::error::Error for TooLong {}
+
+// The inverse of parse_duration, e.g. "1h30m".
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let mut s = String::new();
+    for (n, unit) in [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ] {
+        if n != 0 {
+            s += &format!("{n}{unit}");
+        }
+    }
+    if s.is_empty() {
+        s.push_str("0s");
+    }
+    s
 }
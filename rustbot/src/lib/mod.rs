@@ -10,25 +10,49 @@ extern crate toml;
 pub extern crate futures;
 pub extern crate tokio;
 
+// So that what #[derive(Args)] generates can refer to ::rustbot, here as well as in modules.
+extern crate self as rustbot;
+
+pub mod abi;
 pub mod args;
+pub mod cache;
 pub mod duration;
 pub mod error;
 pub mod format;
+pub mod perms;
+pub mod schedule;
+pub mod services;
 pub mod spans;
+pub mod sql;
+pub mod state;
+pub mod testing;
 pub mod types;
 
+#[cfg(test)]
+mod abi_test;
 #[cfg(test)]
 mod args_test;
 #[cfg(test)]
+mod cache_test;
+#[cfg(test)]
+mod schedule_test;
+#[cfg(test)]
+mod services_test;
+#[cfg(test)]
+mod state_test;
+#[cfg(test)]
 mod test;
 
 pub mod prelude {
-    pub use crate::args::{parse_args, Arg, Atom, Rest};
+    pub use crate::args::{describe_args, parse_args, Arg, Args, Atom, ChannelRef, Comma, KeyValue, Mention, Rest};
     pub use crate::bail_user;
     pub use crate::duration::*;
     pub use crate::error::*;
     pub use crate::format::*;
+    pub use crate::schedule::{Job, Schedule};
+    pub use crate::services::{ProvideService, UseService};
     pub use crate::spans::*;
+    pub use crate::state::KeepState;
     pub use crate::thread;
     pub use crate::types::*;
     pub use anyhow::Context as AnyhowContext; // would conflict with types::Context, but we just need the trait in scope here and don't care about names
@@ -0,0 +1,56 @@
+// Permissions are identified by name; the core ones also have a bit in `Perms`, which modules can
+// keep using for convenience. Modules can define their own names with `Meta::permission`, and
+// should prefix them with the module name, e.g. "ss13.admin".
+
+use crate::types::Perms;
+
+// Granting this gives every permission.
+pub const ALL: &str = "*";
+
+pub const CORE: &[(Perms, &str, &str)] = &[
+    (Perms::Admin, "admin", "manage permissions and other bot-wide settings"),
+    (
+        Perms::Raw,
+        "raw",
+        "send raw protocol messages, and join or leave channels",
+    ),
+    (Perms::Database, "database", "run arbitrary SQL"),
+    (Perms::Eval, "eval", "run arbitrary code"),
+    (Perms::Modules, "modules", "load, unload and configure modules"),
+];
+
+impl Perms {
+    pub fn names(self) -> Vec<&'static str> {
+        CORE.iter()
+            .filter(|(p, _, _)| self.contains(*p))
+            .map(|(_, name, _)| *name)
+            .collect()
+    }
+
+    pub fn from_name(name: &str) -> Option<Perms> {
+        CORE.iter().find(|(_, n, _)| *n == name).map(|(p, _, _)| *p)
+    }
+}
+
+// Matches `s` against a glob pattern, where `*` matches any sequence and `?` any one character.
+// Matching ignores ASCII case, since IRC nicks, hosts and channels do too.
+pub fn glob(pattern: &str, s: &str) -> bool {
+    fn matches(p: &[char], s: &[char]) -> bool {
+        match p.split_first() {
+            None => s.is_empty(),
+            Some(('*', rest)) => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+            Some(('?', rest)) => !s.is_empty() && matches(rest, &s[1..]),
+            Some((c, rest)) => s.first().is_some_and(|sc| sc.eq_ignore_ascii_case(c)) && matches(rest, &s[1..]),
+        }
+    }
+
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    matches(&p, &s)
+}
+
+// How specific a pattern is, for picking the closest match among several: the number of
+// characters that must match exactly.
+pub fn specificity(pattern: &str) -> usize {
+    pattern.chars().filter(|c| *c != '*' && *c != '?').count()
+}
@@ -0,0 +1,242 @@
+// Jobs the bot runs later. A module registers a handler for its jobs with `Meta::schedule`, under a
+// name prefixed with its own as for services, and schedules jobs for it with `Bot::schedule`; jobs
+// are kept in the database, so they survive restarts and reloads, and whatever the handler returns
+// is sent to the job's channel. Jobs due while their module isn't loaded wait until it is.
+
+use chrono::{Datelike, Duration as ChronoDuration, NaiveDate, NaiveDateTime, Timelike};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::prelude::*;
+
+// Repeating more often than this isn't useful to anyone in a chat channel.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    // once, at a Unix timestamp
+    At(i64),
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    // Parses "in <duration>", "every <duration>" or "cron <minute> <hour> <day> <month> <weekday>",
+    // with durations as for parse_duration, e.g. "1h30m", and cron times in UTC. "at <timestamp>"
+    // is also accepted, since that's how one-off jobs are stored.
+    pub fn parse(s: &str, now: i64) -> Result<Self> {
+        let s = s.trim();
+        let (kind, rest) = s.split_once(' ').unwrap_or((s, ""));
+        let rest = rest.trim();
+        match kind {
+            "in" => Ok(Schedule::At(parse_offset(rest, now)?.1)),
+            "at" => match rest.parse() {
+                Ok(t) => Ok(Schedule::At(t)),
+                Err(_) => bail_user!("invalid timestamp {:?}", rest),
+            },
+            "every" => {
+                let (every, _) = parse_offset(rest, now)?;
+                if every < MIN_INTERVAL {
+                    bail_user!("can't repeat more often than every {}", format_duration(MIN_INTERVAL));
+                }
+                Ok(Schedule::Every(every))
+            }
+            "cron" => Ok(Schedule::Cron(Cron::parse(rest)?)),
+            _ => bail_user!("expected \"in <duration>\", \"every <duration>\" or \"cron <expression>\""),
+        }
+    }
+
+    // When a job on this schedule is next due, strictly after `after`, if ever.
+    pub fn next(&self, after: i64) -> Option<i64> {
+        match self {
+            Schedule::At(t) if *t > after => Some(*t),
+            Schedule::At(_) => None,
+            Schedule::Every(d) => add(after, *d),
+            Schedule::Cron(c) => c.next(after),
+        }
+    }
+}
+
+// Parses a duration, with the timestamp that long after now, failing if that can't be represented.
+fn parse_offset(s: &str, now: i64) -> Result<(Duration, i64)> {
+    let d = match parse_duration(s) {
+        Err(e) if e.is::<TooLong>() => None,
+        d => Some(d?),
+    };
+    match d.and_then(|d| Some((d, add(now, d)?))) {
+        Some(offset) => Ok(offset),
+        None => bail_user!("that's too far in the future"),
+    }
+}
+
+fn add(t: i64, d: Duration) -> Option<i64> {
+    t.checked_add(i64::try_from(d.as_secs()).ok()?)
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::At(t) => write!(f, "at {t}"),
+            Schedule::Every(d) => write!(f, "every {}", format_duration(*d)),
+            Schedule::Cron(c) => write!(f, "cron {}", c.text),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    // assigned when the job is scheduled
+    pub id: i64,
+    // "<module>.<name>", as registered with Meta::schedule
+    pub handler: String,
+    // where what the handler returns is sent, as for Bot::send_message
+    pub config_id: String,
+    pub channel: String,
+    // for the handler to interpret, e.g. as JSON
+    pub payload: String,
+    pub schedule: Schedule,
+    // Unix timestamp
+    pub next_run: i64,
+}
+
+impl Job {
+    pub fn new(handler: &str, config_id: &str, channel: &str, payload: &str, schedule: Schedule) -> Result<Self> {
+        let next_run = match schedule.next(now()) {
+            Some(t) => t,
+            None => bail_user!("{} is never due", schedule),
+        };
+        Ok(Self {
+            id: 0,
+            handler: handler.to_string(),
+            config_id: config_id.to_string(),
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+            schedule,
+            next_run,
+        })
+    }
+}
+
+// The current Unix timestamp.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+// A cron expression: minute, hour, day of month, month and day of week, each "*", a number, a
+// range like "1-5", any of those with a step like "*/15", or a comma-separated list of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    text: String,
+    // bit n set if n matches
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // as in cron, if both days and weekdays are restricted, matching either is enough
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail_user!("cron expressions have five fields: minute, hour, day, month and weekday");
+        }
+
+        // Sunday is both 0 and 7
+        let mut weekdays = cron_field(fields[4], 0, 7)?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            text: fields.join(" "),
+            minutes: cron_field(fields[0], 0, 59)?,
+            hours: cron_field(fields[1], 0, 23)?,
+            days: cron_field(fields[2], 1, 31)?,
+            months: cron_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    // The first matching minute strictly after `after`, looking up to five years ahead, since an
+    // expression like "0 0 31 2 *" never matches.
+    pub fn next(&self, after: i64) -> Option<i64> {
+        let start = NaiveDateTime::from_timestamp_opt(after - after.rem_euclid(60) + 60, 0)?;
+        let limit = start + ChronoDuration::days(5 * 366);
+        let mut t = start;
+        while t < limit {
+            if !bit(self.months, t.month()) {
+                let (y, m) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = NaiveDate::from_ymd_opt(y, m, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(t) {
+                t = (t.date() + ChronoDuration::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if !bit(self.hours, t.hour()) {
+                t = t.date().and_hms_opt(t.hour(), 0, 0)? + ChronoDuration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += ChronoDuration::minutes(1);
+            } else {
+                return Some(t.timestamp());
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: NaiveDateTime) -> bool {
+        let day = bit(self.days, t.day());
+        let weekday = bit(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn bit(mask: u64, n: u32) -> bool {
+    mask & 1 << n != 0
+}
+
+fn cron_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let number = |s: &str| match s.parse::<u32>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(UserError::new(format!(
+            "invalid cron field {field:?}: expected {min} to {max}, got {s:?}"
+        ))),
+    };
+
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => bail_user!("invalid step {:?} in cron field {:?}", step, field),
+            },
+            None => (item, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            // like cron, "5/10" means from 5 onwards
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if from > to {
+            bail_user!("invalid range {:?} in cron field {:?}", range, field);
+        }
+        for n in (from..=to).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
@@ -0,0 +1,84 @@
+use chrono::NaiveDate;
+use std::time::Duration;
+
+use crate::prelude::*;
+use crate::schedule::Cron;
+
+// 2024-03-01 was a Friday.
+fn at(day: u32, hour: u32, minute: u32) -> i64 {
+    NaiveDate::from_ymd_opt(2024, 3, day)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+        .timestamp()
+}
+
+#[test]
+fn test_parse_schedule() {
+    let now = at(1, 12, 0);
+    assert_eq!(Schedule::parse("in 1h30m", now).unwrap(), Schedule::At(at(1, 13, 30)));
+    assert_eq!(
+        Schedule::parse("every 1d", now).unwrap(),
+        Schedule::Every(Duration::from_secs(86400))
+    );
+
+    // stored as text, and read back the same
+    for s in &["at 1709294400", "every 1h30m", "cron */15 9-17 * * 1-5"] {
+        assert_eq!(Schedule::parse(s, now).unwrap().to_string(), *s);
+    }
+
+    for (s, err) in &[
+        ("every 30s", "can't repeat more often than every 1m"),
+        ("in 1x", "unexpected input at 1x"),
+        ("in 200000000000000d", "that's too far in the future"),
+        ("in 999999999999999d", "that's too far in the future"),
+        ("every 200000000000000d", "that's too far in the future"),
+        (
+            "tomorrow",
+            "expected \"in <duration>\", \"every <duration>\" or \"cron <expression>\"",
+        ),
+        (
+            "cron * * * *",
+            "cron expressions have five fields: minute, hour, day, month and weekday",
+        ),
+        (
+            "cron 60 * * * *",
+            "invalid cron field \"60\": expected 0 to 59, got \"60\"",
+        ),
+        (
+            "cron * * * * 1-",
+            "invalid cron field \"1-\": expected 0 to 7, got \"\"",
+        ),
+        ("cron */0 * * * *", "invalid step \"0\" in cron field \"*/0\""),
+    ] {
+        let e = Schedule::parse(s, now).unwrap_err();
+        assert!(e.downcast_ref::<UserError>().is_some(), "{}", s);
+        assert_eq!(e.to_string(), *err);
+    }
+}
+
+#[test]
+fn test_next_run() {
+    let now = at(1, 12, 7);
+    assert_eq!(Schedule::At(at(1, 13, 0)).next(now), Some(at(1, 13, 0)));
+    assert_eq!(Schedule::At(at(1, 12, 0)).next(now), None);
+    assert_eq!(Schedule::Every(Duration::from_secs(600)).next(now), Some(at(1, 12, 17)));
+    assert_eq!(Schedule::Every(Duration::from_secs(600)).next(i64::MAX - 60), None);
+
+    let next = |expr: &str, after: i64| Cron::parse(expr).unwrap().next(after);
+    assert_eq!(next("*/15 * * * *", now), Some(at(1, 12, 15)));
+    // strictly after, even on a matching minute
+    assert_eq!(next("*/15 * * * *", at(1, 12, 15)), Some(at(1, 12, 30)));
+    assert_eq!(next("0 9 * * *", now), Some(at(2, 9, 0)));
+    // the next Monday
+    assert_eq!(next("30 8 * * 1", now), Some(at(4, 8, 30)));
+    // Sunday is 0 or 7
+    assert_eq!(next("0 0 * * 7", now), Some(at(3, 0, 0)));
+    // either the 5th or a Sunday
+    assert_eq!(next("0 0 5 * 0", at(3, 1, 0)), Some(at(5, 0, 0)));
+    assert_eq!(
+        next("0 0 1 4 *", now),
+        NaiveDate::from_ymd_opt(2024, 4, 1).map(|d| d.and_hms_opt(0, 0, 0).unwrap().timestamp())
+    );
+    assert_eq!(next("0 0 31 2 *", now), None);
+}
@@ -0,0 +1,65 @@
+// Services let modules call into each other. A module provides a service under a name, prefixed
+// with its own as for permissions, and others look it up each time they use it; it's only
+// available while the module providing it is loaded, and lookups fail once it's dropped.
+//
+// Lookups check the service's type as well as its name, so the types of the well-known services
+// are defined here, where both sides can use them without depending on each other's crates.
+
+use std::any::{type_name, Any};
+use std::collections::BTreeMap;
+
+use crate::prelude::*;
+use crate::spans::Span;
+
+// "dice.roll": evaluates a dice expression, e.g. "2d6+1".
+pub type DiceRoll = Box<dyn Fn(&str) -> Result<Vec<Span<'static>>> + Send + Sync>;
+
+// "time.timezone": looks up a timezone by name, ignoring case, returning its canonical name.
+pub type Timezone = Box<dyn Fn(&str) -> Result<String> + Send + Sync>;
+
+// "time.parse": parses a date and time, e.g. "2024-03-01 14:30", in the named timezone, returning
+// it as a Unix timestamp.
+pub type ParseTime = Box<dyn Fn(&str, &str) -> Result<i64> + Send + Sync>;
+
+// "ss13.topic": sends a topic query, e.g. "status=2", to a game server at "host:port", returning
+// the fields of the response.
+pub type TopicQuery = Box<dyn Fn(&str, &str) -> Result<BTreeMap<String, String>> + Send + Sync>;
+
+pub trait ProvideService {
+    fn provide<T: Any + Send + Sync>(&mut self, name: &str, service: T);
+}
+
+impl<M: Meta + ?Sized> ProvideService for M {
+    fn provide<T: Any + Send + Sync>(&mut self, name: &str, service: T) {
+        self.provide_service(name, Box::new(service));
+    }
+}
+
+pub trait UseService {
+    // Calls `f` with the named service, which must be a `T`.
+    fn service<T: Any + Send + Sync, R>(&self, name: &str, f: impl FnOnce(&T) -> Result<R>) -> Result<R>;
+}
+
+impl<B: Bot + ?Sized> UseService for B {
+    fn service<T: Any + Send + Sync, R>(&self, name: &str, f: impl FnOnce(&T) -> Result<R>) -> Result<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        self.call_service(name, &mut |service| {
+            let service = match service.downcast_ref::<T>() {
+                Some(s) => s,
+                None => bail!("service {} is not a {}", name, type_name::<T>()),
+            };
+            // call_service only calls this once, but it has to be FnMut to go through a dyn
+            result = Some((f.take().unwrap())(service));
+            Ok(())
+        })?;
+        result.unwrap()
+    }
+}
+
+pub fn unavailable(name: &str) -> Error {
+    UserError::new(format!(
+        "{name} isn't available right now; is the module providing it loaded?"
+    ))
+    .into()
+}
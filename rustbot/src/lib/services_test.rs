@@ -0,0 +1,32 @@
+use crate::prelude::*;
+use crate::services::{DiceRoll, Timezone};
+use crate::testing::MockBot;
+
+#[test]
+fn test_service_lookup() {
+    let bot = MockBot::new().provide(
+        "dice.roll",
+        Box::new(|expr: &str| Ok(spans!(format!("rolled {expr}")))) as DiceRoll,
+    );
+
+    let rolled = bot.service("dice.roll", |roll: &DiceRoll| roll("1d6")).unwrap();
+    assert_eq!(spans_to_raw_string(rolled), "rolled 1d6");
+
+    // errors from the service itself come straight through
+    let err = bot
+        .service("dice.roll", |_: &DiceRoll| -> Result<()> { bail_user!("bad roll") })
+        .unwrap_err();
+    assert_eq!(err.to_string(), "bad roll");
+
+    let err = bot.service("dice.roll", |_: &Timezone| Ok(())).unwrap_err();
+    assert!(err.to_string().starts_with("service dice.roll is not a "), "{}", err);
+    assert!(err.downcast_ref::<UserError>().is_none());
+
+    // as if the providing module had been dropped
+    let err = bot.service("time.timezone", |_: &Timezone| Ok(())).unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+    assert_eq!(
+        err.to_string(),
+        "time.timezone isn't available right now; is the module providing it loaded?"
+    );
+}
@@ -12,6 +12,26 @@ pub enum Span<'a> {
     DiscordEmoji(Cow<'a, str>, u64),
 }
 
+impl Span<'_> {
+    // Converts the span into one that doesn't borrow from anything, so it can be stored.
+    pub fn into_owned(self) -> Span<'static> {
+        match self {
+            Span::Text {
+                text,
+                format,
+                color,
+                bg,
+            } => Span::Text {
+                text: Cow::Owned(text.into_owned()),
+                format,
+                color,
+                bg,
+            },
+            Span::DiscordEmoji(name, id) => Span::DiscordEmoji(Cow::Owned(name.into_owned()), id),
+        }
+    }
+}
+
 pub fn spans_to_raw_string(spans: Vec<Span>) -> String {
     spans
         .iter()
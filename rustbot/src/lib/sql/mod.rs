@@ -0,0 +1,437 @@
+// A small database abstraction so the bot and its modules can run on either Postgres or SQLite.
+//
+// Queries use Postgres-style `$1` placeholders in both dialects; the SQLite backend rewrites them.
+// Values are converted to and from a handful of basic types, which both backends understand.
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Result;
+
+pub mod pg;
+pub mod sqlite;
+
+#[cfg(test)]
+mod test;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Json(serde_json::Value),
+    Timestamp(DateTime<Utc>),
+    Bytes(Vec<u8>),
+    // NUMERIC, kept as its exact decimal text
+    Numeric(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b:?}"),
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::Text(s) => write!(f, "{s:?}"),
+            Value::Json(j) => write!(f, "{j}"),
+            Value::Timestamp(t) => write!(f, "{}", t.to_rfc3339()),
+            Value::Bytes(b) => write!(f, "<{} bytes>", b.len()),
+            Value::Numeric(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    // The database's name for the column type, if known.
+    pub type_name: String,
+}
+
+pub struct Row {
+    columns: Arc<Vec<Column>>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(columns: Arc<Vec<Column>>, values: Vec<Value>) -> Self {
+        Self { columns, values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn try_get<I: RowIndex, T: FromSql>(&self, idx: I) -> Result<T> {
+        let i = match idx.index(&self.columns) {
+            Some(i) if i < self.values.len() => i,
+            _ => bail!("invalid column {}", idx),
+        };
+        T::from_sql(&self.values[i]).map_err(|e| anyhow!("error retrieving column {}: {}", idx, e))
+    }
+
+    // Like try_get, but panics on error; only for queries whose column types are known, e.g. in
+    // tests.
+    pub fn get<I: RowIndex, T: FromSql>(&self, idx: I) -> T {
+        self.try_get(idx)
+            .unwrap_or_else(|e| panic!("Row::get as {}: {}", std::any::type_name::<T>(), e))
+    }
+}
+
+pub trait RowIndex: fmt::Display + Copy {
+    fn index(&self, columns: &[Column]) -> Option<usize>;
+}
+
+impl RowIndex for usize {
+    fn index(&self, _: &[Column]) -> Option<usize> {
+        Some(*self)
+    }
+}
+
+impl RowIndex for &str {
+    fn index(&self, columns: &[Column]) -> Option<usize> {
+        columns.iter().position(|c| c.name == *self)
+    }
+}
+
+pub trait Connection: Send {
+    fn dialect(&self) -> Dialect;
+
+    // Runs a statement and returns the number of rows it changed.
+    fn execute(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<u64>;
+
+    fn query(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>>;
+
+    // Runs any number of statements separated by semicolons, with no parameters.
+    fn batch_execute(&mut self, query: &str) -> Result<()>;
+
+    // The columns the given statement would return, without running it.
+    fn columns(&mut self, query: &str) -> Result<Vec<Column>>;
+
+    // Checks the connection is still usable.
+    fn is_valid(&mut self) -> Result<()> {
+        self.query("SELECT 1", &[]).map(|_| ())
+    }
+
+    // Whether the connection is known to be unusable without asking the database, e.g. because
+    // the server closed it; checked whenever it's returned to the pool.
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn query_opt(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Option<Row>> {
+        let mut rows = self.query(query, params)?;
+        match rows.len() {
+            0 => Ok(None),
+            1 => Ok(rows.pop()),
+            n => bail!("query returned {} rows, expected at most one", n),
+        }
+    }
+
+    fn query_one(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Row> {
+        match self.query_opt(query, params)? {
+            Some(row) => Ok(row),
+            None => bail!("query returned no rows, expected one"),
+        }
+    }
+}
+
+pub type ConnectFn = dyn Fn() -> Result<Box<dyn Connection>> + Send + Sync;
+
+// Opens new connections for the pool, and checks existing ones are still alive before they're
+// handed out, so a database restart only fails the queries that were running at the time.
+pub struct Manager {
+    connect: Box<ConnectFn>,
+}
+
+#[derive(Debug)]
+pub struct ConnectError(anyhow::Error);
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+impl r2d2::ManageConnection for Manager {
+    type Connection = Box<dyn Connection>;
+    type Error = ConnectError;
+
+    fn connect(&self) -> std::result::Result<Self::Connection, Self::Error> {
+        (self.connect)().map_err(ConnectError)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> std::result::Result<(), Self::Error> {
+        conn.is_valid().map_err(ConnectError)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.is_closed()
+    }
+}
+
+pub type Pool = r2d2::Pool<Manager>;
+pub type PooledConnection = r2d2::PooledConnection<Manager>;
+
+pub fn pool<F>(max_size: u32, connect: F) -> Result<Pool>
+where
+    F: Fn() -> Result<Box<dyn Connection>> + Send + Sync + 'static,
+{
+    Ok(r2d2::Pool::builder()
+        .max_size(max_size)
+        .connection_timeout(Duration::from_secs(10))
+        .build(Manager {
+            connect: Box::new(connect),
+        })?)
+}
+
+// A pool holding a single connection that's never closed, for in-memory SQLite databases, which
+// only exist as long as their connection does.
+pub fn single_connection_pool<F>(connect: F) -> Result<Pool>
+where
+    F: Fn() -> Result<Box<dyn Connection>> + Send + Sync + 'static,
+{
+    Ok(r2d2::Pool::builder()
+        .max_size(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .test_on_check_out(false)
+        .build(Manager {
+            connect: Box::new(connect),
+        })?)
+}
+
+pub trait ToSql {
+    fn to_sql(&self) -> Value;
+}
+
+pub trait FromSql: Sized {
+    fn from_sql(v: &Value) -> Result<Self>;
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn to_sql(&self) -> Value {
+        (**self).to_sql()
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql(&self) -> Value {
+        match self {
+            Some(v) => v.to_sql(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToSql for bool {
+    fn to_sql(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+macro_rules! int_to_sql {
+    ($($t:ty)*) => {$(
+        impl ToSql for $t {
+            fn to_sql(&self) -> Value {
+                Value::Int(i64::from(*self))
+            }
+        }
+    )*};
+}
+int_to_sql!(i8 i16 i32 i64 u8 u16 u32);
+
+impl ToSql for f32 {
+    fn to_sql(&self) -> Value {
+        Value::Float(f64::from(*self))
+    }
+}
+
+impl ToSql for f64 {
+    fn to_sql(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl ToSql for str {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToSql for String {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl ToSql for Cow<'_, str> {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToSql for serde_json::Value {
+    fn to_sql(&self) -> Value {
+        Value::Json(self.clone())
+    }
+}
+
+impl ToSql for DateTime<Utc> {
+    fn to_sql(&self) -> Value {
+        Value::Timestamp(*self)
+    }
+}
+
+impl ToSql for [u8] {
+    fn to_sql(&self) -> Value {
+        Value::Bytes(self.to_vec())
+    }
+}
+
+impl ToSql for Vec<u8> {
+    fn to_sql(&self) -> Value {
+        Value::Bytes(self.clone())
+    }
+}
+
+impl<T: FromSql> FromSql for Option<T> {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Null => Ok(None),
+            v => Ok(Some(T::from_sql(v)?)),
+        }
+    }
+}
+
+impl FromSql for Value {
+    fn from_sql(v: &Value) -> Result<Self> {
+        Ok(v.clone())
+    }
+}
+
+impl FromSql for bool {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Bool(b) => Ok(*b),
+            // SQLite has no boolean type
+            Value::Int(i) => Ok(*i != 0),
+            v => bail!("expected a bool, got {}", v),
+        }
+    }
+}
+
+macro_rules! int_from_sql {
+    ($($t:ty)*) => {$(
+        impl FromSql for $t {
+            fn from_sql(v: &Value) -> Result<Self> {
+                match v {
+                    Value::Int(i) => Ok(<$t>::try_from(*i)?),
+                    Value::Numeric(n) => match n.parse() {
+                        Ok(i) => Ok(i),
+                        Err(_) => bail!("expected an integer, got {}", n),
+                    },
+                    v => bail!("expected an integer, got {}", v),
+                }
+            }
+        }
+    )*};
+}
+int_from_sql!(i8 i16 i32 i64 u8 u16 u32 u64);
+
+impl FromSql for f64 {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Float(f) => Ok(*f),
+            // SQLite stores floats with no fractional part as integers
+            Value::Int(i) => Ok(*i as f64),
+            Value::Numeric(n) => Ok(n.parse()?),
+            v => bail!("expected a float, got {}", v),
+        }
+    }
+}
+
+impl FromSql for f32 {
+    fn from_sql(v: &Value) -> Result<Self> {
+        f64::from_sql(v).map(|f| f as f32)
+    }
+}
+
+impl FromSql for String {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Text(s) | Value::Numeric(s) => Ok(s.clone()),
+            Value::Bytes(b) => Ok(String::from_utf8(b.clone())?),
+            v => bail!("expected a string, got {}", v),
+        }
+    }
+}
+
+impl FromSql for serde_json::Value {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Json(j) => Ok(j.clone()),
+            // SQLite has no JSON type, so JSON columns are stored as text
+            Value::Text(s) => Ok(serde_json::from_str(s)?),
+            v => bail!("expected JSON, got {}", v),
+        }
+    }
+}
+
+impl FromSql for DateTime<Utc> {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Timestamp(t) => Ok(*t),
+            // SQLite has no timestamp type; CURRENT_TIMESTAMP gives text like "YYYY-MM-DD HH:MM:SS"
+            Value::Text(s) => match DateTime::parse_from_rfc3339(s) {
+                Ok(t) => Ok(t.with_timezone(&Utc)),
+                Err(_) => Ok(Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")?)),
+            },
+            v => bail!("expected a timestamp, got {}", v),
+        }
+    }
+}
+
+impl FromSql for Vec<u8> {
+    fn from_sql(v: &Value) -> Result<Self> {
+        match v {
+            Value::Bytes(b) => Ok(b.clone()),
+            Value::Text(s) => Ok(s.clone().into_bytes()),
+            v => bail!("expected bytes, got {}", v),
+        }
+    }
+}
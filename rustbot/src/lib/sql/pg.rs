@@ -0,0 +1,331 @@
+use bytes::BytesMut;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use postgres::types::{self as pg, IsNull, Kind, Type};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::sync::Arc;
+
+use super::{Column, Connection, Dialect, Row, ToSql, Value};
+use crate::error::Result;
+
+pub struct PgConnection {
+    client: postgres::Client,
+}
+
+impl PgConnection {
+    pub fn connect(conn_str: &str) -> Result<Self> {
+        Ok(Self {
+            client: postgres::Client::connect(conn_str, postgres::NoTls)?,
+        })
+    }
+
+    pub fn client(&mut self) -> &mut postgres::Client {
+        &mut self.client
+    }
+}
+
+impl Connection for PgConnection {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn execute(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        let params = to_params(params);
+        Ok(self.client.execute(query, &param_refs(&params))?)
+    }
+
+    fn query(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        let params = to_params(params);
+        let stmt = self.client.prepare(query)?;
+        let columns = Arc::new(columns(&stmt));
+
+        self.client
+            .query(&stmt, &param_refs(&params))?
+            .iter()
+            .map(|row| {
+                let values = (0..row.len())
+                    .map(|i| Ok(row.try_get::<_, PgValue>(i)?.0))
+                    .collect::<Result<_>>()?;
+                Ok(Row::new(Arc::clone(&columns), values))
+            })
+            .collect()
+    }
+
+    fn batch_execute(&mut self, query: &str) -> Result<()> {
+        Ok(self.client.batch_execute(query)?)
+    }
+
+    fn columns(&mut self, query: &str) -> Result<Vec<Column>> {
+        Ok(columns(&self.client.prepare(query)?))
+    }
+
+    fn is_valid(&mut self) -> Result<()> {
+        self.client.simple_query("")?;
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.client.is_closed()
+    }
+}
+
+fn columns(stmt: &postgres::Statement) -> Vec<Column> {
+    stmt.columns()
+        .iter()
+        .map(|c| Column {
+            name: c.name().to_string(),
+            type_name: c.type_().name().to_string(),
+        })
+        .collect()
+}
+
+fn to_params(params: &[&dyn ToSql]) -> Vec<PgValue> {
+    params.iter().map(|p| PgValue(p.to_sql())).collect()
+}
+
+fn param_refs(params: &[PgValue]) -> Vec<&(dyn pg::ToSql + Sync)> {
+    params.iter().map(|p| p as &(dyn pg::ToSql + Sync)).collect()
+}
+
+// Values are converted to whatever type Postgres expects for each parameter or column, so that
+// e.g. an integer can be passed for an INT4 parameter without the caller having to know that.
+#[derive(Debug)]
+struct PgValue(Value);
+
+fn is_text(ty: &Type) -> bool {
+    <String as pg::FromSql>::accepts(ty)
+}
+
+impl pg::ToSql for PgValue {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> std::result::Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match (&self.0, ty) {
+            (Value::Null, _) => Ok(IsNull::Yes),
+            (Value::Bool(b), &Type::BOOL) => pg::ToSql::to_sql(b, ty, out),
+            (Value::Int(i), _) => match *ty {
+                Type::CHAR => pg::ToSql::to_sql(&i8::try_from(*i)?, ty, out),
+                Type::INT2 => pg::ToSql::to_sql(&i16::try_from(*i)?, ty, out),
+                Type::INT4 => pg::ToSql::to_sql(&i32::try_from(*i)?, ty, out),
+                Type::INT8 => pg::ToSql::to_sql(i, ty, out),
+                Type::OID => pg::ToSql::to_sql(&u32::try_from(*i)?, ty, out),
+                Type::FLOAT4 => pg::ToSql::to_sql(&(*i as f32), ty, out),
+                Type::FLOAT8 => pg::ToSql::to_sql(&(*i as f64), ty, out),
+                Type::NUMERIC => write_numeric(&i.to_string(), out),
+                _ => mismatch(&self.0, ty),
+            },
+            (Value::Float(f), _) => match *ty {
+                Type::FLOAT4 => pg::ToSql::to_sql(&(*f as f32), ty, out),
+                Type::FLOAT8 => pg::ToSql::to_sql(f, ty, out),
+                Type::NUMERIC if f.is_nan() => write_numeric("NaN", out),
+                Type::NUMERIC if f.is_finite() => write_numeric(&f.to_string(), out),
+                _ => mismatch(&self.0, ty),
+            },
+            (Value::Text(s), _) => match (ty.kind(), ty) {
+                (Kind::Enum(_), _) => {
+                    out.extend_from_slice(s.as_bytes());
+                    Ok(IsNull::No)
+                }
+                (_, &Type::JSON) | (_, &Type::JSONB) => {
+                    pg::ToSql::to_sql(&serde_json::from_str::<serde_json::Value>(s)?, ty, out)
+                }
+                (_, &Type::NUMERIC) => write_numeric(s, out),
+                _ if is_text(ty) => pg::ToSql::to_sql(s, ty, out),
+                _ => mismatch(&self.0, ty),
+            },
+            (Value::Json(j), _) => match *ty {
+                Type::JSON | Type::JSONB => pg::ToSql::to_sql(j, ty, out),
+                _ if is_text(ty) => pg::ToSql::to_sql(&j.to_string(), ty, out),
+                _ => mismatch(&self.0, ty),
+            },
+            (Value::Timestamp(t), _) => match *ty {
+                Type::TIMESTAMPTZ => pg::ToSql::to_sql(t, ty, out),
+                // a timestamp without a time zone is taken to be in UTC
+                Type::TIMESTAMP => pg::ToSql::to_sql(&t.naive_utc(), ty, out),
+                _ => mismatch(&self.0, ty),
+            },
+            (Value::Bytes(b), &Type::BYTEA) => pg::ToSql::to_sql(b, ty, out),
+            (Value::Numeric(n), _) => match *ty {
+                Type::NUMERIC => write_numeric(n, out),
+                Type::FLOAT4 => pg::ToSql::to_sql(&n.parse::<f32>()?, ty, out),
+                Type::FLOAT8 => pg::ToSql::to_sql(&n.parse::<f64>()?, ty, out),
+                _ if is_text(ty) => pg::ToSql::to_sql(n, ty, out),
+                _ => mismatch(&self.0, ty),
+            },
+            (v, ty) => mismatch(v, ty),
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <PgValue as pg::FromSql>::accepts(ty)
+    }
+
+    pg::to_sql_checked!();
+}
+
+fn mismatch(v: &Value, ty: &Type) -> std::result::Result<IsNull, Box<dyn Error + Sync + Send>> {
+    Err(format!("can't pass {} as {}", v, ty.name()).into())
+}
+
+impl<'a> pg::FromSql<'a> for PgValue {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> std::result::Result<Self, Box<dyn Error + Sync + Send>> {
+        let v = match *ty {
+            Type::BOOL => Value::Bool(<bool as pg::FromSql>::from_sql(ty, raw)?),
+            Type::CHAR => Value::Int(<i8 as pg::FromSql>::from_sql(ty, raw)?.into()),
+            Type::INT2 => Value::Int(<i16 as pg::FromSql>::from_sql(ty, raw)?.into()),
+            Type::INT4 => Value::Int(<i32 as pg::FromSql>::from_sql(ty, raw)?.into()),
+            Type::INT8 => Value::Int(<i64 as pg::FromSql>::from_sql(ty, raw)?),
+            Type::OID => Value::Int(<u32 as pg::FromSql>::from_sql(ty, raw)?.into()),
+            Type::FLOAT4 => Value::Float(<f32 as pg::FromSql>::from_sql(ty, raw)?.into()),
+            Type::FLOAT8 => Value::Float(<f64 as pg::FromSql>::from_sql(ty, raw)?),
+            Type::JSON | Type::JSONB => Value::Json(<serde_json::Value as pg::FromSql>::from_sql(ty, raw)?),
+            Type::TIMESTAMPTZ => Value::Timestamp(<DateTime<Utc> as pg::FromSql>::from_sql(ty, raw)?),
+            Type::TIMESTAMP => {
+                Value::Timestamp(Utc.from_utc_datetime(&<NaiveDateTime as pg::FromSql>::from_sql(ty, raw)?))
+            }
+            Type::BYTEA => Value::Bytes(<Vec<u8> as pg::FromSql>::from_sql(ty, raw)?),
+            Type::NUMERIC => Value::Numeric(read_numeric(raw)?),
+            _ if is_text(ty) => Value::Text(<String as pg::FromSql>::from_sql(ty, raw)?),
+            _ => match ty.kind() {
+                // enums are sent as their label
+                Kind::Enum(_) => Value::Text(std::str::from_utf8(raw)?.to_string()),
+                _ => return Err(format!("unsupported column type {}", ty.name()).into()),
+            },
+        };
+        Ok(PgValue(v))
+    }
+
+    fn from_sql_null(_ty: &Type) -> std::result::Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(PgValue(Value::Null))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        match *ty {
+            Type::BOOL
+            | Type::CHAR
+            | Type::INT2
+            | Type::INT4
+            | Type::INT8
+            | Type::OID
+            | Type::FLOAT4
+            | Type::FLOAT8
+            | Type::JSON
+            | Type::JSONB
+            | Type::TIMESTAMP
+            | Type::TIMESTAMPTZ
+            | Type::BYTEA
+            | Type::NUMERIC => true,
+            _ => is_text(ty) || matches!(ty.kind(), Kind::Enum(_)),
+        }
+    }
+}
+
+// NUMERIC's binary format: the number of base-10000 digits, the weight of the first digit, the
+// sign, and the number of decimal places to show, all as 16-bit integers, then the digits.
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+
+pub(super) fn read_numeric(raw: &[u8]) -> std::result::Result<String, Box<dyn Error + Sync + Send>> {
+    let word = |i: usize| match raw.get(2 * i..2 * i + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => Err("invalid numeric value"),
+    };
+    let ndigits = word(0)? as usize;
+    let weight = word(1)? as i16 as isize;
+    let sign = word(2)?;
+    let dscale = word(3)? as usize;
+    let digits = (0..ndigits)
+        .map(|i| word(4 + i))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let digit = |i: isize| {
+        if i >= 0 {
+            digits.get(i as usize).copied().unwrap_or(0)
+        } else {
+            0
+        }
+    };
+
+    let mut s = String::new();
+    match sign {
+        NUMERIC_NAN => return Ok("NaN".to_string()),
+        NUMERIC_NEG => s.push('-'),
+        _ => {}
+    }
+    if weight < 0 {
+        s.push('0');
+    }
+    for i in 0..=weight {
+        if i == 0 {
+            s += &digit(i).to_string();
+        } else {
+            s += &format!("{:04}", digit(i));
+        }
+    }
+    if dscale > 0 {
+        let mut frac = String::new();
+        let mut i = weight + 1;
+        while frac.len() < dscale {
+            frac += &format!("{:04}", digit(i));
+            i += 1;
+        }
+        frac.truncate(dscale);
+        s.push('.');
+        s += &frac;
+    }
+    Ok(s)
+}
+
+pub(super) fn write_numeric(s: &str, out: &mut BytesMut) -> std::result::Result<IsNull, Box<dyn Error + Sync + Send>> {
+    let invalid = || format!("invalid numeric value {s:?}");
+    let (sign, int, frac) = if s == "NaN" {
+        (NUMERIC_NAN, "", "")
+    } else {
+        let (sign, abs) = match s.strip_prefix('-') {
+            Some(abs) => (NUMERIC_NEG, abs),
+            None => (NUMERIC_POS, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int, frac) = abs.split_once('.').unwrap_or((abs, ""));
+        if (int.is_empty() && frac.is_empty()) || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid().into());
+        }
+        (sign, int, frac)
+    };
+
+    // pad both parts out to whole base-10000 digits
+    let int = format!("{}{}", "0".repeat((4 - int.len() % 4) % 4), int);
+    let frac = format!("{}{}", frac, "0".repeat((4 - frac.len() % 4) % 4));
+    let mut digits: Vec<u16> = int
+        .as_bytes()
+        .chunks(4)
+        .chain(frac.as_bytes().chunks(4))
+        .map(|c| std::str::from_utf8(c).unwrap().parse().unwrap())
+        .collect();
+    let mut weight = (int.len() / 4) as isize - 1;
+
+    while digits.first() == Some(&0) {
+        digits.remove(0);
+        weight -= 1;
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    let sign = if digits.is_empty() && sign == NUMERIC_NEG {
+        NUMERIC_POS
+    } else {
+        sign
+    };
+    if digits.is_empty() {
+        weight = 0;
+    }
+
+    let dscale = s.split_once('.').map_or(0, |(_, f)| f.len());
+    let header = [
+        digits.len() as u16,
+        i16::try_from(weight).map_err(|_| invalid())? as u16,
+        sign,
+        u16::try_from(dscale).map_err(|_| invalid())?,
+    ];
+    for w in header.iter().chain(&digits) {
+        out.extend_from_slice(&w.to_be_bytes());
+    }
+    Ok(IsNull::No)
+}
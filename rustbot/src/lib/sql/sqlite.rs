@@ -0,0 +1,126 @@
+use rusqlite::types::{Value as SqliteValue, ValueRef};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Column, Connection, Dialect, Row, ToSql, Value};
+use crate::error::Result;
+
+pub struct SqliteConnection {
+    conn: rusqlite::Connection,
+}
+
+impl SqliteConnection {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Self::setup(rusqlite::Connection::open(path)?)?;
+        // let readers carry on while something else is writing
+        conn.conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+        Ok(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::setup(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn setup(conn: rusqlite::Connection) -> Result<Self> {
+        // match Postgres' behaviour, which all our queries are written for
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA case_sensitive_like = ON;")?;
+        // other pooled connections may hold the write lock for a moment
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Ok(Self { conn })
+    }
+}
+
+impl Connection for SqliteConnection {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn execute(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        let mut stmt = self.conn.prepare(&translate(query))?;
+        let n = stmt.execute(rusqlite::params_from_iter(to_params(params)))?;
+        Ok(n as u64)
+    }
+
+    fn query(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        let mut stmt = self.conn.prepare(&translate(query))?;
+        let columns = Arc::new(columns(&stmt));
+
+        let mut rows = stmt.query(rusqlite::params_from_iter(to_params(params)))?;
+        let mut result = vec![];
+        while let Some(row) = rows.next()? {
+            let values = (0..columns.len())
+                .map(|i| {
+                    Ok(match row.get_ref(i)? {
+                        ValueRef::Null => Value::Null,
+                        ValueRef::Integer(i) => Value::Int(i),
+                        ValueRef::Real(f) => Value::Float(f),
+                        ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+                        ValueRef::Blob(b) => Value::Bytes(b.to_vec()),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            result.push(Row::new(Arc::clone(&columns), values));
+        }
+
+        Ok(result)
+    }
+
+    fn batch_execute(&mut self, query: &str) -> Result<()> {
+        Ok(self.conn.execute_batch(query)?)
+    }
+
+    fn columns(&mut self, query: &str) -> Result<Vec<Column>> {
+        Ok(columns(&self.conn.prepare(&translate(query))?))
+    }
+}
+
+fn columns(stmt: &rusqlite::Statement) -> Vec<Column> {
+    stmt.columns()
+        .iter()
+        .map(|c| Column {
+            name: c.name().to_string(),
+            type_name: c.decl_type().unwrap_or("").to_string(),
+        })
+        .collect()
+}
+
+fn to_params(params: &[&dyn ToSql]) -> Vec<SqliteValue> {
+    params
+        .iter()
+        .map(|p| match p.to_sql() {
+            Value::Null => SqliteValue::Null,
+            Value::Bool(b) => SqliteValue::Integer(b.into()),
+            Value::Int(i) => SqliteValue::Integer(i),
+            Value::Float(f) => SqliteValue::Real(f),
+            Value::Text(s) => SqliteValue::Text(s),
+            Value::Json(j) => SqliteValue::Text(j.to_string()),
+            // the format of CURRENT_TIMESTAMP, which SQLite's date functions understand
+            Value::Timestamp(t) => SqliteValue::Text(t.format("%Y-%m-%d %H:%M:%S%.f").to_string()),
+            Value::Bytes(b) => SqliteValue::Blob(b),
+            Value::Numeric(n) => SqliteValue::Text(n),
+        })
+        .collect()
+}
+
+// Rewrites Postgres-style `$1` placeholders to SQLite's `?1`, leaving string literals and quoted
+// identifiers alone.
+pub fn translate(query: &str) -> String {
+    let mut out = String::with_capacity(query.len());
+    let mut quote = None;
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '$') if chars.peek().is_some_and(char::is_ascii_digit) => {
+                out.push('?');
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+
+    out
+}
@@ -0,0 +1,240 @@
+use super::sqlite::{translate, SqliteConnection};
+use super::*;
+
+#[test]
+fn test_translate() {
+    assert_eq!(
+        translate("SELECT a FROM t WHERE b = $1 AND c LIKE $12"),
+        "SELECT a FROM t WHERE b = ?1 AND c LIKE ?12"
+    );
+    assert_eq!(translate("SELECT '$1', \"$2\", $3"), "SELECT '$1', \"$2\", ?3");
+    assert_eq!(translate("SELECT 'it''s $1', $2"), "SELECT 'it''s $1', ?2");
+    assert_eq!(translate("SELECT $ FROM t"), "SELECT $ FROM t");
+}
+
+#[test]
+fn test_sqlite_round_trip() {
+    let mut db = SqliteConnection::open_in_memory().unwrap();
+    assert_eq!(db.dialect(), Dialect::Sqlite);
+
+    db.batch_execute(
+        "CREATE TABLE t (id INT4 PRIMARY KEY, name TEXT NOT NULL, enabled BOOL NOT NULL, score FLOAT8, data TEXT)",
+    )
+    .unwrap();
+
+    let data = serde_json::json!({"a": [1, 2]});
+    let n = db
+        .execute(
+            "INSERT INTO t (id, name, enabled, score, data) VALUES ($1, $2, $3, $4, $5), (2, 'two', false, NULL, NULL)",
+            &[&1i32, &"one", &true, &Some(2.0), &data],
+        )
+        .unwrap();
+    assert_eq!(n, 2);
+
+    let rows = db
+        .query("SELECT id, name, enabled, score, data FROM t ORDER BY id", &[])
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+
+    assert_eq!(rows[0].get::<_, i32>(0), 1);
+    assert_eq!(rows[0].get::<_, String>("name"), "one");
+    assert!(rows[0].get::<_, bool>(2));
+    assert_eq!(rows[0].get::<_, Option<f64>>(3), Some(2.0));
+    assert_eq!(rows[0].get::<_, serde_json::Value>(4), data);
+
+    assert!(!rows[1].get::<_, bool>("enabled"));
+    assert_eq!(rows[1].get::<_, Option<f64>>("score"), None);
+    assert!(rows[1].try_get::<_, String>("data").is_err());
+    assert!(rows[1].try_get::<_, String>("missing").is_err());
+
+    assert_eq!(rows[0].columns()[1].name, "name");
+    assert_eq!(rows[0].columns()[1].type_name, "TEXT");
+
+    let row = db.query_opt("SELECT name FROM t WHERE id = $1", &[&2i64]).unwrap();
+    assert_eq!(row.unwrap().get::<_, String>(0), "two");
+    assert!(db
+        .query_opt("SELECT name FROM t WHERE id = $1", &[&3i64])
+        .unwrap()
+        .is_none());
+    assert!(db.query_opt("SELECT name FROM t", &[]).is_err());
+}
+
+#[test]
+fn test_sqlite_timestamps_and_bytes() {
+    use chrono::{TimeZone, Utc};
+
+    let mut db = SqliteConnection::open_in_memory().unwrap();
+    db.batch_execute("CREATE TABLE t (at TIMESTAMP NOT NULL, data BYTEA NOT NULL, n NUMERIC)")
+        .unwrap();
+
+    let at = Utc.with_ymd_and_hms(2026, 10, 18, 12, 30, 0).unwrap();
+    // not valid UTF-8
+    let data: Vec<u8> = vec![0, 159, 146, 150];
+    db.execute("INSERT INTO t VALUES ($1, $2, 1.5)", &[&at, &data]).unwrap();
+
+    let row = db
+        .query_one("SELECT at, data, n, CURRENT_TIMESTAMP FROM t", &[])
+        .unwrap();
+    assert_eq!(row.try_get::<_, chrono::DateTime<Utc>>(0).unwrap(), at);
+    assert_eq!(row.try_get::<_, Vec<u8>>(1).unwrap(), data);
+    assert_eq!(row.try_get::<_, f64>(2).unwrap(), 1.5);
+    assert!(row.try_get::<_, chrono::DateTime<Utc>>(3).is_ok());
+    assert!(row.try_get::<_, String>(1).is_err());
+}
+
+#[test]
+fn test_pg_numeric() {
+    use super::pg::{read_numeric, write_numeric};
+
+    for (n, want) in [
+        ("0", "0"),
+        ("-0", "0"),
+        ("+5", "5"),
+        ("10000", "10000"),
+        ("-12345678.9", "-12345678.9"),
+        ("0.00001", "0.00001"),
+        (".5", "0.5"),
+        ("1.10", "1.10"),
+        ("NaN", "NaN"),
+    ] {
+        let mut raw = bytes::BytesMut::new();
+        write_numeric(n, &mut raw).unwrap();
+        assert_eq!(read_numeric(&raw).unwrap(), want);
+    }
+
+    // 1234.5 as Postgres sends it: two digits, weight 0, positive, one decimal place
+    let raw = [0, 2, 0, 0, 0, 0, 0, 1, 0x04, 0xd2, 0x13, 0x88];
+    assert_eq!(read_numeric(&raw).unwrap(), "1234.5");
+
+    for bad in ["", ".", "1e5", "1.2.3", "abc"] {
+        assert!(write_numeric(bad, &mut bytes::BytesMut::new()).is_err());
+    }
+}
+
+#[test]
+fn test_sqlite_like_is_case_sensitive() {
+    let mut db = SqliteConnection::open_in_memory().unwrap();
+    let row = db.query_one("SELECT 'abc' LIKE 'ABC', 'abc' LIKE 'a%'", &[]).unwrap();
+    assert!(!row.get::<_, bool>(0));
+    assert!(row.get::<_, bool>(1));
+}
+
+// A connection that starts failing once `down` is set, like a Postgres connection after a restart.
+struct Flaky {
+    conn: SqliteConnection,
+    down: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Flaky {
+    fn check(&self) -> Result<()> {
+        if self.is_closed() {
+            anyhow::bail!("connection closed");
+        }
+        Ok(())
+    }
+}
+
+impl Connection for Flaky {
+    fn dialect(&self) -> Dialect {
+        self.conn.dialect()
+    }
+
+    fn execute(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<u64> {
+        self.check()?;
+        self.conn.execute(query, params)
+    }
+
+    fn query(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>> {
+        self.check()?;
+        self.conn.query(query, params)
+    }
+
+    fn batch_execute(&mut self, query: &str) -> Result<()> {
+        self.check()?;
+        self.conn.batch_execute(query)
+    }
+
+    fn columns(&mut self, query: &str) -> Result<Vec<Column>> {
+        self.check()?;
+        self.conn.columns(query)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.down.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[test]
+fn test_pool_replaces_broken_connections() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let downs: Arc<parking_lot::Mutex<Vec<Arc<AtomicBool>>>> = Default::default();
+
+    let pool = {
+        let opened = Arc::clone(&opened);
+        let downs = Arc::clone(&downs);
+        pool(1, move || {
+            opened.fetch_add(1, Ordering::SeqCst);
+            let down = Arc::new(AtomicBool::new(false));
+            downs.lock().push(Arc::clone(&down));
+            Ok(Box::new(Flaky {
+                conn: SqliteConnection::open_in_memory()?,
+                down,
+            }))
+        })
+        .unwrap()
+    };
+
+    let one: i64 = pool.get().unwrap().query_one("SELECT 1", &[]).unwrap().get(0);
+    assert_eq!(one, 1);
+    assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+    // the server goes away; the next checkout should notice and reconnect
+    for down in downs.lock().iter() {
+        down.store(true, Ordering::SeqCst);
+    }
+
+    let one: i64 = pool.get().unwrap().query_one("SELECT 1", &[]).unwrap().get(0);
+    assert_eq!(one, 1);
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_pool_drops_closed_connections() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let opened = Arc::new(AtomicUsize::new(0));
+    let down = Arc::new(AtomicBool::new(false));
+
+    // without checking connections on checkout, so only has_broken can catch a closed one
+    let pool = {
+        let opened = Arc::clone(&opened);
+        let down = Arc::clone(&down);
+        r2d2::Pool::builder()
+            .max_size(1)
+            .test_on_check_out(false)
+            .build(Manager {
+                connect: Box::new(move || {
+                    opened.fetch_add(1, Ordering::SeqCst);
+                    Ok(Box::new(Flaky {
+                        conn: SqliteConnection::open_in_memory()?,
+                        down: Arc::clone(&down),
+                    }))
+                }),
+            })
+            .unwrap()
+    };
+
+    let conn = pool.get().unwrap();
+    assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+    // the server closes the connection while it's in use; it shouldn't go back in the pool
+    down.store(true, Ordering::SeqCst);
+    drop(conn);
+    down.store(false, Ordering::SeqCst);
+
+    let one: i64 = pool.get().unwrap().query_one("SELECT 1", &[]).unwrap().get(0);
+    assert_eq!(one, 1);
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,38 @@
+// State kept across reloads. When a module is reloaded, the new build is loaded alongside the old
+// one and takes over its commands and handlers at once, and then the old one is shut down; state
+// the old one kept is handed to the new one as JSON, so it doesn't matter if its types changed, as
+// long as they still deserialize.
+//
+// The state is saved at the moment of the swap, so anything the old module's threads change after
+// that, before they're stopped, is lost.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::prelude::*;
+
+pub trait KeepState {
+    // Saves the value `f` returns when the module is reloaded.
+    fn keep<T: Serialize, F: Fn() -> T + Send + Sync + 'static>(&mut self, f: F);
+
+    // The value the module's previous instance kept, if it was reloaded and the value is still a
+    // `T`.
+    fn restore<T: DeserializeOwned>(&mut self) -> Option<T>;
+}
+
+impl<M: Meta + ?Sized> KeepState for M {
+    fn keep<T: Serialize, F: Fn() -> T + Send + Sync + 'static>(&mut self, f: F) {
+        self.save_state(Box::new(move || Ok(serde_json::to_vec(&f())?)));
+    }
+
+    fn restore<T: DeserializeOwned>(&mut self) -> Option<T> {
+        let state = self.restored_state()?;
+        match serde_json::from_slice(&state) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("discarding state from before reload: {}", e);
+                None
+            }
+        }
+    }
+}
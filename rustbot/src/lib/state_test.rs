@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::prelude::*;
+use crate::testing::{MockContext, TestMeta};
+
+fn get_meta(meta: &mut dyn Meta) {
+    let count = Arc::new(AtomicU64::new(meta.restore().unwrap_or(0)));
+
+    let c = Arc::clone(&count);
+    meta.keep(move || c.load(Ordering::SeqCst));
+
+    meta.cmd(
+        "count",
+        Command::new(move |ctx, _| ctx.say(&(count.fetch_add(1, Ordering::SeqCst) + 1).to_string())),
+    );
+}
+
+#[test]
+fn test_state_survives_reload() {
+    let ctx = MockContext::new();
+    let mut meta = TestMeta::load(get_meta);
+    meta.call(&ctx, "count", "").unwrap();
+    meta.call(&ctx, "count", "").unwrap();
+
+    let meta = meta.reload(get_meta).unwrap();
+    meta.call(&ctx, "count", "").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["1", "2", "3"]);
+
+    // state that no longer fits is dropped, rather than failing the load
+    let mut meta = TestMeta::default();
+    meta.restored_state = Some(b"\"not a number\"".to_vec());
+    assert_eq!(meta.restore::<u64>(), None);
+    get_meta(&mut meta);
+    meta.call(&ctx, "count", "").unwrap();
+    assert_eq!(ctx.take_replies(), vec!["1"]);
+}
@@ -28,4 +28,28 @@ fn test_parse_duration() {
     for case in error_cases {
         assert_eq!(duration::parse_duration(case.0).unwrap_err().to_string(), case.1);
     }
+
+    for s in &["213503982334601d7h1m", "18446744073709551615d"] {
+        let e = duration::parse_duration(s).unwrap_err();
+        assert!(e.downcast_ref::<duration::TooLong>().is_some(), "{}", s);
+    }
+    assert_eq!(
+        duration::parse_duration("213503982334601d7h").unwrap().as_secs(),
+        213_503_982_334_601 * 86400 + 7 * 3600
+    );
+}
+
+#[test]
+fn test_format_duration() {
+    for (secs, s) in &[
+        (0, "0s"),
+        (59, "59s"),
+        (3600, "1h"),
+        (5400, "1h30m"),
+        (90061, "1d1h1m1s"),
+    ] {
+        let d = std::time::Duration::from_secs(*secs);
+        assert_eq!(duration::format_duration(d), *s);
+        assert_eq!(duration::parse_duration(s).unwrap(), d);
+    }
 }
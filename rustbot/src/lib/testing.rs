@@ -0,0 +1,577 @@
+// Test implementations of the bot-facing traits, so modules can run their commands and handlers
+// end-to-end in unit tests without a network connection.
+
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+
+use crate::args::{Arg, ChannelRef, Mention};
+use crate::cache::Generations;
+use crate::error::Result;
+use crate::perms::ALL;
+use crate::schedule::Job;
+use crate::spans::Span;
+use crate::sql::{self, sqlite::SqliteConnection};
+use crate::types::*;
+
+// Converts a message into one that doesn't borrow from anything, so it can be stored.
+pub fn to_owned_message(m: Message) -> Message<'static> {
+    fn owned(s: Cow<str>) -> Cow<'static, str> {
+        Cow::Owned(s.into_owned())
+    }
+
+    match m {
+        Message::Simple(s) => Message::Simple(s),
+        Message::Code(s) => Message::Code(s),
+        Message::Spans(s) => Message::Spans(s.into_iter().map(Span::into_owned).collect()),
+        Message::Prefixed(p, s) => Message::Prefixed(
+            p.into_iter().map(Span::into_owned).collect(),
+            s.into_iter().map(Span::into_owned).collect(),
+        ),
+        Message::List { prefix, sep, items } => Message::List {
+            prefix: owned(prefix),
+            sep: owned(sep),
+            items: items.into_iter().map(owned).collect(),
+        },
+    }
+}
+
+// Renders a message as plain text with all formatting removed, for easy comparisons.
+pub fn message_text(m: &Message) -> String {
+    m.to_text()
+}
+
+// Runs a command, or the subcommand its arguments name, as the bot would if it has no cmdperm
+// overrides: doing nothing unless the source has the permissions for all of them.
+pub fn call(ctx: &dyn Context, name: &str, cmd: &Command, args: &str) -> Result<()> {
+    match cmd.resolve_permitted(name, args, |_, c| c.permitted(ctx))? {
+        Some((_, leaf, args)) => leaf.run(ctx, args),
+        None => Ok(()),
+    }
+}
+
+#[derive(Clone)]
+pub enum Sent {
+    Message {
+        config: String,
+        target: String,
+        message: Message<'static>,
+    },
+    IrcPrivmsg {
+        config: String,
+        channel: String,
+        message: String,
+    },
+    IrcRaw {
+        config: String,
+        line: String,
+    },
+    DiscordMessage {
+        config: String,
+        guild: String,
+        channel: String,
+        message: String,
+        process: bool,
+    },
+}
+
+// A Bot that records everything sent through it.
+#[derive(Default)]
+pub struct MockBot {
+    db: Option<sql::Pool>,
+    generations: Generations,
+    sent: Mutex<Vec<Sent>>,
+    services: BTreeMap<String, Service>,
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl MockBot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_sql(mut self, pool: sql::Pool) -> Self {
+        self.db = Some(pool);
+        self
+    }
+
+    // Gives the bot an empty in-memory SQLite database; tests should create whatever tables
+    // they need with `sql()?.batch_execute(...)`.
+    #[must_use]
+    pub fn with_sqlite(self) -> Self {
+        let pool = sql::single_connection_pool(|| Ok(Box::new(SqliteConnection::open_in_memory()?)))
+            .expect("failed to open in-memory database");
+        self.with_sql(pool)
+    }
+
+    // Provides a service, as a loaded module would.
+    #[must_use]
+    pub fn provide<T: Any + Send + Sync>(mut self, name: &str, service: T) -> Self {
+        self.services.insert(name.to_string(), Box::new(service));
+        self
+    }
+
+    pub fn sent(&self) -> Vec<Sent> {
+        self.sent.lock().clone()
+    }
+
+    pub fn take_sent(&self) -> Vec<Sent> {
+        std::mem::take(&mut *self.sent.lock())
+    }
+
+    // The jobs scheduled through the bot and not cancelled, in the order they were scheduled;
+    // they're never run.
+    pub fn jobs(&self) -> Vec<Job> {
+        self.jobs.lock().clone()
+    }
+}
+
+impl Bot for MockBot {
+    fn sql(&self) -> Result<sql::PooledConnection> {
+        match &self.db {
+            Some(db) => Ok(db.get()?),
+            None => bail!("MockBot has no database; use MockBot::with_sqlite or MockBot::with_sql"),
+        }
+    }
+
+    fn generation(&self, table: &str) -> u64 {
+        self.generations.get(table)
+    }
+
+    fn invalidate(&self, table: Option<&str>) {
+        self.generations.bump(table);
+    }
+
+    fn irc_send_privmsg(&self, config: &str, channel: &str, message: &str) -> Result<()> {
+        self.sent.lock().push(Sent::IrcPrivmsg {
+            config: config.to_string(),
+            channel: channel.to_string(),
+            message: message.to_string(),
+        });
+        Ok(())
+    }
+
+    fn irc_send_raw(&self, config: &str, line: &str) -> Result<()> {
+        self.sent.lock().push(Sent::IrcRaw {
+            config: config.to_string(),
+            line: line.to_string(),
+        });
+        Ok(())
+    }
+
+    fn dis_unprocess_message(&self, _config: &str, _guild: &str, message: &str) -> Result<String> {
+        Ok(message.to_string())
+    }
+
+    fn dis_send_message(&self, config: &str, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
+        self.sent.lock().push(Sent::DiscordMessage {
+            config: config.to_string(),
+            guild: guild.to_string(),
+            channel: channel.to_string(),
+            message: message.to_string(),
+            process,
+        });
+        Ok(())
+    }
+
+    fn send_message(&self, config: &str, target: &str, message: Message) -> Result<()> {
+        self.sent.lock().push(Sent::Message {
+            config: config.to_string(),
+            target: target.to_string(),
+            message: to_owned_message(message),
+        });
+        Ok(())
+    }
+
+    fn call_service(&self, name: &str, f: &mut ServiceFn) -> Result<()> {
+        match self.services.get(name) {
+            Some(service) => f(service.as_ref()),
+            None => Err(crate::services::unavailable(name)),
+        }
+    }
+
+    fn schedule(&self, mut job: Job) -> Result<i64> {
+        let mut jobs = self.jobs.lock();
+        job.id = jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+        jobs.push(job);
+        Ok(jobs.last().unwrap().id)
+    }
+
+    fn unschedule(&self, id: i64) -> Result<bool> {
+        let mut jobs = self.jobs.lock();
+        let before = jobs.len();
+        jobs.retain(|j| j.id != id);
+        Ok(jobs.len() != before)
+    }
+
+    fn scheduled(&self, handler: &str) -> Result<Vec<Job>> {
+        let mut jobs: Vec<Job> = self
+            .jobs
+            .lock()
+            .iter()
+            .filter(|j| j.handler == handler)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|j| j.next_run);
+        Ok(jobs)
+    }
+}
+
+#[derive(Clone)]
+pub struct MockSource {
+    pub user: String,
+    pub user_pretty: String,
+    pub channel: String,
+    pub discord: Option<(Option<u64>, u64, u64)>,
+    pub irc: Option<(Option<String>, String)>,
+}
+
+impl MockSource {
+    // A message from `nick` in the given IRC channel, or a query if `channel` is None.
+    pub fn irc(channel: Option<&str>, nick: &str) -> Self {
+        Self {
+            user: format!("{nick}!{nick}@test"),
+            user_pretty: nick.to_string(),
+            channel: format!("irc:{}", channel.unwrap_or("query")),
+            discord: None,
+            irc: Some((channel.map(str::to_string), nick.to_string())),
+        }
+    }
+
+    pub fn discord(guild: Option<u64>, channel: u64, user: u64, name: &str) -> Self {
+        Self {
+            user: format!("{guild:?}:{user}"),
+            user_pretty: name.to_string(),
+            channel: format!(
+                "dis:{}:{}",
+                guild.map(|g| g.to_string()).unwrap_or_else(|| "none".to_string()),
+                channel
+            ),
+            discord: Some((guild, channel, user)),
+            irc: None,
+        }
+    }
+}
+
+impl Default for MockSource {
+    fn default() -> Self {
+        Self::irc(Some("#test"), "tester")
+    }
+}
+
+impl Source for MockSource {
+    fn user_string(&self) -> Cow<str> {
+        (&self.user).into()
+    }
+
+    fn user_pretty(&self) -> Cow<str> {
+        (&self.user_pretty).into()
+    }
+
+    fn channel_string(&self) -> Cow<str> {
+        (&self.channel).into()
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        self.discord
+    }
+
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        self.irc.clone()
+    }
+}
+
+// A Context that records its replies and sub-invocations.
+pub struct MockContext {
+    pub bot: MockBot,
+    config_id: String,
+    source: MockSource,
+    perms: Perms,
+    named_perms: Vec<String>,
+    replies: Mutex<Vec<Message<'static>>>,
+    subs: Mutex<Vec<(String, String)>>,
+    // what find_user and find_channel find
+    users: Vec<(Mention, String)>,
+    channels: Vec<(ChannelRef, String)>,
+}
+
+impl MockContext {
+    pub fn new() -> Self {
+        Self {
+            bot: MockBot::new(),
+            config_id: "test".to_string(),
+            source: MockSource::default(),
+            perms: Perms::None,
+            named_perms: vec![],
+            replies: Mutex::new(vec![]),
+            subs: Mutex::new(vec![]),
+            users: vec![],
+            channels: vec![],
+        }
+    }
+
+    #[must_use]
+    pub fn with_bot(mut self, bot: MockBot) -> Self {
+        self.bot = bot;
+        self
+    }
+
+    #[must_use]
+    pub fn with_config_id(mut self, config_id: &str) -> Self {
+        self.config_id = config_id.to_string();
+        self
+    }
+
+    #[must_use]
+    pub fn with_source(mut self, source: MockSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    #[must_use]
+    pub fn with_perms(mut self, perms: Perms) -> Self {
+        self.perms = perms;
+        self
+    }
+
+    // Gives the source a permission by name, e.g. one defined by the module under test.
+    #[must_use]
+    pub fn with_perm(mut self, name: &str) -> Self {
+        self.named_perms.push(name.to_string());
+        self
+    }
+
+    // Has find_user find a user given as e.g. "@bob", as the network would.
+    #[must_use]
+    pub fn with_user(mut self, arg: &str, user_string: &str) -> Self {
+        self.users
+            .push((Mention::parse_full(arg).unwrap(), user_string.to_string()));
+        self
+    }
+
+    // Has find_channel find a channel given as e.g. "#chan", as the network would.
+    #[must_use]
+    pub fn with_channel(mut self, arg: &str, channel_string: &str) -> Self {
+        self.channels
+            .push((ChannelRef::parse_full(arg).unwrap(), channel_string.to_string()));
+        self
+    }
+
+    pub fn replies(&self) -> Vec<Message<'static>> {
+        self.replies.lock().clone()
+    }
+
+    // The replies so far as plain text, clearing them.
+    pub fn take_replies(&self) -> Vec<String> {
+        std::mem::take(&mut *self.replies.lock())
+            .iter()
+            .map(message_text)
+            .collect()
+    }
+
+    // The (name, message) pairs passed to do_sub so far.
+    pub fn subs(&self) -> Vec<(String, String)> {
+        self.subs.lock().clone()
+    }
+}
+
+impl Default for MockContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context for MockContext {
+    fn config_id(&self) -> &str {
+        &self.config_id
+    }
+
+    fn bot(&self) -> &(dyn Bot + Sync) {
+        &self.bot
+    }
+
+    fn say(&self, message: &str) -> Result<()> {
+        self.reply(Message::Simple(message.to_string()))
+    }
+
+    fn reply(&self, message: Message) -> Result<()> {
+        self.replies.lock().push(to_owned_message(message));
+        Ok(())
+    }
+
+    fn has_perm(&self, name: &str) -> Result<bool> {
+        Ok(self.perms.names().contains(&name) || self.named_perms.iter().any(|p| p == name || p == ALL))
+    }
+
+    fn source(&self) -> &dyn Source {
+        &self.source
+    }
+
+    fn do_sub(&self, name: &str, msg: &str) -> Result<()> {
+        self.subs.lock().push((name.to_string(), msg.to_string()));
+        Ok(())
+    }
+
+    fn find_user(&self, user: &Mention) -> Result<Option<String>> {
+        Ok(self.users.iter().find(|(m, _)| m == user).map(|(_, u)| u.clone()))
+    }
+
+    fn find_channel(&self, channel: &ChannelRef) -> Result<Option<String>> {
+        Ok(self.channels.iter().find(|(c, _)| c == channel).map(|(_, c)| c.clone()))
+    }
+}
+
+// A Meta that keeps everything a module registers so tests can inspect and invoke it. Threads
+// registered by the module are kept but never started.
+#[derive(Default)]
+pub struct TestMeta {
+    pub commands: BTreeMap<String, Command>,
+    pub handlers: Vec<(HandleType, Box<MsgHandlerFn>)>,
+    pub event_handlers: Vec<(EventType, Box<EventHandlerFn>)>,
+    pub deinit: Option<Box<DeinitFn>>,
+    pub threads: Vec<Box<ThreadFn>>,
+    // name -> description
+    pub permissions: BTreeMap<String, String>,
+    // (name, version)
+    pub module: Option<(String, String)>,
+    // (module, version requirement)
+    pub depends: Vec<(String, String)>,
+    pub services: BTreeMap<String, Service>,
+    pub save_state: Option<Box<SaveStateFn>>,
+    pub job_handlers: BTreeMap<String, Box<JobFn>>,
+    // the state given to the module when it was loaded; see reload
+    pub restored_state: Option<State>,
+    unload_channels: Vec<oneshot::Sender<()>>,
+}
+
+impl TestMeta {
+    // Loads a module through its `get_meta` function.
+    pub fn load(get_meta: fn(&mut dyn Meta)) -> Self {
+        let mut meta = Self::default();
+        get_meta(&mut meta);
+        meta
+    }
+
+    // Loads a module through its `get_meta_conf` function, with the given module config.
+    pub fn load_conf(get_meta_conf: fn(&mut dyn Meta, toml::Value) -> Result<()>, conf: toml::Value) -> Result<Self> {
+        let mut meta = Self::default();
+        get_meta_conf(&mut meta, conf)?;
+        Ok(meta)
+    }
+
+    // Loads the module again as the bot does when it's reloaded, passing on any state it saved.
+    pub fn reload(&mut self, get_meta: fn(&mut dyn Meta)) -> Result<Self> {
+        let mut meta = Self::default();
+        if let Some(f) = &mut self.save_state {
+            meta.restored_state = Some(f()?);
+        }
+        get_meta(&mut meta);
+        Ok(meta)
+    }
+
+    pub fn command(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    // A service the module provided, if it's of the given type.
+    pub fn service<T: Any>(&self, name: &str) -> Option<&T> {
+        self.services.get(name)?.downcast_ref()
+    }
+
+    // Runs a command as the bot would, including its permission check.
+    pub fn call(&self, ctx: &dyn Context, name: &str, args: &str) -> Result<()> {
+        match self.commands.get(name) {
+            Some(cmd) => call(ctx, name, cmd, args),
+            None => panic!("module did not register a command named {:?}", name),
+        }
+    }
+
+    // Runs every handler registered for the given message type.
+    pub fn run_handlers(&self, ctx: &dyn Context, typ: HandleType, msg: &str) -> Result<()> {
+        for (ty, handler) in &self.handlers {
+            if ty.contains(typ) {
+                handler(ctx, typ, msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a job with the handler the module registered for it, as the bot would when it's due.
+    pub fn run_job(&self, bot: &dyn Bot, job: &Job) -> Result<Option<Message<'static>>> {
+        match self.job_handlers.get(&job.handler) {
+            Some(f) => f(bot, job),
+            None => panic!("module did not register a job handler named {:?}", job.handler),
+        }
+    }
+
+    // Runs every handler registered for the event's type.
+    pub fn run_event_handlers(&self, ctx: &dyn Context, event: &Event) -> Result<()> {
+        for (ty, handler) in &self.event_handlers {
+            if ty.contains(event.typ()) {
+                handler(ctx, event)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Meta for TestMeta {
+    fn cmd(&mut self, name: &str, cmd: Command) {
+        self.commands.insert(name.to_string(), cmd);
+    }
+
+    fn deinit(&mut self, f: Box<DeinitFn>) {
+        self.deinit = Some(f);
+    }
+
+    fn handle(&mut self, typ: HandleType, f: Box<MsgHandlerFn>) {
+        self.handlers.push((typ, f));
+    }
+
+    fn on_event(&mut self, typ: EventType, f: Box<EventHandlerFn>) {
+        self.event_handlers.push((typ, f));
+    }
+
+    fn on_unload_channel(&mut self) -> oneshot::Receiver<()> {
+        let (send, recv) = oneshot::channel();
+        self.unload_channels.push(send);
+        recv
+    }
+
+    fn thread(&mut self, f: Box<ThreadFn>) {
+        self.threads.push(f);
+    }
+
+    fn permission(&mut self, name: &str, description: &str) {
+        self.permissions.insert(name.to_string(), description.to_string());
+    }
+
+    fn module(&mut self, name: &str, version: &str) {
+        self.module = Some((name.to_string(), version.to_string()));
+    }
+
+    fn depends(&mut self, module: &str, version: &str) {
+        self.depends.push((module.to_string(), version.to_string()));
+    }
+
+    fn provide_service(&mut self, name: &str, service: Service) {
+        self.services.insert(name.to_string(), service);
+    }
+
+    fn save_state(&mut self, f: Box<SaveStateFn>) {
+        self.save_state = Some(f);
+    }
+
+    fn restored_state(&mut self) -> Option<State> {
+        self.restored_state.clone()
+    }
+
+    fn schedule(&mut self, handler: &str, f: Box<JobFn>) {
+        self.job_handlers.insert(handler.to_string(), f);
+    }
+}
@@ -1,13 +1,16 @@
 #![allow(non_upper_case_globals)]
 
 use bitflags::bitflags;
-use parking_lot::Mutex;
-use postgres::types::{FromSql, Type};
+use std::any::Any;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use super::error::Result;
-use super::spans::Span;
+use super::args::{ChannelRef, Mention};
+use super::error::{Result, UserError};
+use super::schedule::Job;
+use super::spans::{spans_to_raw_string, Span};
+use super::sql;
 
 bitflags! {
     pub struct Perms: u64 {
@@ -33,44 +36,239 @@ impl std::fmt::Display for Perms {
     }
 }
 
-impl FromSql<'_> for Perms {
-    fn from_sql(
-        ty: &Type,
-        raw: &[u8],
-    ) -> std::result::Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
-        i64::from_sql(ty, raw).map(|i| Perms { bits: i as u64 })
+impl sql::FromSql for Perms {
+    fn from_sql(v: &sql::Value) -> Result<Self> {
+        <i64 as sql::FromSql>::from_sql(v).map(|i| Perms { bits: i as u64 })
     }
-    fn accepts(ty: &Type) -> bool {
-        i64::accepts(ty)
+}
+
+impl sql::ToSql for Perms {
+    fn to_sql(&self) -> sql::Value {
+        sql::Value::Int(self.bits as i64)
     }
 }
 
 pub type CommandFn = dyn Fn(&dyn Context, &str) -> Result<()> + Send + Sync;
-#[derive(Clone)]
-pub struct Command {
-    pub function: Arc<CommandFn>,
-    pub req_perms: Perms,
+// A command, or a group of subcommands. F is the type of its function, which is only something
+// other than CommandFn for the bot's own commands, which are given more than a Context.
+pub struct Command<F: ?Sized = CommandFn> {
+    // None for a group, which does nothing itself
+    pub function: Option<Arc<F>>,
+    // the names of the permissions needed to run the command; see rustbot::perms
+    pub req_perms: Vec<String>,
+    pub help: Help,
+    // run instead of the command when the first word of its arguments is their name; see resolve
+    pub subcommands: BTreeMap<String, Command<F>>,
+}
+
+// not derived, which would need F: Clone
+impl<F: ?Sized> Clone for Command<F> {
+    fn clone(&self) -> Self {
+        Self {
+            function: self.function.clone(),
+            req_perms: self.req_perms.clone(),
+            help: self.help.clone(),
+            subcommands: self.subcommands.clone(),
+        }
+    }
 }
 
 impl Command {
     pub fn new<F: 'static + Fn(&dyn Context, &str) -> Result<()> + Send + Sync>(f: F) -> Self {
         Self {
-            function: Arc::new(f),
-            req_perms: Perms::None,
+            function: Some(Arc::new(f)),
+            ..Self::group()
+        }
+    }
+    // Runs this command, not any of its subcommands, without checking permissions.
+    pub fn run(&self, ctx: &dyn Context, args: &str) -> Result<()> {
+        match &self.function {
+            Some(f) => f(ctx, args),
+            None => Err(self.no_subcommand(ctx, args)?.into()),
         }
     }
+}
+
+impl<F: ?Sized> Command<F> {
+    // A command that's only there for its subcommands, which says what they are if none is given.
+    pub fn group() -> Self {
+        Self {
+            function: None,
+            req_perms: vec![],
+            help: Help::default(),
+            subcommands: BTreeMap::new(),
+        }
+    }
+    // Adds a subcommand, e.g. `add` for `list add <item>`; it needs its own permissions as well as
+    // this command's, and is given the arguments after its name.
+    #[must_use]
+    pub fn sub(&self, name: &str, cmd: Self) -> Self {
+        let mut s = self.clone();
+        s.help.subcommands.insert(name.to_string(), cmd.help.clone());
+        s.subcommands.insert(name.to_string(), cmd);
+        s
+    }
+    // One line on what the command does, for help.
+    #[must_use]
+    pub fn summary(&self, summary: &str) -> Self {
+        let mut s = self.clone();
+        s.help.summary = summary.to_string();
+        s
+    }
+    // The command's arguments, e.g. "<duration> <text>".
+    #[must_use]
+    pub fn usage(&self, usage: &str) -> Self {
+        let mut s = self.clone();
+        s.help.usage = usage.to_string();
+        s
+    }
+    // An example of the command's arguments; may be given more than once.
+    #[must_use]
+    pub fn example(&self, args: &str) -> Self {
+        let mut s = self.clone();
+        s.help.examples.push(args.to_string());
+        s
+    }
+    // The signature of the arguments the command parses with parse_args!, as given by
+    // describe_args! for the same arguments.
+    #[must_use]
+    pub fn args(&self, signature: String) -> Self {
+        let mut s = self.clone();
+        s.help.signature = signature;
+        s
+    }
     #[must_use]
     pub fn req_perms(&self, p: Perms) -> Self {
         let mut s = self.clone();
-        s.req_perms.insert(p);
+        for name in p.names() {
+            s = s.req_perm(name);
+        }
+        s
+    }
+    #[must_use]
+    pub fn req_perm(&self, name: &str) -> Self {
+        let mut s = self.clone();
+        if !s.req_perms.iter().any(|p| p == name) {
+            s.req_perms.push(name.to_string());
+        }
         s
     }
-    pub fn call(&self, ctx: &dyn Context, args: &str) -> Result<()> {
-        if !ctx.perms()?.contains(self.req_perms) {
-            return Ok(());
+    pub fn permitted(&self, ctx: &dyn Context) -> Result<bool> {
+        ctx.has_perms(&self.req_perms)
+    }
+    // Follows the subcommands named at the start of the arguments as far as they go, returning
+    // each with its name, and the arguments left for the last.
+    pub fn resolve<'a, 'b>(&'a self, args: &'b str) -> (Vec<(&'a str, &'a Self)>, &'b str) {
+        let mut path = vec![];
+        let mut cmd = self;
+        let mut args = args.trim_start();
+        loop {
+            let (word, rest) = match args.split_once(char::is_whitespace) {
+                Some((word, rest)) => (word, rest.trim_start()),
+                None => (args, ""),
+            };
+            match cmd.subcommands.get_key_value(word) {
+                Some((name, sub)) => {
+                    path.push((name.as_str(), sub));
+                    cmd = sub;
+                    args = rest;
+                }
+                None => return (path, args),
+            }
+        }
+    }
+    // Resolves the subcommand the arguments name, checking with may_run that the source can run
+    // it and everything on the way; subcommands are checked by their full name, e.g. "list.add".
+    // Returns that name, the command to run and its arguments, or None if anything isn't allowed.
+    pub fn resolve_permitted<'a, 'b>(
+        &'a self,
+        name: &str,
+        args: &'b str,
+        mut may_run: impl FnMut(&str, &Self) -> Result<bool>,
+    ) -> Result<Option<(String, &'a Self, &'b str)>> {
+        if !may_run(name, self)? {
+            return Ok(None);
+        }
+        let (path, args) = self.resolve(args);
+        let mut name = name.to_string();
+        for (sub, s) in &path {
+            name = format!("{name}.{sub}");
+            if !may_run(&name, s)? {
+                return Ok(None);
+            }
+        }
+        Ok(Some((name, path.last().map_or(self, |(_, s)| *s), args)))
+    }
+    // What a group says when its arguments don't start with one of its subcommands: which of them
+    // the source can run, those most like what they typed first.
+    pub fn no_subcommand(&self, ctx: &dyn Context, args: &str) -> Result<UserError> {
+        let word = args.split_whitespace().next().unwrap_or("");
+        let mut names = vec![];
+        for (name, sub) in &self.subcommands {
+            if sub.permitted(ctx)? {
+                names.push(name.as_str());
+            }
         }
+        let common = |name: &str| name.chars().zip(word.chars()).take_while(|(a, b)| a == b).count();
+        names.sort_by_key(|name| std::cmp::Reverse(common(name)));
 
-        (self.function)(ctx, args)
+        let msg = if word.is_empty() {
+            "missing subcommand"
+        } else {
+            "unknown subcommand"
+        };
+        Ok(match names.as_slice() {
+            [] => UserError::new(format!("{msg}; you can't run any here")),
+            _ => UserError::new(format!("{msg}; try {}", names.join(", "))),
+        })
+    }
+}
+
+// What help says about a command; everything is optional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Help {
+    pub summary: String,
+    pub usage: String,
+    pub examples: Vec<String>,
+    pub signature: String,
+    pub subcommands: BTreeMap<String, Help>,
+}
+
+impl Help {
+    pub fn new(usage: &str, summary: &str) -> Self {
+        Self {
+            summary: summary.to_string(),
+            usage: usage.to_string(),
+            ..Self::default()
+        }
+    }
+
+    // Describes the command as `name` for help, in a line or few.
+    pub fn describe(&self, name: &str) -> String {
+        // without a usage line, the signature is the next best thing
+        let usage = if self.usage.is_empty() {
+            &self.signature
+        } else {
+            &self.usage
+        };
+        let mut lines = vec![match (usage.is_empty(), self.summary.is_empty()) {
+            (true, true) => format!("{name}: no description"),
+            (true, false) => format!("{name}: {}", self.summary),
+            (false, true) => format!("{name} {usage}"),
+            (false, false) => format!("{name} {usage}: {}", self.summary),
+        }];
+        if !self.signature.is_empty() && *usage != self.signature {
+            lines.push(format!("arguments: {}", self.signature));
+        }
+        if !self.examples.is_empty() {
+            let examples: Vec<String> = self.examples.iter().map(|e| format!("{name} {e}")).collect();
+            lines.push(format!("e.g. {}", examples.join("; ")));
+        }
+        if !self.subcommands.is_empty() {
+            let names: Vec<&str> = self.subcommands.keys().map(String::as_str).collect();
+            lines.push(format!("subcommands: {}", names.join(", ")));
+        }
+        lines.join("\n")
     }
 }
 
@@ -91,25 +289,131 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct EventType: u64 {
+        const None           = 0x0000_0000;
+
+        const Join           = 0x0000_0001;
+        const Part           = 0x0000_0002;
+        const Quit           = 0x0000_0004;
+        const Kick           = 0x0000_0008;
+        const Nick           = 0x0000_0010;
+        const Topic          = 0x0000_0020;
+
+        const Edit           = 0x0000_0100;
+        const Delete         = 0x0000_0200;
+        const ReactionAdd    = 0x0000_0400;
+        const ReactionRemove = 0x0000_0800;
+        const MemberJoin     = 0x0000_1000;
+
+        const All            = 0xFFFF_FFFF;
+    }
+}
+
+// Something other than a message happening on a network. The user it's about, and the channel it
+// happened in if any, are the handler's context's source, which replies go to as usual.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Join,
+    Part { reason: Option<String> },
+    // the source has no channel, since the user left every channel at once
+    Quit { reason: Option<String> },
+    // the source is the user who kicked `target`
+    Kick { target: String, reason: Option<String> },
+    // the source is the user under their new nick, with no channel
+    Nick { old: String },
+    Topic { topic: String },
+    // message IDs are whatever the network uses, e.g. the snowflake on Discord
+    Edit { message_id: String, content: String },
+    // the source is the message's author
+    Delete { message_id: String },
+    ReactionAdd { message_id: String, emoji: String },
+    ReactionRemove { message_id: String, emoji: String },
+    // a user joined a Discord server; the source's channel is the server's system channel
+    MemberJoin,
+}
+
+impl Event {
+    pub fn typ(&self) -> EventType {
+        match self {
+            Event::Join => EventType::Join,
+            Event::Part { .. } => EventType::Part,
+            Event::Quit { .. } => EventType::Quit,
+            Event::Kick { .. } => EventType::Kick,
+            Event::Nick { .. } => EventType::Nick,
+            Event::Topic { .. } => EventType::Topic,
+            Event::Edit { .. } => EventType::Edit,
+            Event::Delete { .. } => EventType::Delete,
+            Event::ReactionAdd { .. } => EventType::ReactionAdd,
+            Event::ReactionRemove { .. } => EventType::ReactionRemove,
+            Event::MemberJoin => EventType::MemberJoin,
+        }
+    }
+}
+
 pub type DeinitFn = dyn FnMut(&dyn Bot) -> Result<()> + Send + Sync;
 
 pub type MsgHandlerFn = dyn Fn(&dyn Context, HandleType, &str) -> Result<()> + Send + Sync;
 
+pub type EventHandlerFn = dyn Fn(&dyn Context, &Event) -> Result<()> + Send + Sync;
+
 pub type ThreadFn = dyn FnOnce() + 'static + Send;
 
+// Runs a scheduled job, returning what to send to its channel, if anything.
+pub type JobFn = dyn Fn(&dyn Bot, &Job) -> Result<Option<Message<'static>>> + Send + Sync;
+
 pub trait Meta {
     fn cmd(&mut self, name: &str, cmd: Command);
     fn deinit(&mut self, f: Box<DeinitFn>);
 
     fn handle(&mut self, typ: HandleType, f: Box<MsgHandlerFn>);
 
+    // Like handle, for events other than messages; `typ` is the events to be called for.
+    fn on_event(&mut self, typ: EventType, f: Box<EventHandlerFn>);
+
     fn on_unload_channel(&mut self) -> futures::channel::oneshot::Receiver<()>;
 
     fn thread(&mut self, f: Box<ThreadFn>);
+
+    // Defines a permission the module's commands can require, so it can be listed and granted.
+    fn permission(&mut self, name: &str, description: &str);
+
+    // Declares the module's name and version. `module!` does this from the crate's Cargo.toml
+    // before anything else, so modules only need to call it to override those.
+    fn module(&mut self, name: &str, version: &str);
+
+    // Declares that the module needs another loaded first, with a version matching a semver
+    // requirement such as "0.1" or ">=1.2".
+    fn depends(&mut self, module: &str, version: &str);
+
+    // Makes a service available to other modules; see rustbot::services, which has a typed
+    // wrapper for this.
+    fn provide_service(&mut self, name: &str, service: Service);
+
+    // Keeps state across a reload: when the module is replaced by a new build of itself, `f` is
+    // called on the old one and what it returns is passed to the new one, which gets it from
+    // restored_state. See rustbot::state, which has a typed wrapper for these.
+    fn save_state(&mut self, f: Box<SaveStateFn>);
+    fn restored_state(&mut self) -> Option<State>;
+
+    // Registers the handler for scheduled jobs named `handler`; see rustbot::schedule.
+    fn schedule(&mut self, handler: &str, f: Box<JobFn>);
 }
 
+// Bytes, since nothing from the old module's library can outlive it.
+pub type State = Vec<u8>;
+pub type SaveStateFn = dyn FnMut() -> Result<State> + Send + Sync;
+
+pub type Service = Box<dyn Any + Send + Sync>;
+pub type ServiceFn<'a> = dyn FnMut(&(dyn Any + Send + Sync)) -> Result<()> + 'a;
+
 pub trait Bot {
-    fn sql(&self) -> &Mutex<postgres::Client>;
+    fn sql(&self) -> Result<sql::PooledConnection>;
+
+    // See rustbot::cache. Anything that writes to a table another part of the bot may have cached
+    // should call invalidate; on Postgres, changes from outside the bot are picked up automatically.
+    fn generation(&self, table: &str) -> u64;
+    fn invalidate(&self, table: Option<&str>);
 
     fn irc_send_privmsg(&self, _: &str, _: &str, _: &str) -> Result<()>;
     fn irc_send_raw(&self, _: &str, _: &str) -> Result<()>;
@@ -118,6 +422,17 @@ pub trait Bot {
     fn dis_send_message(&self, _: &str, _: &str, _: &str, _: &str, _: bool) -> Result<()>;
 
     fn send_message(&self, _: &str, _: &str, _: Message) -> Result<()>;
+
+    // Calls `f` with the named service, failing if no loaded module provides it; see
+    // rustbot::services, which has a typed wrapper for this.
+    fn call_service(&self, name: &str, f: &mut ServiceFn) -> Result<()>;
+
+    // Stores a job to be run when it's due, returning its ID; see rustbot::schedule.
+    fn schedule(&self, job: Job) -> Result<i64>;
+    // Cancels a job, returning whether there was one with that ID.
+    fn unschedule(&self, id: i64) -> Result<bool>;
+    // The jobs waiting to be run by a handler, soonest first.
+    fn scheduled(&self, handler: &str) -> Result<Vec<Job>>;
 }
 
 pub trait Context {
@@ -125,10 +440,36 @@ pub trait Context {
     fn bot(&self) -> &(dyn Bot + Sync);
     fn say(&self, _: &str) -> Result<()>;
     fn reply(&self, _: Message) -> Result<()>;
-    fn perms(&self) -> Result<Perms>;
+    fn has_perm(&self, name: &str) -> Result<bool>;
     fn source(&self) -> &dyn Source;
 
+    // Whether the source has all of the permissions, e.g. those a command requires.
+    fn has_perms(&self, names: &[String]) -> Result<bool> {
+        for name in names {
+            if !self.has_perm(name)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // The core permissions the source has here.
+    fn perms(&self) -> Result<Perms> {
+        let mut perms = Perms::None;
+        for (p, name, _) in crate::perms::CORE {
+            if self.has_perm(name)? {
+                perms.insert(*p);
+            }
+        }
+        Ok(perms)
+    }
+
     fn do_sub(&self, name: &str, msg: &str) -> Result<()>;
+
+    // The user or channel a command's argument refers to, as Source::user_string or
+    // Source::channel_string would give it, if the source's network has one by that name or ID.
+    fn find_user(&self, user: &Mention) -> Result<Option<String>>;
+    fn find_channel(&self, channel: &ChannelRef) -> Result<Option<String>>;
 }
 
 pub trait Source {
@@ -152,3 +493,22 @@ pub enum Message<'a> {
         items: Vec<Cow<'a, str>>,
     },
 }
+
+impl Message<'_> {
+    // The message as plain text with all formatting removed.
+    pub fn to_text(&self) -> String {
+        match self {
+            Message::Simple(s) | Message::Code(s) => s.clone(),
+            Message::Spans(s) => spans_to_raw_string(s.clone()),
+            Message::Prefixed(p, s) => {
+                let p = spans_to_raw_string(p.clone());
+                spans_to_raw_string(s.clone())
+                    .split('\n')
+                    .map(|line| p.clone() + line)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            Message::List { prefix, sep, items } => format!("{}{}", prefix, items.join(sep)),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use parking_lot::Mutex;
+use std::any::Any;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::thread;
+
+use super::{Adapter, AdapterSource, Event, EventKind, EventSink};
+use crate::config;
+use crate::context::Source;
+use crate::message;
+use rustbot::prelude::*;
+use rustbot::types;
+
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+pub struct ConsoleAdapter {
+    config: config::Console,
+    outputs: Mutex<Vec<Output>>,
+}
+
+impl ConsoleAdapter {
+    pub fn new(config: config::Console) -> Self {
+        Self {
+            config,
+            outputs: Mutex::new(vec![]),
+        }
+    }
+
+    // Feeds each line of `input` through the sink as if it was sent by the configured user, with
+    // replies written to `output`. Lines are handled one at a time, so a reply is always written
+    // before the next line is read.
+    pub fn serve(&self, input: impl BufRead, output: Output, sink: &EventSink) -> Result<()> {
+        self.outputs.lock().push(Arc::clone(&output));
+
+        let result = (|| {
+            for line in input.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let source = ConsoleSource {
+                    user: self.config.user.clone(),
+                    channel: self.config.channel.clone(),
+                    perms: self.config.perms.clone(),
+                    output: Arc::clone(&output),
+                };
+                sink(Event {
+                    source: Source::Adapter(Arc::new(source)),
+                    kind: EventKind::Message {
+                        typ: HandleType::PlainMsg | HandleType::Public,
+                        message: line,
+                    },
+                });
+            }
+            Ok(())
+        })();
+
+        self.outputs.lock().retain(|o| !Arc::ptr_eq(o, &output));
+        result
+    }
+
+    fn render(&self, message: Message) -> Result<Vec<String>> {
+        match self.config.format {
+            config::ConsoleFormat::Irc => message::format_irc(message),
+            config::ConsoleFormat::Plain => Ok(message::format_plain(message)),
+        }
+    }
+
+    fn write(&self, output: &Output, message: Message) -> Result<()> {
+        let mut output = output.lock();
+        for line in self.render(message)? {
+            writeln!(output, "{line}")?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+impl Adapter for ConsoleAdapter {
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn descriptor(&self) -> String {
+        match &self.config.socket {
+            Some(path) => format!("Console: {} ({})", self.config.id, path),
+            None => format!("Console: {} (stdin)", self.config.id),
+        }
+    }
+
+    fn network(&self) -> &'static str {
+        "con"
+    }
+
+    fn connect(&self, _bot: &dyn Bot, sink: EventSink) -> Result<()> {
+        let path = match &self.config.socket {
+            Some(path) => path,
+            None => {
+                info!("connect: {}", self.descriptor());
+                let output: Output = Arc::new(Mutex::new(io::stdout()));
+                self.serve(io::stdin().lock(), output, &sink)?;
+                info!("{}: stdin closed", self.config.id);
+                return Ok(());
+            }
+        };
+
+        // clean up after a previous run, if necessary
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        info!("connect: {}", self.descriptor());
+
+        thread::scope(|s| {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let output: Output = Arc::new(Mutex::new(stream.try_clone()?));
+                let sink = &sink;
+                s.spawn(move || {
+                    if let Err(e) = self.serve(BufReader::new(stream), output, sink) {
+                        warn!("{}: console client failed: {}", self.config.id, e);
+                    }
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn send(&self, _target: &str, message: Message) -> Result<()> {
+        // there's only the one channel, so anyone connected sees everything
+        let outputs = self.outputs.lock().clone();
+        for output in &outputs {
+            self.write(output, message.clone())?;
+        }
+        Ok(())
+    }
+
+    fn reply(&self, source: &dyn AdapterSource, message: Message) -> Result<()> {
+        let source = match source.as_any().downcast_ref::<ConsoleSource>() {
+            Some(s) => s,
+            None => bail!("console adapter asked to reply to a non-console source"),
+        };
+
+        self.write(&source.output, message)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct ConsoleSource {
+    user: String,
+    channel: String,
+    perms: Vec<String>,
+    output: Output,
+}
+
+impl types::Source for ConsoleSource {
+    fn user_string(&self) -> Cow<str> {
+        format!("con:{}", self.user).into()
+    }
+
+    fn user_pretty(&self) -> Cow<str> {
+        (&self.user).into()
+    }
+
+    fn channel_string(&self) -> Cow<str> {
+        format!("con:{}", self.channel).into()
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        None
+    }
+
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        None
+    }
+}
+
+impl AdapterSource for ConsoleSource {
+    fn subjects(&self) -> Vec<String> {
+        vec![format!("con:{}", self.user)]
+    }
+
+    fn implicit_perms(&self) -> Vec<String> {
+        self.perms.clone()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,591 @@
+use parking_lot::{Mutex, RwLock};
+use serenity::cache::Cache;
+use serenity::model::channel;
+use serenity::model::guild;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::prelude as ser;
+use serenity::prelude as dis;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::{Adapter, AdapterSource, Event, EventKind, EventSink};
+use crate::config;
+use crate::context::Source;
+use crate::message;
+use crate::ratelimit::SendQueue;
+use rustbot::prelude::*;
+use rustbot::types;
+
+// Messages kept per channel.
+const MESSAGE_CACHE: usize = 100;
+
+pub struct DiscordAdapter {
+    config: config::Discord,
+    cache_and_http: RwLock<Option<Arc<serenity::CacheAndHttp>>>,
+    // Discord limits messages per channel, so each channel gets its own queue
+    queues: Mutex<BTreeMap<ChannelId, Arc<SendQueue>>>,
+}
+
+impl DiscordAdapter {
+    pub fn new(config: config::Discord) -> Self {
+        Self {
+            config,
+            cache_and_http: RwLock::new(None),
+            queues: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn say(&self, channel: ChannelId, message: String) -> Result<()> {
+        let http = Arc::clone(&self.cache_and_http()?.http);
+        let queue = Arc::clone(self.queues.lock().entry(channel).or_insert_with(|| {
+            Arc::new(SendQueue::new(
+                &format!("{}:{}", self.config.id, channel),
+                self.config.send_limit,
+            ))
+        }));
+        queue.push(Box::new(move || {
+            channel.say(http, message)?;
+            Ok(())
+        }))
+    }
+
+    fn cache_and_http(&self) -> Result<Arc<serenity::CacheAndHttp>> {
+        match &*self.cache_and_http.read() {
+            Some(c) => Ok(Arc::clone(c)),
+            None => bail!("no cache found for config {:?}", self.config.id),
+        }
+    }
+
+    fn get_replacements(guild: impl std::ops::Deref<Target = guild::Guild>, reverse: bool) -> Vec<(String, String)> {
+        let mut replacements = vec![];
+        for (id, m) in &guild.members {
+            replacements.push((format!("@{}", m.user.read().name), format!("<@{id}>")));
+            if reverse {
+                replacements.push((format!("@{}", m.user.read().name), format!("<@!{id}>")));
+            }
+        }
+
+        for (id, r) in &guild.roles {
+            replacements.push((format!("@{}", r.name), format!("<@&{id}>")));
+        }
+
+        for (id, c) in &guild.channels {
+            replacements.push((format!("#{}", c.read().name), format!("<#{id}>")));
+        }
+
+        for (id, e) in &guild.emojis {
+            replacements.push((format!(":{}:", e.name), format!("<:{}:{}>", e.name, id)));
+        }
+
+        replacements
+    }
+
+    pub fn unprocess_message(&self, guild: &str, message: &str) -> Result<String> {
+        let cache_and_http = self.cache_and_http()?;
+        let cache = cache_and_http.cache.read();
+
+        let mut message = message.to_string();
+
+        let guildobj = find_guild(&cache, guild)
+            .ok_or_else(|| Error::msg("guild not found"))?
+            .read();
+
+        let mut replacements = Self::get_replacements(guildobj, true);
+
+        replacements.sort_by(|l, r| {
+            if l.1.len() != r.1.len() {
+                return l.1.len().cmp(&r.1.len()).reverse();
+            }
+
+            l.1.cmp(&r.1)
+        });
+
+        for (replace, find) in replacements {
+            message = message.replace(&find, &replace);
+        }
+
+        Ok(message)
+    }
+
+    pub fn send_message(&self, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
+        let cache_and_http = self.cache_and_http()?;
+
+        let cache = cache_and_http.cache.read();
+
+        let guildobj = find_guild(&cache, guild)
+            .ok_or_else(|| Error::msg("guild not found"))?
+            .read();
+
+        let chanid = find_channel(&guildobj, channel).ok_or_else(|| Error::msg("channel not found"))?;
+
+        if process {
+            let mut message = message.to_string();
+
+            let mut replacements = Self::get_replacements(guildobj, false);
+
+            replacements.sort_by(|l, r| {
+                if l.0.len() != r.0.len() {
+                    return l.0.len().cmp(&r.0.len()).reverse();
+                }
+
+                l.0.cmp(&r.0)
+            });
+
+            {
+                for (find, replace) in replacements {
+                    let mut need_replace = false;
+
+                    let is_replace_before_ok = |c| {
+                        let cat = unic_ucd::GeneralCategory::of(c);
+
+                        cat.is_separator() || cat.is_punctuation()
+                    };
+
+                    // Check whether we actually need to do anything.
+                    // Most of the time, we don't, so we can avoid allocating.
+                    if message.ends_with(&find) {
+                        need_replace = true;
+                    } else {
+                        for part in message.split(&find).skip(1) {
+                            if part.starts_with(is_replace_before_ok) {
+                                need_replace = true;
+                            }
+                        }
+                    }
+
+                    if need_replace {
+                        let mut parts = message.split(&find);
+                        let mut new_parts = vec![parts.next().unwrap()];
+
+                        for part in parts {
+                            if part.is_empty() || part.starts_with(is_replace_before_ok) {
+                                new_parts.push(&replace);
+                            } else {
+                                new_parts.push(&find);
+                            }
+                            new_parts.push(part);
+                        }
+
+                        message = new_parts.join("");
+                    }
+                }
+            }
+
+            self.say(chanid, message)
+        } else {
+            self.say(chanid, message.to_string())
+        }
+    }
+}
+
+// Finds a guild by ID or name.
+// Finds a member of a guild by username or nickname.
+fn find_member(guild: &guild::Guild, name: &str) -> Option<UserId> {
+    guild
+        .members
+        .iter()
+        .find(|(_, m)| m.user.read().name == name || m.nick.as_deref() == Some(name))
+        .map(|(id, _)| *id)
+}
+
+fn find_guild<'a>(cache: &'a Cache, guild: &str) -> Option<&'a Arc<dis::RwLock<guild::Guild>>> {
+    if let Ok(id) = guild.parse() {
+        cache.guilds.get(&GuildId(id))
+    } else {
+        cache.guilds.values().find(|g| g.read().name == guild)
+    }
+}
+
+// Finds a channel within a guild by ID or name.
+fn find_channel(guild: &guild::Guild, channel: &str) -> Option<ChannelId> {
+    if let Ok(id) = channel.parse() {
+        if guild.channels.get(&ChannelId(id)).is_some() {
+            Some(ChannelId(id))
+        } else {
+            None
+        }
+    } else {
+        guild
+            .channels
+            .iter()
+            .find(|(_, c)| c.read().name == channel)
+            .map(|(id, _)| *id)
+    }
+}
+
+impl Adapter for DiscordAdapter {
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn descriptor(&self) -> String {
+        format!("Discord: {}", self.config.id)
+    }
+
+    fn network(&self) -> &'static str {
+        "dis"
+    }
+
+    fn connect(&self, _bot: &dyn Bot, sink: EventSink) -> Result<()> {
+        let mut dis = dis::Client::new(&self.config.token, Handler { sink })?;
+        // Discord doesn't say who wrote a deleted message, so keep recent ones to look it up
+        dis.cache_and_http
+            .cache
+            .write()
+            .settings_mut()
+            .max_messages(MESSAGE_CACHE);
+
+        *self.cache_and_http.write() = Some(dis.cache_and_http.clone());
+        info!("connect: {}", self.config.id);
+        dis.start()?;
+        Ok(())
+    }
+
+    fn send(&self, target: &str, message: Message) -> Result<()> {
+        match target.split_once(':') {
+            Some((guild, channel)) => self.send_message(guild, channel, &message::format_discord(message)?, true),
+            None => bail!("invalid discord target {:?}", target),
+        }
+    }
+
+    fn reply(&self, source: &dyn AdapterSource, message: Message) -> Result<()> {
+        let source = match source.as_any().downcast_ref::<DiscordSource>() {
+            Some(s) => s,
+            None => bail!("Discord adapter asked to reply to a non-Discord source"),
+        };
+
+        self.say(source.channel, message::format_discord(message)?)
+    }
+
+    // Mentions by ID are taken as they are, as the member cache of a large guild may not have
+    // everyone in it; names are looked up in the guild the source is in.
+    fn find_user(&self, source: &dyn AdapterSource, user: &Mention) -> Result<Option<String>> {
+        let source = match source.as_any().downcast_ref::<DiscordSource>() {
+            Some(s) => s,
+            None => bail!("Discord adapter asked to find a user for a non-Discord source"),
+        };
+        let id = match (user, source.guild) {
+            (Mention::Id(id), _) => UserId(*id),
+            (Mention::Name(name), Some(guild)) => {
+                let cache_and_http = self.cache_and_http()?;
+                let cache = cache_and_http.cache.read();
+                match cache.guilds.get(&guild).and_then(|g| find_member(&g.read(), name)) {
+                    Some(id) => id,
+                    None => return Ok(None),
+                }
+            }
+            (Mention::Name(_), None) => return Ok(None),
+        };
+        Ok(Some(format!("{:?}:{}", source.guild.map(|g| *g.as_u64()), id.as_u64())))
+    }
+
+    fn find_channel(&self, source: &dyn AdapterSource, channel: &ChannelRef) -> Result<Option<String>> {
+        let source = match source.as_any().downcast_ref::<DiscordSource>() {
+            Some(s) => s,
+            None => bail!("Discord adapter asked to find a channel for a non-Discord source"),
+        };
+        let guild = match source.guild {
+            Some(guild) => guild,
+            None => return Ok(None),
+        };
+        let channel = match channel {
+            ChannelRef::Id(id) => id.to_string(),
+            ChannelRef::Name(name) => name.trim_start_matches('#').to_string(),
+        };
+
+        let cache_and_http = self.cache_and_http()?;
+        let cache = cache_and_http.cache.read();
+        Ok(cache
+            .guilds
+            .get(&guild)
+            .and_then(|g| find_channel(&g.read(), &channel))
+            .map(|id| format!("dis:{}:{}", guild.as_u64(), id.as_u64())))
+    }
+
+    fn send_queues(&self) -> Vec<Arc<SendQueue>> {
+        self.queues.lock().values().cloned().collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct Handler {
+    sink: EventSink,
+}
+
+impl Handler {
+    fn spawn(&self, disctx: dis::Context, f: impl FnOnce(&EventSink, &dis::Context) + Send + 'static) {
+        let sink = Arc::clone(&self.sink);
+        rayon::spawn(move || {
+            f(&sink, &disctx);
+        });
+    }
+}
+
+impl dis::EventHandler for Handler {
+    fn message(&self, disctx: dis::Context, msg: channel::Message) {
+        self.spawn(disctx, move |sink, disctx| incoming(sink, disctx, msg));
+    }
+
+    fn message_update(
+        &self,
+        disctx: dis::Context,
+        _old: Option<channel::Message>,
+        _new: Option<channel::Message>,
+        update: ser::MessageUpdateEvent,
+    ) {
+        // updates without content are Discord adding embeds and the like, not edits
+        if let (Some(author), Some(content)) = (update.author, update.content) {
+            let event = types::Event::Edit {
+                message_id: update.id.to_string(),
+                content,
+            };
+            let (channel, guild) = (update.channel_id, update.guild_id);
+            self.spawn(disctx, move |sink, disctx| {
+                other(sink, disctx, author, channel, guild, event)
+            });
+        }
+    }
+
+    fn message_delete(&self, disctx: dis::Context, channel_id: ChannelId, message_id: ser::MessageId) {
+        self.spawn(disctx, move |sink, disctx| {
+            let msg = match disctx.cache.read().message(channel_id, message_id) {
+                Some(msg) => msg,
+                None => {
+                    debug!("ignoring deletion of uncached message {}", message_id);
+                    return;
+                }
+            };
+            let event = types::Event::Delete {
+                message_id: message_id.to_string(),
+            };
+            other(sink, disctx, msg.author, channel_id, msg.guild_id, event);
+        });
+    }
+
+    fn reaction_add(&self, disctx: dis::Context, reaction: channel::Reaction) {
+        let event = types::Event::ReactionAdd {
+            message_id: reaction.message_id.to_string(),
+            emoji: reaction.emoji.to_string(),
+        };
+        self.spawn(disctx, move |sink, disctx| reacted(sink, disctx, reaction, event));
+    }
+
+    fn reaction_remove(&self, disctx: dis::Context, reaction: channel::Reaction) {
+        let event = types::Event::ReactionRemove {
+            message_id: reaction.message_id.to_string(),
+            emoji: reaction.emoji.to_string(),
+        };
+        self.spawn(disctx, move |sink, disctx| reacted(sink, disctx, reaction, event));
+    }
+
+    fn guild_member_addition(&self, disctx: dis::Context, guild_id: GuildId, member: guild::Member) {
+        self.spawn(disctx, move |sink, disctx| {
+            let channel = disctx
+                .cache
+                .read()
+                .guild(guild_id)
+                .and_then(|g| g.read().system_channel_id);
+            let channel = match channel {
+                Some(c) => c,
+                None => {
+                    debug!("ignoring member joining guild {} with no system channel", guild_id);
+                    return;
+                }
+            };
+            let user = member.user.read().clone();
+            other(sink, disctx, user, channel, Some(guild_id), types::Event::MemberJoin);
+        });
+    }
+}
+
+fn reacted(sink: &EventSink, disctx: &dis::Context, reaction: channel::Reaction, event: types::Event) {
+    match reaction.user_id.to_user(disctx) {
+        Ok(user) => other(sink, disctx, user, reaction.channel_id, reaction.guild_id, event),
+        Err(e) => warn!("failed to look up user {} for reaction: {}", reaction.user_id, e),
+    }
+}
+
+// Passes on an event other than a message, from `user` in `channel`.
+fn other(
+    sink: &EventSink,
+    disctx: &dis::Context,
+    user: ser::User,
+    channel: ChannelId,
+    guild: Option<GuildId>,
+    event: types::Event,
+) {
+    if user.id == disctx.cache.read().user.id {
+        return;
+    }
+
+    let roles = guild
+        .and_then(|g| disctx.cache.read().member(g, user.id))
+        .map(|m| m.roles)
+        .unwrap_or_default();
+    sink(Event {
+        source: Source::Adapter(Arc::new(DiscordSource {
+            user,
+            channel,
+            guild,
+            roles,
+        })),
+        kind: EventKind::Other(event),
+    });
+}
+
+fn incoming(sink: &EventSink, disctx: &dis::Context, msg: channel::Message) {
+    if msg.author.id == disctx.cache.read().user.id {
+        return;
+    }
+
+    let mut typ = HandleType::None;
+
+    match msg.channel_id.to_channel(disctx) {
+        Err(e) => {
+            warn!("failed to determine channel type for incoming message: {}", e);
+            return;
+        }
+        Ok(c) => match c {
+            channel::Channel::Private(_) => typ |= HandleType::Private,
+            channel::Channel::Group(_) => typ |= HandleType::Group,
+            channel::Channel::Guild(_) => typ |= HandleType::Public,
+            _ => return,
+        },
+    }
+
+    let source = Source::Adapter(Arc::new(DiscordSource {
+        user: msg.author,
+        channel: msg.channel_id,
+        guild: msg.guild_id,
+        roles: msg.member.map(|m| m.roles).unwrap_or_default(),
+    }));
+
+    let event = |typ, message| Event {
+        source: source.clone(),
+        kind: EventKind::Message { typ, message },
+    };
+
+    if !msg.content.is_empty() {
+        sink(event(HandleType::PlainMsg | typ, msg.content.clone()));
+    }
+    for att in msg.attachments {
+        sink(event(HandleType::Attachment | typ, att.proxy_url));
+    }
+    if msg.content.is_empty() {
+        for embed in msg.embeds {
+            if embed.title.is_none() && embed.description.is_none() {
+                // probably just a link, skip it
+                continue;
+            }
+
+            let mut data = vec![];
+            if let Some(author) = embed.author {
+                if let Some(url) = author.url {
+                    data.push(format!("{} <{}>", author.name, url));
+                } else {
+                    data.push(author.name);
+                }
+            }
+            if let Some(title) = embed.title {
+                if let Some(url) = embed.url {
+                    data.push(format!("{title} <{url}>"));
+                } else {
+                    data.push(title);
+                }
+            }
+            if let Some(description) = embed.description {
+                data.append(&mut description.split('\n').map(str::to_string).collect());
+            }
+            for field in embed.fields {
+                if field.inline {
+                    data.push(format!("{}: {}", field.name, field.value.replace('\n', "\t")));
+                } else {
+                    data.push(format!("{}:", field.name));
+                    for line in field.value.split('\n') {
+                        data.push(format!("\t{line}"));
+                    }
+                }
+            }
+
+            if data.is_empty() {
+                continue;
+            }
+
+            let mut spans = vec![];
+
+            if data.len() == 1 {
+                spans.push(format!("│ {}", data.remove(0)));
+            } else {
+                spans.push(format!("╽ {}", data.remove(0)));
+                let lastline = data.remove(data.len() - 1);
+                for line in data {
+                    spans.push(format!("┃ {line}"));
+                }
+                spans.push(format!("╿ {lastline}"));
+            }
+
+            sink(event(HandleType::Embed | typ, spans.join("\n")));
+        }
+    }
+}
+
+pub struct DiscordSource {
+    user: ser::User,
+    channel: ser::ChannelId,
+    guild: Option<ser::GuildId>,
+    roles: Vec<ser::RoleId>,
+}
+
+impl types::Source for DiscordSource {
+    fn user_string(&self) -> Cow<str> {
+        format!("{:?}:{}", self.guild.map(|g| *g.as_u64()), self.user.id.as_u64()).into()
+    }
+
+    fn user_pretty(&self) -> Cow<str> {
+        (&self.user.name).into()
+    }
+
+    fn channel_string(&self) -> Cow<str> {
+        format!(
+            "dis:{}:{}",
+            self.guild
+                .map(|g| format!("{}", *g.as_u64()))
+                .unwrap_or_else(|| "none".to_string()),
+            self.channel.as_u64()
+        )
+        .into()
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        Some((
+            self.guild.map(|g| *g.as_u64()),
+            *self.channel.as_u64(),
+            *self.user.id.as_u64(),
+        ))
+    }
+
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        None
+    }
+}
+
+impl AdapterSource for DiscordSource {
+    fn subjects(&self) -> Vec<String> {
+        let mut subjects = vec![format!("dis:{}", self.user.id.as_u64())];
+        if let Some(guild) = self.guild {
+            for role in &self.roles {
+                subjects.push(format!("dis-role:{}:{}", guild.as_u64(), role.as_u64()));
+            }
+        }
+        subjects
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,573 @@
+use ::irc::client::ext::ClientExt;
+use ::irc::client::prelude as irc;
+use ::irc::client::prelude::Client;
+use ::irc::proto::CapSubCommand;
+use parking_lot::{Mutex, RwLock};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use super::{Adapter, AdapterSource, Event, EventKind, EventSink};
+use crate::config;
+use crate::context::Source;
+use crate::message;
+use crate::ratelimit::SendQueue;
+use rustbot::prelude::*;
+use rustbot::types;
+
+// Capabilities we use if the server offers them; "sasl" is also requested if it's configured.
+const WANTED_CAPS: &[&str] = &["multi-prefix", "account-notify", "extended-join", "account-tag"];
+
+// SASL responses longer than this are split across several AUTHENTICATE messages.
+const SASL_CHUNK_LEN: usize = 400;
+
+pub struct IrcAdapter {
+    config: config::Irc,
+    client: RwLock<Option<Arc<irc::IrcClient>>>,
+    queue: Arc<SendQueue>,
+    caps: Mutex<Negotiation>,
+    // lowercased nick -> services account, for users we've seen log in or join
+    accounts: RwLock<BTreeMap<String, String>>,
+    // lowercased nick -> prefix, for users we've seen do anything; see find_user
+    users: RwLock<BTreeMap<String, Prefix>>,
+}
+
+#[derive(Default)]
+struct Negotiation {
+    offered: Vec<String>,
+    enabled: BTreeSet<String>,
+}
+
+impl IrcAdapter {
+    pub fn new(config: config::Irc) -> Self {
+        Self {
+            queue: Arc::new(SendQueue::new(&config.id, config.send_limit)),
+            config,
+            client: RwLock::new(None),
+            caps: Mutex::new(Negotiation::default()),
+            accounts: RwLock::new(BTreeMap::new()),
+            users: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    // The services account the sender of a message is logged in to, if we know it.
+    pub fn account_for(&self, msg: &irc::Message) -> Option<String> {
+        if self.caps.lock().enabled.contains("account-tag") {
+            // the tag is authoritative; if it's missing, they're not logged in
+            return msg
+                .tags
+                .iter()
+                .flatten()
+                .find(|tag| tag.0 == "account")
+                .and_then(|tag| tag.1.clone());
+        }
+
+        let nick = msg.source_nickname()?;
+        self.accounts.read().get(&nick.to_ascii_lowercase()).cloned()
+    }
+
+    // Keeps track of which account each user is logged in to, from account-notify and
+    // extended-join, for servers that don't tag every message with it.
+    pub fn track_account(&self, msg: &irc::Message) {
+        let nick = match msg.source_nickname() {
+            Some(nick) => nick.to_ascii_lowercase(),
+            None => return,
+        };
+
+        let mut accounts = self.accounts.write();
+        let mut set = |account: &str| {
+            if account == "*" {
+                accounts.remove(&nick);
+            } else {
+                accounts.insert(nick.clone(), account.to_string());
+            }
+        };
+
+        match &msg.command {
+            irc::Command::ACCOUNT(account) => set(account),
+            irc::Command::JOIN(_, Some(account), Some(_)) if self.caps.lock().enabled.contains("extended-join") => {
+                set(account)
+            }
+            irc::Command::NICK(new) => {
+                if let Some(account) = accounts.remove(&nick) {
+                    accounts.insert(new.to_ascii_lowercase(), account);
+                }
+            }
+            irc::Command::QUIT(_) => {
+                accounts.remove(&nick);
+            }
+            _ => {}
+        }
+    }
+
+    // Keeps the prefix of the sender of a message, so they can be found by nick.
+    pub fn track_user(&self, msg: &irc::Message) {
+        let (nick, user, host) = match parse_prefix(msg.prefix.clone()) {
+            Some(Prefix::User { nick, user, host }) => (nick, user, host),
+            _ => return,
+        };
+
+        let mut users = self.users.write();
+        match &msg.command {
+            irc::Command::NICK(new) => {
+                users.remove(&nick.to_ascii_lowercase());
+                users.insert(
+                    new.to_ascii_lowercase(),
+                    Prefix::User {
+                        nick: new.clone(),
+                        user,
+                        host,
+                    },
+                );
+            }
+            irc::Command::QUIT(_) => {
+                users.remove(&nick.to_ascii_lowercase());
+            }
+            _ => {
+                users.insert(nick.to_ascii_lowercase(), Prefix::User { nick, user, host });
+            }
+        }
+    }
+
+    // Works out what to send in reply to a message during capability negotiation and SASL, which
+    // hold up registration until we send CAP END.
+    pub fn negotiate(&self, command: &irc::Command) -> Vec<irc::Command> {
+        let sasl = self.config.sasl.as_ref();
+        let mut caps = self.caps.lock();
+        let end = || vec![irc::Command::CAP(None, CapSubCommand::END, None, None)];
+
+        match command {
+            irc::Command::CAP(_, CapSubCommand::LS, more, Some(offered)) => {
+                // values (e.g. "sasl=PLAIN,EXTERNAL") are only informational for what we use
+                caps.offered.extend(
+                    offered
+                        .split_whitespace()
+                        .map(|c| c.split('=').next().unwrap().to_string()),
+                );
+                if more.as_deref() == Some("*") {
+                    // there's another line of capabilities to come
+                    return vec![];
+                }
+
+                let mut req: Vec<&str> = WANTED_CAPS
+                    .iter()
+                    .copied()
+                    .filter(|c| caps.offered.iter().any(|o| o == c))
+                    .collect();
+                if sasl.is_some() {
+                    if caps.offered.iter().any(|o| o == "sasl") {
+                        req.push("sasl");
+                    } else {
+                        error!(
+                            "{}: server doesn't support SASL; continuing without logging in",
+                            self.config.id
+                        );
+                    }
+                }
+
+                if req.is_empty() {
+                    end()
+                } else {
+                    vec![irc::Command::CAP(None, CapSubCommand::REQ, None, Some(req.join(" ")))]
+                }
+            }
+            irc::Command::CAP(_, CapSubCommand::ACK, _, Some(acked)) => {
+                caps.enabled.extend(
+                    acked
+                        .split_whitespace()
+                        .filter(|c| !c.starts_with('-'))
+                        .map(str::to_string),
+                );
+                match sasl {
+                    Some(sasl) if caps.enabled.contains("sasl") => {
+                        let mechanism = match sasl {
+                            config::Sasl::Plain { .. } => "PLAIN",
+                            config::Sasl::External => "EXTERNAL",
+                        };
+                        vec![irc::Command::AUTHENTICATE(mechanism.to_string())]
+                    }
+                    _ => end(),
+                }
+            }
+            irc::Command::CAP(_, CapSubCommand::NAK, _, rejected) => {
+                warn!("{}: server rejected capabilities {:?}", self.config.id, rejected);
+                end()
+            }
+            irc::Command::AUTHENTICATE(challenge) if challenge == "+" => match sasl {
+                Some(config::Sasl::Plain { user, password }) => {
+                    let response = base64::encode(format!("{user}\0{user}\0{password}"));
+                    let mut lines: Vec<irc::Command> = response
+                        .as_bytes()
+                        .chunks(SASL_CHUNK_LEN)
+                        .map(|chunk| irc::Command::AUTHENTICATE(String::from_utf8_lossy(chunk).into_owned()))
+                        .collect();
+                    if response.len() % SASL_CHUNK_LEN == 0 {
+                        // tells the server there's no more to come
+                        lines.push(irc::Command::AUTHENTICATE("+".to_string()));
+                    }
+                    lines
+                }
+                Some(config::Sasl::External) => vec![irc::Command::AUTHENTICATE("+".to_string())],
+                None => vec![],
+            },
+            irc::Command::Response(irc::Response::RPL_SASLSUCCESS, _, _) => {
+                info!("{}: logged in with SASL", self.config.id);
+                end()
+            }
+            irc::Command::Response(
+                irc::Response::ERR_SASLFAIL
+                | irc::Response::ERR_SASLTOOLONG
+                | irc::Response::ERR_SASLABORT
+                | irc::Response::ERR_SASLALREADY
+                | irc::Response::ERR_NICKLOCKED,
+                _,
+                message,
+            ) => {
+                error!(
+                    "{}: SASL login failed ({}); continuing without it",
+                    self.config.id,
+                    message.as_deref().unwrap_or("no reason given")
+                );
+                end()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn client(&self) -> Result<Arc<irc::IrcClient>> {
+        match &*self.client.read() {
+            Some(client) => Ok(Arc::clone(client)),
+            None => bail!("{} is not connected", self.config.id),
+        }
+    }
+
+    // Messages go out through the queue, so a long reply doesn't get the bot kicked for flooding.
+    pub fn send_privmsg(&self, channel: &str, message: &str) -> Result<()> {
+        let client = self.client()?;
+        let channel = channel.to_string();
+        let message = str_max_bytes(message, 490).to_string();
+        self.queue.push(Box::new(move || {
+            client.send_privmsg(&channel, &message).map_err(from_irc)
+        }))
+    }
+
+    pub fn send_raw(&self, line: &str) -> Result<()> {
+        let client = self.client()?;
+        let line = str_max_bytes(line, 510).to_string();
+        self.queue
+            .push(Box::new(move || client.send(line.as_str()).map_err(from_irc)))
+    }
+}
+
+impl Adapter for IrcAdapter {
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn descriptor(&self) -> String {
+        format!("IRC: {} ({}:{})", self.config.id, self.config.server, self.config.port)
+    }
+
+    fn network(&self) -> &'static str {
+        "irc"
+    }
+
+    fn connect(&self, bot: &dyn Bot, sink: EventSink) -> Result<()> {
+        let channels: Vec<String> = bot
+            .sql()?
+            .query(
+                "SELECT channel FROM irc_channels WHERE config_id = $1",
+                &[&self.config.id],
+            )?
+            .iter()
+            .map(|row| row.try_get(0))
+            .collect::<Result<_>>()?;
+
+        let c = &self.config;
+        let client = Arc::new(
+            irc::IrcClient::from_config(irc::Config {
+                nickname: Some(c.nick.clone()),
+                username: Some(c.user.clone()),
+                realname: Some(c.real.clone()),
+                server: Some(c.server.clone()),
+                port: Some(c.port),
+                use_ssl: Some(c.ssl),
+                channels: Some(channels),
+                password: c.pass.clone(),
+                client_cert_path: c.client_cert.clone(),
+                client_cert_pass: c.client_cert_pass.clone(),
+                ..Default::default()
+            })
+            .map_err(from_irc)?,
+        );
+
+        *self.caps.lock() = Negotiation::default();
+        self.accounts.write().clear();
+        self.users.write().clear();
+
+        // like ClientExt::identify, but without ending capability negotiation straight away
+        client
+            .send(irc::Command::CAP(
+                None,
+                CapSubCommand::LS,
+                Some("302".to_string()),
+                None,
+            ))
+            .map_err(from_irc)?;
+        if let Some(pass) = &c.pass {
+            client.send(irc::Command::PASS(pass.clone())).map_err(from_irc)?;
+        }
+        client.send(irc::Command::NICK(c.nick.clone())).map_err(from_irc)?;
+        client
+            .send(irc::Command::USER(c.user.clone(), "0".to_string(), c.real.clone()))
+            .map_err(from_irc)?;
+
+        *self.client.write() = Some(Arc::clone(&client));
+        info!("connect: {}", self.descriptor());
+
+        client
+            .for_each_incoming(|irc_msg| {
+                for reply in self.negotiate(&irc_msg.command) {
+                    if let Err(e) = client.send(reply) {
+                        warn!("{}: failed to send during negotiation: {}", self.config.id, e);
+                    }
+                }
+                let account = self.account_for(&irc_msg);
+                self.track_account(&irc_msg);
+                self.track_user(&irc_msg);
+
+                let sink = Arc::clone(&sink);
+                let client = Arc::clone(&client);
+                rayon::spawn(move || {
+                    if let Some(event) = incoming(client.current_nickname(), irc_msg, account) {
+                        sink(event);
+                    }
+                });
+            })
+            .map_err(from_irc)
+    }
+
+    fn send(&self, target: &str, message: Message) -> Result<()> {
+        for line in message::format_irc(message)? {
+            self.send_privmsg(target, &line)?;
+        }
+        Ok(())
+    }
+
+    fn reply(&self, source: &dyn AdapterSource, message: Message) -> Result<()> {
+        let source = match source.as_any().downcast_ref::<IrcSource>() {
+            Some(s) => s,
+            None => bail!("IRC adapter asked to reply to a non-IRC source"),
+        };
+
+        if let Some(Prefix::User { nick, .. }) = &source.prefix {
+            match &source.channel {
+                None => {
+                    for msg in message::format_irc(message)? {
+                        self.send_privmsg(nick, &msg)?;
+                    }
+                }
+
+                Some(ch) => {
+                    for msg in message::format_irc(message)? {
+                        self.send_privmsg(ch, &format!("{nick}: {msg}"))?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Users are found among those we've seen, by nick; channels needn't be looked up, as their
+    // names are the same for everyone.
+    fn find_user(&self, _source: &dyn AdapterSource, user: &Mention) -> Result<Option<String>> {
+        Ok(match user {
+            Mention::Name(nick) => self.users.read().get(&nick.to_ascii_lowercase()).map(Prefix::to_string),
+            Mention::Id(_) => None,
+        })
+    }
+
+    fn find_channel(&self, _source: &dyn AdapterSource, channel: &ChannelRef) -> Result<Option<String>> {
+        Ok(match channel {
+            ChannelRef::Name(name) => Some(format!("irc:{name}")),
+            ChannelRef::Id(_) => None,
+        })
+    }
+
+    fn send_queues(&self) -> Vec<Arc<SendQueue>> {
+        vec![Arc::clone(&self.queue)]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub fn incoming(bot_name: &str, irc_msg: irc::Message, account: Option<String>) -> Option<Event> {
+    let prefix = parse_prefix(irc_msg.prefix);
+    // the bot's own joins and so on aren't interesting to modules, and would have them greeting it
+    if let Some(Prefix::User { nick, .. }) = &prefix {
+        if nick == bot_name {
+            return None;
+        }
+    }
+
+    let source = |prefix: Option<Prefix>, channel: Option<String>| {
+        Source::Adapter(Arc::new(IrcSource {
+            prefix,
+            channel,
+            account: account.clone(),
+        }))
+    };
+    let other = |channel: Option<String>, event: types::Event| {
+        Some(Event {
+            source: source(prefix.clone(), channel),
+            kind: EventKind::Other(event),
+        })
+    };
+
+    match irc_msg.command {
+        irc::Command::PRIVMSG(channel, message) => {
+            let mut typ = HandleType::PlainMsg;
+
+            if channel == bot_name {
+                typ |= HandleType::Private;
+            } else {
+                typ |= HandleType::Public;
+            }
+
+            Some(Event {
+                source: source(prefix.clone(), if channel == bot_name { None } else { Some(channel) }),
+                kind: EventKind::Message { typ, message },
+            })
+        }
+        irc::Command::JOIN(channel, _, _) => other(Some(channel), types::Event::Join),
+        irc::Command::PART(channel, reason) => other(Some(channel), types::Event::Part { reason }),
+        irc::Command::QUIT(reason) => other(None, types::Event::Quit { reason }),
+        irc::Command::KICK(channel, target, reason) => other(Some(channel), types::Event::Kick { target, reason }),
+        irc::Command::TOPIC(channel, Some(topic)) => other(Some(channel), types::Event::Topic { topic }),
+        irc::Command::NICK(new) => match prefix.clone() {
+            Some(Prefix::User { nick, user, host }) => Some(Event {
+                source: source(Some(Prefix::User { nick: new, user, host }), None),
+                kind: EventKind::Other(types::Event::Nick { old: nick }),
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_prefix(prefix: Option<String>) -> Option<Prefix> {
+    match prefix {
+        None => None,
+        Some(s) => {
+            if !s.contains('!') {
+                Some(Prefix::Server(s))
+            } else {
+                let ss = s.clone();
+                let nr: Vec<&str> = ss.splitn(2, '!').collect();
+                if !nr[1].contains('@') {
+                    Some(Prefix::Server(s))
+                } else {
+                    let uh: Vec<&str> = nr[1].splitn(2, '@').collect();
+                    Some(Prefix::User {
+                        nick: nr[0].to_string(),
+                        user: uh[0].to_string(),
+                        host: uh[1].to_string(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn str_max_bytes(s: &str, n: usize) -> &str {
+    if s.len() <= n {
+        return s;
+    }
+
+    let (last_char_inside, _) = s.char_indices().take_while(|(i, _)| *i <= n).last().unwrap();
+    &s[..last_char_inside]
+}
+
+fn from_irc(e: ::irc::error::IrcError) -> Error {
+    Error::msg(format!("{e}"))
+}
+
+pub struct IrcSource {
+    prefix: Option<Prefix>,
+    channel: Option<String>,
+    account: Option<String>,
+}
+
+impl types::Source for IrcSource {
+    fn user_string(&self) -> Cow<str> {
+        if let Some(prefix) = &self.prefix {
+            format!("{prefix}").into()
+        } else {
+            "none".into()
+        }
+    }
+
+    fn user_pretty(&self) -> Cow<str> {
+        match &self.prefix {
+            Some(Prefix::User { nick, .. }) => nick.into(),
+            Some(Prefix::Server(s)) => s.into(),
+            None => "???".into(),
+        }
+    }
+
+    fn channel_string(&self) -> Cow<str> {
+        if let Some(channel) = &self.channel {
+            format!("irc:{channel}").into()
+        } else {
+            "irc:query".into()
+        }
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        None
+    }
+
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        match &self.prefix {
+            Some(Prefix::User { nick, .. }) => Some((self.channel.clone(), nick.clone())),
+            Some(Prefix::Server(s)) => Some((self.channel.clone(), s.clone())),
+            None => None,
+        }
+    }
+}
+
+impl AdapterSource for IrcSource {
+    fn subjects(&self) -> Vec<String> {
+        let mut subjects = match &self.prefix {
+            Some(Prefix::User { nick, user, host }) => vec![format!("irc:{nick}!{user}@{host}")],
+            _ => vec![],
+        };
+        if let Some(account) = &self.account {
+            subjects.push(format!("irc-account:{account}"));
+        }
+        subjects
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Prefix {
+    Server(String),
+    User { nick: String, user: String, host: String },
+}
+
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Server(s) => write!(f, "{s}"),
+            Self::User { nick, user, host } => write!(f, "{nick}!{user}@{host}"),
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Adapter, AdapterSource, Event, EventKind, EventSink};
+use crate::config;
+use crate::context::Source;
+use crate::message;
+use rustbot::prelude::*;
+use rustbot::types;
+
+// How long the homeserver may hold a sync request open before returning an empty response.
+const SYNC_TIMEOUT_MS: u64 = 30_000;
+
+pub struct MatrixAdapter {
+    config: config::Matrix,
+    http: reqwest::blocking::Client,
+    joined: Mutex<BTreeSet<String>>,
+    // room ID -> how many members it has, to tell direct messages apart
+    members: Mutex<BTreeMap<String, u64>>,
+    // room alias -> ID, for the rooms in the database given by alias
+    aliases: Mutex<BTreeMap<String, String>>,
+    txn_prefix: String,
+    txn_counter: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct SyncResponse {
+    next_batch: String,
+    #[serde(default)]
+    rooms: SyncRooms,
+}
+
+#[derive(Deserialize, Default)]
+struct SyncRooms {
+    #[serde(default)]
+    join: BTreeMap<String, JoinedRoom>,
+    #[serde(default)]
+    leave: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JoinedRoom {
+    #[serde(default)]
+    timeline: Timeline,
+    #[serde(default)]
+    summary: RoomSummary,
+}
+
+// only sent when it's changed since the last sync
+#[derive(Deserialize, Default)]
+struct RoomSummary {
+    #[serde(rename = "m.joined_member_count")]
+    joined_member_count: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+struct Timeline {
+    #[serde(default)]
+    events: Vec<RoomEvent>,
+}
+
+#[derive(Deserialize)]
+struct RoomEvent {
+    #[serde(rename = "type")]
+    typ: String,
+    #[serde(default)]
+    sender: String,
+    #[serde(default)]
+    content: RoomMessage,
+}
+
+#[derive(Deserialize, Default)]
+struct RoomMessage {
+    #[serde(default)]
+    msgtype: String,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct JoinResponse {
+    room_id: String,
+}
+
+#[derive(Deserialize)]
+struct AliasResponse {
+    room_id: String,
+}
+
+#[derive(Deserialize)]
+struct JoinedMembersResponse {
+    joined: BTreeMap<String, serde_json::Value>,
+}
+
+impl MatrixAdapter {
+    pub fn new(config: config::Matrix) -> Result<Self> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_millis(SYNC_TIMEOUT_MS * 2))
+            .build()?;
+
+        Ok(Self {
+            config,
+            http,
+            joined: Mutex::new(BTreeSet::new()),
+            members: Mutex::new(BTreeMap::new()),
+            aliases: Mutex::new(BTreeMap::new()),
+            txn_prefix: format!("rustbot{}", SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()),
+            txn_counter: AtomicU64::new(0),
+        })
+    }
+
+    fn url(&self, path: &[&str]) -> Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(&self.config.homeserver)?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("invalid homeserver URL {:?}", self.config.homeserver))?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"].iter().chain(path));
+        Ok(url)
+    }
+
+    fn post(&self, path: &[&str], body: &serde_json::Value) -> Result<String> {
+        Ok(self
+            .http
+            .post(self.url(path)?)
+            .bearer_auth(&self.config.access_token)
+            .json(body)
+            .send()?
+            .error_for_status()?
+            .text()?)
+    }
+
+    // Like post, but a GET, and None if there's no such thing.
+    fn get(&self, path: &[&str]) -> Result<Option<String>> {
+        let resp = self
+            .http
+            .get(self.url(path)?)
+            .bearer_auth(&self.config.access_token)
+            .send()?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.text()?))
+    }
+
+    // Performs a single sync, passing any new room messages to the sink, and returns the token
+    // to pass as `since` next time. Without a sink, the events are only used to find out which
+    // rooms we're in; this is used for the initial sync so we don't respond to old messages.
+    pub fn sync_once(&self, since: Option<&str>, sink: Option<&EventSink>) -> Result<String> {
+        let mut req = self
+            .http
+            .get(self.url(&["sync"])?)
+            .bearer_auth(&self.config.access_token);
+        req = match since {
+            Some(since) => req.query(&[("since", since), ("timeout", &SYNC_TIMEOUT_MS.to_string())]),
+            None => req.query(&[("timeout", "0")]),
+        };
+
+        let resp: SyncResponse = serde_json::from_str(&req.send()?.error_for_status()?.text()?)?;
+
+        {
+            let mut joined = self.joined.lock();
+            let mut members = self.members.lock();
+            for room in resp.rooms.leave.keys() {
+                joined.remove(room);
+                members.remove(room);
+            }
+            for (room, data) in &resp.rooms.join {
+                joined.insert(room.clone());
+                if let Some(n) = data.summary.joined_member_count {
+                    members.insert(room.clone(), n);
+                }
+            }
+        }
+
+        if let Some(sink) = sink {
+            for (room, data) in resp.rooms.join {
+                let typ = match self.is_direct(&room) {
+                    Ok(true) => HandleType::Private,
+                    Ok(false) => HandleType::Public,
+                    Err(e) => {
+                        warn!("{}: failed to count the members of {}: {}", self.config.id, room, e);
+                        HandleType::Public
+                    }
+                };
+                for event in data.timeline.events {
+                    if event.typ != "m.room.message" || event.sender == self.config.user {
+                        continue;
+                    }
+                    // notices are sent by other bots and should never be responded to
+                    if event.content.msgtype != "m.text" && event.content.msgtype != "m.emote" {
+                        continue;
+                    }
+
+                    let sink = Arc::clone(sink);
+                    let source = MatrixSource {
+                        sender: event.sender,
+                        room: room.clone(),
+                    };
+                    let message = event.content.body;
+                    rayon::spawn(move || {
+                        sink(Event {
+                            source: Source::Adapter(Arc::new(source)),
+                            kind: EventKind::Message {
+                                typ: HandleType::PlainMsg | typ,
+                                message,
+                            },
+                        })
+                    });
+                }
+            }
+        }
+
+        Ok(resp.next_batch)
+    }
+
+    // Whether a room is a direct message: just us and one other user.
+    fn is_direct(&self, room: &str) -> Result<bool> {
+        if let Some(n) = self.members.lock().get(room) {
+            return Ok(*n <= 2);
+        }
+        let n = match self.get(&["rooms", room, "joined_members"])? {
+            Some(resp) => serde_json::from_str::<JoinedMembersResponse>(&resp)?.joined.len() as u64,
+            None => bail!("no such room"),
+        };
+        self.members.lock().insert(room.to_string(), n);
+        Ok(n <= 2)
+    }
+
+    // The ID of the room with the given alias, if there is one.
+    fn resolve_alias(&self, alias: &str) -> Result<Option<String>> {
+        if let Some(id) = self.aliases.lock().get(alias) {
+            return Ok(Some(id.clone()));
+        }
+        let id = match self.get(&["directory", "room", alias])? {
+            Some(resp) => serde_json::from_str::<AliasResponse>(&resp)?.room_id,
+            None => return Ok(None),
+        };
+        self.aliases.lock().insert(alias.to_string(), id.clone());
+        Ok(Some(id))
+    }
+
+    // Joins any rooms in the database we're not in yet, and leaves any we're in that aren't.
+    fn sync_rooms(&self, bot: &dyn Bot) -> Result<()> {
+        let rooms: Vec<String> = bot
+            .sql()?
+            .query("SELECT room FROM matrix_rooms WHERE config_id = $1", &[&self.config.id])?
+            .iter()
+            .map(|row| row.try_get(0))
+            .collect::<Result<_>>()?;
+
+        // rooms can be given by alias, but we only know the IDs of those we're in
+        let mut wanted = BTreeSet::new();
+        for room in rooms {
+            if !room.starts_with('#') {
+                wanted.insert(room);
+                continue;
+            }
+            match self.resolve_alias(&room)? {
+                Some(id) => {
+                    wanted.insert(id);
+                }
+                None => warn!("{}: no room has the alias {}", self.config.id, room),
+            }
+        }
+
+        let joined = self.joined.lock().clone();
+
+        for room in wanted.difference(&joined) {
+            match self.post(&["join", room], &serde_json::json!({})) {
+                Ok(resp) => {
+                    let resp: JoinResponse = serde_json::from_str(&resp)?;
+                    info!("{}: joined {}", self.config.id, resp.room_id);
+                    self.joined.lock().insert(resp.room_id);
+                }
+                Err(e) => warn!("{}: failed to join {}: {}", self.config.id, room, e),
+            }
+        }
+
+        for room in joined.difference(&wanted) {
+            match self.post(&["rooms", room, "leave"], &serde_json::json!({})) {
+                Ok(_) => {
+                    info!("{}: left {}", self.config.id, room);
+                    self.joined.lock().remove(room);
+                }
+                Err(e) => warn!("{}: failed to leave {}: {}", self.config.id, room, e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Adapter for MatrixAdapter {
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn descriptor(&self) -> String {
+        format!("Matrix: {} ({})", self.config.id, self.config.homeserver)
+    }
+
+    fn network(&self) -> &'static str {
+        "mx"
+    }
+
+    fn connect(&self, bot: &dyn Bot, sink: EventSink) -> Result<()> {
+        self.joined.lock().clear();
+        self.members.lock().clear();
+        self.aliases.lock().clear();
+        let mut since = self.sync_once(None, None)?;
+        info!("connect: {}", self.descriptor());
+
+        loop {
+            self.sync_rooms(bot)?;
+            since = self.sync_once(Some(&since), Some(&sink))?;
+        }
+    }
+
+    fn send(&self, target: &str, message: Message) -> Result<()> {
+        let (body, formatted_body) = message::format_matrix(message)?;
+        let txn = format!(
+            "{}-{}",
+            self.txn_prefix,
+            self.txn_counter.fetch_add(1, Ordering::SeqCst)
+        );
+
+        self.http
+            .put(self.url(&["rooms", target, "send", "m.room.message", &txn])?)
+            .bearer_auth(&self.config.access_token)
+            .json(&serde_json::json!({
+                "msgtype": "m.notice",
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": formatted_body,
+            }))
+            .send()?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    // Users are given by Matrix ID, on the sender's server if none is given, and rooms by ID or
+    // alias; the homeserver is asked whether they exist.
+    fn find_user(&self, source: &dyn AdapterSource, user: &Mention) -> Result<Option<String>> {
+        let source = match source.as_any().downcast_ref::<MatrixSource>() {
+            Some(s) => s,
+            None => bail!("Matrix adapter asked to find a user for a non-Matrix source"),
+        };
+        let id = match user {
+            Mention::Name(name) if name.contains(':') => format!("@{name}"),
+            Mention::Name(name) => match source.sender.split_once(':') {
+                Some((_, server)) => format!("@{name}:{server}"),
+                None => return Ok(None),
+            },
+            Mention::Id(_) => return Ok(None),
+        };
+        Ok(self.get(&["profile", &id])?.map(|_| id))
+    }
+
+    fn find_channel(&self, _source: &dyn AdapterSource, channel: &ChannelRef) -> Result<Option<String>> {
+        Ok(match channel {
+            ChannelRef::Name(alias) if alias.starts_with('#') => {
+                self.resolve_alias(alias)?.map(|id| format!("mx:{id}"))
+            }
+            ChannelRef::Name(id) if id.starts_with('!') => Some(format!("mx:{id}")),
+            _ => None,
+        })
+    }
+
+    fn reply(&self, source: &dyn AdapterSource, message: Message) -> Result<()> {
+        let source = match source.as_any().downcast_ref::<MatrixSource>() {
+            Some(s) => s,
+            None => bail!("Matrix adapter asked to reply to a non-Matrix source"),
+        };
+
+        self.send(&source.room, message)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct MatrixSource {
+    sender: String,
+    room: String,
+}
+
+impl types::Source for MatrixSource {
+    fn user_string(&self) -> Cow<str> {
+        (&self.sender).into()
+    }
+
+    fn user_pretty(&self) -> Cow<str> {
+        // @localpart:server
+        let localpart = self.sender.trim_start_matches('@');
+        match localpart.split_once(':') {
+            Some((localpart, _)) => localpart.into(),
+            None => localpart.into(),
+        }
+    }
+
+    fn channel_string(&self) -> Cow<str> {
+        format!("mx:{}", self.room).into()
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        None
+    }
+
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        None
+    }
+}
+
+impl AdapterSource for MatrixSource {
+    fn subjects(&self) -> Vec<String> {
+        vec![format!("mx:{}", self.sender)]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
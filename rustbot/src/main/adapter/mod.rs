@@ -0,0 +1,81 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::context::Source;
+use crate::ratelimit::SendQueue;
+use rustbot::prelude::*;
+use rustbot::types;
+
+pub mod console;
+pub mod discord;
+pub mod irc;
+pub mod matrix;
+
+// Something that happened on some chat network, with everything network-specific hidden behind
+// the source.
+pub struct Event {
+    pub source: Source,
+    pub kind: EventKind,
+}
+
+pub enum EventKind {
+    // an incoming message, for commands and message handlers
+    Message { typ: HandleType, message: String },
+    // anything else, for modules' event handlers
+    Other(types::Event),
+}
+
+// Adapters pass each incoming event to the sink; it's called on whichever thread the adapter
+// received the event on, so adapters are expected to spawn off their network thread first.
+pub type EventSink = Arc<dyn Fn(Event) + Send + Sync>;
+
+pub trait Adapter: Send + Sync {
+    // The config ID this adapter was created for.
+    fn config_id(&self) -> &str;
+
+    // Human-readable description of the connection, used for thread names and logging.
+    fn descriptor(&self) -> String;
+
+    // The prefix used for this network in channel strings, e.g. "irc" for "irc:#channel".
+    fn network(&self) -> &'static str;
+
+    // Connects to the network and passes incoming events to `sink` until the connection is lost.
+    // Returning (with or without an error) causes a reconnect after a backoff.
+    fn connect(&self, bot: &dyn Bot, sink: EventSink) -> Result<()>;
+
+    // Sends a message to a target; the target is the part of a channel string after the network
+    // prefix, e.g. "#channel" for "irc:#channel".
+    fn send(&self, target: &str, message: Message) -> Result<()>;
+
+    // Replies to the source of an event; the source is always one created by this adapter.
+    fn reply(&self, source: &dyn AdapterSource, message: Message) -> Result<()>;
+
+    // The user or channel an argument of a command from the source refers to, as the adapter's
+    // sources give them in user_string and channel_string, if there's one by that name or ID.
+    fn find_user(&self, _source: &dyn AdapterSource, _user: &Mention) -> Result<Option<String>> {
+        Ok(None)
+    }
+    fn find_channel(&self, _source: &dyn AdapterSource, _channel: &ChannelRef) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    // The queues outgoing messages wait in, if the adapter rate-limits them.
+    fn send_queues(&self) -> Vec<Arc<SendQueue>> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+pub trait AdapterSource: types::Source + Send + Sync {
+    // The subjects permissions can be granted to for this source, e.g. "irc:nick!user@host"; see
+    // crate::perms.
+    fn subjects(&self) -> Vec<String>;
+
+    // Permissions the source has regardless of grants, e.g. from the adapter's config.
+    fn implicit_perms(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
@@ -0,0 +1,265 @@
+// Command aliases. An alias maps a name to one or more target commands (separated by ';' to run
+// several), optionally rewriting the arguments with a JSON transform on the way. Like grants,
+// aliases can be limited to configs and channels matching glob patterns; the most specific match
+// wins.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use rustbot::cache::Cached;
+use rustbot::perms::{glob, specificity};
+use rustbot::prelude::*;
+use serde::Deserialize;
+
+// The most commands one alias can expand to, counting those of any aliases it runs.
+const MAX_EXPANSION: usize = 8;
+
+#[derive(Clone)]
+pub struct Alias {
+    pub name: String,
+    pub config_id: String,
+    pub channel: String,
+    pub target: String,
+    pub transform: Option<ArgumentTransform>,
+}
+
+impl Alias {
+    fn matches(&self, config_id: &str, channel: &str) -> bool {
+        glob(&self.config_id, config_id) && glob(&self.channel, channel)
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = &str> {
+        self.target.split(';').map(str::trim).filter(|t| !t.is_empty())
+    }
+}
+
+pub struct Aliases {
+    aliases: Cached<Vec<Alias>>,
+}
+
+impl Aliases {
+    pub fn new() -> Self {
+        Self {
+            aliases: Cached::new(&["aliases"]),
+        }
+    }
+
+    // The alias that applies to a name in the given channel, if any.
+    pub fn get(&self, bot: &dyn Bot, name: &str, config_id: &str, channel: &str) -> Result<Option<Alias>> {
+        let aliases = self.aliases.get(bot, |db| {
+            let mut aliases = vec![];
+            for row in db.query("SELECT name, config_id, channel, target, transform FROM aliases", &[])? {
+                let name: String = row.try_get(0)?;
+                // transforms are parsed once here rather than every time the alias is used; they
+                // were checked when added, so only one edited by hand can fail
+                let transform: Option<String> = row.try_get(4)?;
+                let transform = match transform.as_deref().map(parse_transform).transpose() {
+                    Ok(t) => t,
+                    Err(e) => {
+                        warn!("skipping alias {}: {}", name, e);
+                        continue;
+                    }
+                };
+                aliases.push(Alias {
+                    name,
+                    config_id: row.try_get(1)?,
+                    channel: row.try_get(2)?,
+                    target: row.try_get(3)?,
+                    transform,
+                });
+            }
+            Ok(aliases)
+        })?;
+
+        Ok(aliases
+            .iter()
+            .filter(|a| a.name == name && a.matches(config_id, channel))
+            .max_by_key(|a| (specificity(&a.channel), specificity(&a.config_id)))
+            .cloned())
+    }
+
+    // Expands a command into the commands it stands for, with their arguments; anything that isn't
+    // an alias stands for itself.
+    pub fn resolve(
+        &self,
+        bot: &dyn Bot,
+        config_id: &str,
+        channel: &str,
+        cmd: &str,
+        args: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let mut out = vec![];
+        self.resolve_into(bot, config_id, channel, &mut vec![cmd.to_string()], args, &mut out)?;
+        Ok(out)
+    }
+
+    fn resolve_into(
+        &self,
+        bot: &dyn Bot,
+        config_id: &str,
+        channel: &str,
+        chain: &mut Vec<String>,
+        args: &str,
+        out: &mut Vec<(String, String)>,
+    ) -> Result<()> {
+        let name = chain.last().unwrap().clone();
+        let alias = match self.get(bot, &name, config_id, channel)? {
+            Some(alias) => alias,
+            None => {
+                if out.len() == MAX_EXPANSION {
+                    bail_user!("alias {:?} runs more than {} commands", chain[0], MAX_EXPANSION);
+                }
+                out.push((name, args.to_string()));
+                return Ok(());
+            }
+        };
+
+        let args = match &alias.transform {
+            Some(t) => t.apply(args)?,
+            None => args.to_string(),
+        };
+        for target in alias.targets() {
+            if chain.iter().any(|c| c == target) {
+                bail_user!("alias loop: {} -> {}", chain.join(" -> "), target);
+            }
+            chain.push(target.to_string());
+            self.resolve_into(bot, config_id, channel, chain, &args, out)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_transform(s: &str) -> Result<ArgumentTransform> {
+    match serde_json::from_str(s) {
+        Ok(t) => Ok(t),
+        Err(e) => bail_user!("invalid transform {:?}: {}", s, e),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentTransform {
+    RegexReplace {
+        #[serde(deserialize_with = "regex_from_str")]
+        find: Regex,
+        replace: String,
+        #[serde(deserialize_with = "opt_bool_from_int")]
+        global: Option<bool>,
+    },
+    ByIndex(Vec<Index>),
+    // fills in arguments that weren't given, by position
+    Defaults(Vec<String>),
+    // names the arguments in order, the last taking the rest of the line, and substitutes them for
+    // "{name}" in the text; "{name=default}" gives a default for when it's missing
+    Template {
+        params: Vec<String>,
+        text: String,
+    },
+    // applies several transforms in turn
+    Chain(Vec<ArgumentTransform>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Index {
+    Single(u64),
+    Multi(i64),
+    Literal(String),
+}
+
+impl ArgumentTransform {
+    pub fn apply(&self, args: &str) -> Result<String> {
+        Ok(match self {
+            ArgumentTransform::RegexReplace { find, replace, global } => {
+                let n = usize::from(!global.unwrap_or(false));
+                find.replacen(args, n, replace.as_str()).into_owned()
+            }
+            ArgumentTransform::ByIndex(t) => {
+                let indexed: Vec<_> = args.split(' ').collect();
+                let mut new_args = Vec::with_capacity(usize::max(5, 2 * indexed.len()));
+                for item in t.iter() {
+                    match item {
+                        Index::Single(0) => new_args.extend_from_slice(&indexed),
+                        Index::Single(n) => new_args.push(indexed.get((n - 1) as usize).unwrap_or(&"")),
+                        Index::Multi(n) => {
+                            new_args.extend_from_slice(indexed.get((-n - 1) as usize..).unwrap_or(&[]));
+                        }
+                        Index::Literal(s) => new_args.push(s),
+                    }
+                }
+                new_args.join(" ")
+            }
+            ArgumentTransform::Defaults(defaults) => {
+                let mut words: Vec<&str> = args.split_whitespace().collect();
+                if let Some(missing) = defaults.get(words.len()..) {
+                    words.extend(missing.iter().map(String::as_str));
+                }
+                words.join(" ")
+            }
+            ArgumentTransform::Template { params, text } => {
+                let mut values = vec![];
+                let mut rest = args.trim();
+                for (i, _) in params.iter().enumerate() {
+                    if i == params.len() - 1 {
+                        values.push(rest);
+                        break;
+                    }
+                    let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    values.push(word);
+                    rest = tail.trim_start();
+                }
+
+                let mut unknown = None;
+                let res = TEMPLATE_PARAM.replace_all(text, |c: &Captures| {
+                    let name = &c[1];
+                    match params.iter().position(|p| p == name) {
+                        Some(i) if !values[i].is_empty() => values[i].to_string(),
+                        Some(_) => c.get(2).map_or("", |d| d.as_str()).to_string(),
+                        None => {
+                            unknown.get_or_insert_with(|| name.to_string());
+                            String::new()
+                        }
+                    }
+                });
+                if let Some(name) = unknown {
+                    bail_user!("alias template uses {{{}}}, which isn't one of its params", name);
+                }
+                res.into_owned()
+            }
+            ArgumentTransform::Chain(transforms) => {
+                let mut args = args.to_string();
+                for t in transforms {
+                    args = t.apply(&args)?;
+                }
+                args
+            }
+        })
+    }
+}
+
+lazy_static! {
+    // "{name}" or "{name=default}" in a template's text
+    static ref TEMPLATE_PARAM: Regex = Regex::new(r"\{(\w+)(?:=([^}]*))?\}").unwrap();
+}
+
+fn regex_from_str<'de, D>(deserializer: D) -> std::result::Result<Regex, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Regex::new(&s).map_err(serde::de::Error::custom)
+}
+
+fn opt_bool_from_int<'de, D>(deserializer: D) -> std::result::Result<Option<bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match u8::deserialize(deserializer)? {
+        0 => Ok(Some(false)),
+        1 => Ok(Some(true)),
+        other => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(other.into()),
+            &"zero or one",
+        )),
+    }
+}
@@ -1,44 +1,64 @@
-use ::irc::client::ext::ClientExt;
-use ::irc::client::prelude as irc;
-use ::irc::client::prelude::Client;
 use flexi_logger::{LogSpecBuilder, Logger, LoggerHandle};
 use futures::channel::oneshot::{self, Receiver, Sender};
 use libloading::Library;
 use log::{error, info, Level};
 use parking_lot::{Mutex, RwLock};
-use postgres::types::{FromSql, Type};
-use regex::Regex;
-use serde::Deserialize;
-use serenity::model::channel;
-use serenity::model::guild;
-use serenity::model::id::{ChannelId, GuildId};
-use serenity::prelude as dis;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::str;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 use std::thread;
 use std::time::{Duration, Instant};
 
+use super::adapter::{self, Adapter, EventSink};
+use super::alias;
 use super::config;
 use super::context;
-use super::context::{Prefix, Source};
 use super::core;
 use super::db;
-use super::message;
+use super::deps;
+use super::perms;
+use super::ratelimit;
+use super::scheduler;
+use rustbot::abi;
+use rustbot::cache::{self, Cached};
 use rustbot::prelude::{Source as LibSource, *};
+use rustbot::sql;
 use rustbot::types;
 
+// The most commands one pipeline ("!a | !b | !c") can run, counting any pipelines it's part of.
+const MAX_PIPELINE_STAGES: usize = 5;
+
 pub struct Rustbot {
-    clients: RwLock<BTreeMap<String, Arc<irc::IrcClient>>>,
-    caches: RwLock<BTreeMap<String, Arc<serenity::CacheAndHttp>>>,
-    db: Mutex<postgres::Client>,
+    // for work that outlives the command starting it; see arc
+    this: Weak<Rustbot>,
+    adapters: RwLock<BTreeMap<String, Arc<dyn Adapter>>>,
+    db: sql::Pool,
+    // when both modules and commands are locked at once, modules is always locked first
     modules: RwLock<BTreeMap<String, Module>>,
-    core_commands: RwLock<BTreeMap<String, (Perms, Box<core::CoreCommand>)>>,
+    core_commands: RwLock<core::CoreCommands>,
     commands: RwLock<BTreeMap<String, (String, Command)>>,
     logger: Mutex<LogInfo>,
 
+    generations: cache::Generations,
+    // config_id -> enabled module names
+    enabled_modules: Cached<BTreeMap<String, Vec<String>>>,
+    // (config_id, channel pattern, cmdchars), most specific pattern first
+    cmdchars: Cached<Vec<(String, String, String)>>,
+    aliases: alias::Aliases,
+
+    pub(crate) perms: perms::Permissions,
+    limits: config::Limits,
+    // keyed by "<config_id>:<user>" for the overall limit, with "/<command>" for per-command ones
+    pub(crate) cooldowns: ratelimit::Limiter,
     pub(crate) suppress_errors: RwLock<BTreeMap<String, Instant>>,
+    // whether a recompile is running; see crate::recompile
+    pub(crate) building: AtomicBool,
+    pub(crate) scheduler: scheduler::Scheduler,
 }
 
 struct LogInfo {
@@ -46,159 +66,27 @@ struct LogInfo {
     current_level: Level,
 }
 
-fn irc_parse_prefix(prefix: Option<String>) -> Option<context::Prefix> {
-    match prefix {
-        None => None,
-        Some(s) => {
-            if !s.contains('!') {
-                Some(Prefix::Server(s))
-            } else {
-                let ss = s.clone();
-                let nr: Vec<&str> = ss.splitn(2, '!').collect();
-                if !nr[1].contains('@') {
-                    Some(Prefix::Server(s))
-                } else {
-                    let uh: Vec<&str> = nr[1].splitn(2, '@').collect();
-                    Some(Prefix::User {
-                        nick: nr[0].to_string(),
-                        user: uh[0].to_string(),
-                        host: uh[1].to_string(),
-                    })
-                }
-            }
-        }
-    }
-}
-
 impl Rustbot {
-    fn irc_incoming(&self, cfg: String, bot_name: &str, irc_msg: irc::Message) {
-        if let irc::Command::PRIVMSG(channel, message) = irc_msg.command {
-            let mut typ = HandleType::PlainMsg;
-
-            if channel == bot_name {
-                typ |= HandleType::Private;
-            } else {
-                typ |= HandleType::Public;
-            }
-
-            let source = Source::Irc {
-                prefix: irc_parse_prefix(irc_msg.prefix),
-                channel: if channel == bot_name { None } else { Some(channel) },
-            };
-            let ctx = &context::Context {
-                bot: self,
-                config: cfg,
-                source,
-                bot_name: bot_name.to_string(),
-            };
-            self.handle(ctx, typ, message.as_str());
-        }
+    pub fn arc(&self) -> Arc<Rustbot> {
+        self.this.upgrade().unwrap()
     }
 
-    fn dis_incoming(&self, cfg: String, disctx: dis::Context, msg: channel::Message) {
-        if msg.author.id == disctx.cache.read().user.id {
-            return;
-        }
-
-        let mut typ = HandleType::None;
-
-        match msg.channel_id.to_channel(&disctx) {
-            Err(e) => {
-                warn!("failed to determine channel type for incoming message: {}", e);
-                return;
-            }
-            Ok(c) => match c {
-                channel::Channel::Private(_) => typ |= HandleType::Private,
-                channel::Channel::Group(_) => typ |= HandleType::Group,
-                channel::Channel::Guild(_) => typ |= HandleType::Public,
-                _ => return,
-            },
-        }
-
+    fn handle(&self, config: &str, event: adapter::Event) {
         let ctx = &context::Context {
             bot: self,
-            config: cfg,
-            source: Source::Discord {
-                user: msg.author,
-                channel: msg.channel_id,
-                guild: msg.guild_id,
-
-                cache: disctx.cache,
-                http: disctx.http,
-            },
-            bot_name: String::new(),
+            config: config.to_string(),
+            source: event.source,
         };
-
-        if !msg.content.is_empty() {
-            self.handle(ctx, HandleType::PlainMsg | typ, msg.content.as_str());
-        }
-        for att in msg.attachments {
-            self.handle(ctx, HandleType::Attachment | typ, &att.proxy_url);
-        }
-        if msg.content.is_empty() {
-            for embed in msg.embeds {
-                if embed.title.is_none() && embed.description.is_none() {
-                    // probably just a link, skip it
-                    continue;
-                }
-
-                let mut data = vec![];
-                if let Some(author) = embed.author {
-                    if let Some(url) = author.url {
-                        data.push(format!("{} <{}>", author.name, url));
-                    } else {
-                        data.push(author.name);
-                    }
-                }
-                if let Some(title) = embed.title {
-                    if let Some(url) = embed.url {
-                        data.push(format!("{title} <{url}>"));
-                    } else {
-                        data.push(title);
-                    }
-                }
-                if let Some(description) = embed.description {
-                    data.append(&mut description.split('\n').map(str::to_string).collect());
-                }
-                for field in embed.fields {
-                    if field.inline {
-                        data.push(format!("{}: {}", field.name, field.value.replace('\n', "\t")));
-                    } else {
-                        data.push(format!("{}:", field.name));
-                        for line in field.value.split('\n') {
-                            data.push(format!("\t{line}"));
-                        }
-                    }
-                }
-
-                if data.is_empty() {
-                    continue;
-                }
-
-                let mut spans = vec![];
-
-                if data.len() == 1 {
-                    spans.push(format!("│ {}", data.remove(0)));
-                } else {
-                    spans.push(format!("╽ {}", data.remove(0)));
-                    let lastline = data.remove(data.len() - 1);
-                    for line in data {
-                        spans.push(format!("┃ {line}"));
-                    }
-                    spans.push(format!("╿ {lastline}"));
-                }
-
-                self.handle(ctx, HandleType::Embed | typ, &spans.join("\n"));
-            }
-        }
-    }
-
-    fn handle(&self, ctx: &context::Context, typ: HandleType, message: &str) {
-        match self.handle_inner(ctx, typ, message) {
+        let res = match &event.kind {
+            adapter::EventKind::Message { typ, message } => self.handle_inner(ctx, *typ, message),
+            adapter::EventKind::Other(event) => self.handle_event(ctx, event),
+        };
+        match res {
             Ok(()) => (),
             Err(err) => self.handle_err(ctx, err),
         }
     }
+
     fn handle_err(&self, ctx: &context::Context, err: Error) {
         match match err.downcast::<UserError>() {
             Ok(ue) => {
@@ -220,51 +108,98 @@ impl Rustbot {
         }
     }
 
+    // The modules enabled for a config.
+    fn enabled_modules(&self, config: &str) -> Result<Vec<String>> {
+        let enabled = self.enabled_modules.get(self, |db| {
+            let mut enabled: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for row in db.query(
+                "SELECT config_id, name FROM modules JOIN enabled_modules USING (name) WHERE modules.enabled",
+                &[],
+            )? {
+                enabled.entry(row.try_get(0)?).or_default().push(row.try_get(1)?);
+            }
+            Ok(enabled)
+        })?;
+        Ok(enabled.get(config).cloned().unwrap_or_default())
+    }
+
+    fn handle_event(&self, ctx: &context::Context, event: &types::Event) -> Result<()> {
+        for name in self.enabled_modules(&ctx.config)? {
+            if let Some(m) = self.modules.read().get(&name) {
+                m.with_meta::<Result<_>>(|meta| {
+                    for (ty, handler) in &meta.event_handlers {
+                        if ty.contains(event.typ()) {
+                            self.maybe_ignore_err(&name, handler(ctx, event), ())
+                                .with_context(|| format!("failed to run event handler for module {name:?}"))?;
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn handle_inner(&self, ctx: &context::Context, mut typ: HandleType, message: &str) -> Result<()> {
-        let enabled = {
-            let mut db = ctx.bot().sql().lock();
-            let mods: Vec<String> = db.query(
-                    "SELECT name FROM modules JOIN enabled_modules USING (name) WHERE config_id = $1 AND modules.enabled",
-                    &[&ctx.config],
-                )?
-                .iter()
-                .map(|row| row.get(0))
-                .collect();
-            mods
-        };
+        let enabled = self.enabled_modules(&ctx.config)?;
 
         if typ.contains(HandleType::PlainMsg) {
             let cmdchars: Cow<'static, str> = {
-                let chars = ctx.bot().sql().lock().query(
-                    "SELECT cmdchars FROM cmdchars WHERE config_id = $1 AND $2 LIKE channel ORDER BY channel DESC LIMIT 1",
-                    &[&ctx.config, &ctx.source.channel_string()],
-                )?;
-                if chars.is_empty() {
-                    Cow::Borrowed("")
-                } else {
-                    Cow::Owned(chars.get(0).unwrap().get(0))
+                let all = self.cmdchars.get(self, |db| {
+                    db.query(
+                        "SELECT config_id, channel, cmdchars FROM cmdchars ORDER BY channel DESC",
+                        &[],
+                    )?
+                    .iter()
+                    .map(|row| Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?)))
+                    .collect()
+                })?;
+                let channel = ctx.source.channel_string();
+                match all
+                    .iter()
+                    .find(|(config_id, pattern, _)| *config_id == ctx.config && cache::like(pattern, &channel))
+                {
+                    Some((_, _, chars)) => Cow::Owned(chars.clone()),
+                    None => Cow::Borrowed(""),
                 }
             };
 
             if message.starts_with(|c| cmdchars.contains(c)) {
                 // it's a command!
                 let prefix = message.chars().take(1).next().unwrap();
-                let parts: Vec<&str> = message[prefix.len_utf8()..].splitn(2, char::is_whitespace).collect();
-
-                let (cmd, args) = self.resolve_alias(parts[0], parts.get(1).unwrap_or(&""))?;
+                let stages = split_pipeline(&message[prefix.len_utf8()..], &cmdchars);
+                if ctx.source.pipeline_depth() + stages.len() > MAX_PIPELINE_STAGES {
+                    bail_user!("pipelines can have at most {} commands", MAX_PIPELINE_STAGES);
+                }
 
-                if let Some((p, f)) = self.core_commands.read().get(&cmd) {
-                    if ctx.perms()?.contains(*p) {
-                        f(ctx, &args).with_context(|| format!("failed to run command {cmd:?}"))?;
+                // each stage but the last runs with its replies captured, and what it said is
+                // added to the end of the next stage's arguments
+                let mut input = String::new();
+                for (i, stage) in stages.iter().enumerate() {
+                    let line = match input.as_str() {
+                        "" => stage.to_string(),
+                        input => format!("{stage} {input}"),
+                    };
+                    if i == stages.len() - 1 {
+                        self.run_command(ctx, &enabled, &line)?;
+                        break;
                     }
-                } else {
-                    let res = self.commands.read().get(&cmd).cloned();
-                    if let Some((m, f)) = res {
-                        if enabled.contains(&m) {
-                            f.call(ctx, &args)
-                                .with_context(|| format!("failed to run command {cmd:?}"))?;
-                        }
+
+                    let output = Arc::new(Mutex::new(vec![]));
+                    let capture = context::Context {
+                        bot: self,
+                        config: ctx.config.clone(),
+                        source: context::Source::Capture {
+                            parent: Box::new(ctx.source.clone()),
+                            output: output.clone(),
+                        },
+                    };
+                    if !self.run_command(&capture, &enabled, &line)? {
+                        // not a command the source can run, so the rest can't run either
+                        break;
                     }
+                    input = output.lock().join("\n");
                 }
 
                 typ |= HandleType::Command;
@@ -289,6 +224,150 @@ impl Rustbot {
         Ok(())
     }
 
+    // Runs a command line without its command character, returning whether the command was one
+    // the source could run.
+    fn run_command(&self, ctx: &context::Context, enabled: &[String], line: &str) -> Result<bool> {
+        let parts: Vec<&str> = line.splitn(2, char::is_whitespace).collect();
+        // an alias can stand for several commands, which run in turn until one can't
+        for (cmd, args) in self.resolve_alias(ctx, parts[0], parts.get(1).unwrap_or(&""))? {
+            if !self.run_resolved(ctx, enabled, &cmd, &args)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_resolved(&self, ctx: &context::Context, enabled: &[String], cmd: &str, args: &str) -> Result<bool> {
+        if let Some(c) = self.core_commands.read().get(cmd) {
+            let (name, leaf, args) = match self.permitted_leaf(ctx, cmd, c, args)? {
+                Some(found) if self.within_limits(ctx, cmd)? => found,
+                _ => return Ok(false),
+            };
+            match &leaf.function {
+                Some(f) => f(ctx, args),
+                None => Err(leaf.no_subcommand(ctx, args)?.into()),
+            }
+            .with_context(|| format!("failed to run command {name:?}"))?;
+            return Ok(true);
+        }
+
+        let res = self.commands.read().get(cmd).cloned();
+        if let Some((m, c)) = res {
+            if !enabled.contains(&m) {
+                return Ok(false);
+            }
+            let (name, leaf, args) = match self.permitted_leaf(ctx, cmd, &c, args)? {
+                Some(found) if self.within_limits(ctx, cmd)? => found,
+                _ => return Ok(false),
+            };
+            leaf.run(ctx, args)
+                .with_context(|| format!("failed to run command {name:?}"))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Finds the subcommand a command's arguments name, if any, checking the source may run it and
+    // everything on the way, with cmdperm overriding each by its full name, e.g. "list.add".
+    // Returns that name, the command to run and its arguments.
+    fn permitted_leaf<'a, 'b, F: ?Sized>(
+        &self,
+        ctx: &context::Context,
+        cmd: &str,
+        c: &'a Command<F>,
+        args: &'b str,
+    ) -> Result<Option<(String, &'a Command<F>, &'b str)>> {
+        c.resolve_permitted(cmd, args, |name, c| self.may_run(ctx, name, &c.req_perms))
+    }
+
+    // The commands the source can run where it is, core ones included, with the module each is from
+    // ("core" for the bot's own) and its help.
+    pub fn available_commands(&self, ctx: &context::Context) -> Result<BTreeMap<String, (String, Help)>> {
+        let mut available = BTreeMap::new();
+        for (name, cmd) in self.core_commands.read().iter() {
+            if self.may_run(ctx, name, &cmd.req_perms)? {
+                available.insert(name.clone(), ("core".to_string(), cmd.help.clone()));
+            }
+        }
+
+        let enabled = self.enabled_modules(&ctx.config)?;
+        for (name, (module, cmd)) in self.commands.read().iter() {
+            if enabled.contains(module) && self.may_run(ctx, name, &cmd.req_perms)? {
+                available
+                    .entry(name.clone())
+                    .or_insert_with(|| (module.clone(), cmd.help.clone()));
+            }
+        }
+        Ok(available)
+    }
+
+    // Checks whether the source may run a command needing the given permissions, taking into
+    // account any override for the command where it was sent.
+    fn may_run(&self, ctx: &context::Context, cmd: &str, required: &[String]) -> Result<bool> {
+        let overridden: Vec<String>;
+        let required = match self
+            .perms
+            .command_override(self, cmd, &ctx.config, &ctx.source.channel_string())?
+        {
+            Some((_, false)) => return Ok(false),
+            Some((permission, true)) => {
+                overridden = permission.into_iter().collect();
+                &overridden
+            }
+            None => required,
+        };
+        ctx.has_perms(required)
+    }
+
+    // Checks the source hasn't been running commands too quickly, telling them to slow down if so
+    // (once, rather than for every command in a flood).
+    fn within_limits(&self, ctx: &context::Context, cmd: &str) -> Result<bool> {
+        let user = format!("{}:{}", ctx.config, ctx.source.user_string());
+        let now = Instant::now();
+
+        let mut denied = None;
+        if let Some(limit) = self.limits.user {
+            denied = self.cooldowns.check(&user, limit, now).err();
+        }
+        if denied.is_none() {
+            if let Some(limit) = self.limits.command.get(cmd) {
+                denied = self.cooldowns.check(&format!("{user}/{cmd}"), *limit, now).err();
+            }
+        }
+
+        match denied {
+            None => Ok(true),
+            Some(_) if ctx.has_perm("admin")? => Ok(true),
+            Some(d) => {
+                if d.first {
+                    ctx.say_uncaptured(&format!(
+                        "slow down! you can use {} again in {}",
+                        cmd,
+                        ratelimit::format_wait(d.wait)
+                    ))?;
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    // Every permission that can be granted, with the module defining it (None for core ones)
+    // and its description.
+    pub fn permissions(&self) -> BTreeMap<String, (Option<String>, String)> {
+        let mut perms: BTreeMap<_, _> = rustbot::perms::CORE
+            .iter()
+            .map(|(_, name, desc)| (name.to_string(), (None, desc.to_string())))
+            .collect();
+        for (module, m) in self.modules.read().iter() {
+            m.with_meta(|meta| {
+                for (name, desc) in &meta.permissions {
+                    perms.insert(name.clone(), (Some(module.clone()), desc.clone()));
+                }
+            });
+        }
+        perms
+    }
+
     fn maybe_ignore_err<T>(&self, name: &str, res: Result<T>, on_ignore: T) -> Result<T> {
         match self.suppress_errors.read().get(name) {
             None => res,
@@ -308,153 +387,273 @@ impl Rustbot {
         }
     }
 
-    fn resolve_alias(&self, cmd: &str, args: &str) -> Result<(String, String)> {
-        let (newcmd, transforms): (String, ArgumentTransforms) = {
-            let mut db = self.sql().lock();
-            let rows = db.query(
-                "WITH RECURSIVE resolve(depth, name, transform) AS (
-                    VALUES (0, $1, null)
-                    UNION ALL SELECT resolve.depth + 1, aliases.target, aliases.transform
-                              FROM aliases, resolve
-                              WHERE aliases.name = resolve.name
-                )
-                VALUES (
-                    (SELECT name FROM resolve ORDER BY depth DESC LIMIT 1),
-                    (to_jsonb(array(SELECT transform::jsonb FROM resolve WHERE transform IS NOT NULL ORDER BY depth ASC)))
-                )",
-                &[&cmd],
-            )?;
-            if rows.is_empty() {
-                bail!("failed to resolve alias: no result rows?");
-            }
-            let row = rows.get(0).unwrap();
-
-            (row.get(0), row.get(1))
-        };
-
-        let mut args = args.to_string();
-        for transform in transforms.iter() {
-            match transform {
-                ArgumentTransform::RegexReplace { find, replace, global } => {
-                    let re = Regex::new(find)?;
-                    let n = usize::from(!global.unwrap_or(false));
+    // The commands an alias stands for where the source is, or just the command if it isn't one.
+    pub(crate) fn resolve_alias(&self, ctx: &context::Context, cmd: &str, args: &str) -> Result<Vec<(String, String)>> {
+        self.aliases
+            .resolve(self, &ctx.config, &ctx.source.channel_string(), cmd, args)
+    }
 
-                    args = re.replacen(args.as_str(), n, replace.as_str()).into_owned();
-                }
-                ArgumentTransform::ByIndex(t) => {
-                    let new_args = {
-                        let indexed: Vec<_> = args.split(' ').collect();
-                        let mut new_args = Vec::with_capacity(usize::max(5, 2 * indexed.len()));
-                        for item in t.iter() {
-                            match item {
-                                Index::Single(0) => new_args.extend_from_slice(&indexed),
-                                Index::Single(n) => new_args.push(indexed.get((n - 1) as usize).unwrap_or(&"")),
-                                Index::Multi(n) => {
-                                    new_args.extend_from_slice(indexed.get((-n - 1) as usize..).unwrap_or(&[]));
-                                }
-                                Index::Literal(s) => new_args.push(s),
-                            }
-                        }
-                        new_args.join(" ")
-                    };
-                    args = new_args;
-                }
-            }
+    pub fn adapter(&self, config: &str) -> Result<Arc<dyn Adapter>> {
+        match self.adapters.read().get(config) {
+            Some(a) => Ok(Arc::clone(a)),
+            None => bail!("invalid configid"),
         }
+    }
 
-        Ok((newcmd, args))
+    pub fn adapters(&self) -> Vec<Arc<dyn Adapter>> {
+        self.adapters.read().values().cloned().collect()
     }
 
-    fn dis_get_replacements(
-        guild: impl std::ops::Deref<Target = guild::Guild>,
-        reverse: bool,
-    ) -> Vec<(String, String)> {
-        let mut replacements = vec![];
-        for (id, m) in &guild.members {
-            replacements.push((format!("@{}", m.user.read().name), format!("<@{id}>")));
-            if reverse {
-                replacements.push((format!("@{}", m.user.read().name), format!("<@!{id}>")));
-            }
+    // Runs `f` with the adapter for the given config, if it's of the expected type.
+    fn with_adapter<T: Adapter + 'static, R>(&self, config: &str, f: impl FnOnce(&T) -> Result<R>) -> Result<R> {
+        let adapter = self.adapter(config)?;
+        match adapter.as_any().downcast_ref::<T>() {
+            Some(a) => f(a),
+            None => bail!("config {:?} is a {} config", config, adapter.network()),
         }
+    }
 
-        for (id, r) in &guild.roles {
-            replacements.push((format!("@{}", r.name), format!("<@&{id}>")));
-        }
+    fn start_adapter(self: &Arc<Self>, adapter: Arc<dyn Adapter>) -> Result<()> {
+        let id = adapter.config_id().to_string();
+        self.adapters.write().insert(id.clone(), Arc::clone(&adapter));
 
-        for (id, c) in &guild.channels {
-            replacements.push((format!("#{}", c.read().name), format!("<#{id}>")));
-        }
+        let sink: EventSink = {
+            let b = Arc::clone(self);
+            Arc::new(move |event| b.handle(&id, event))
+        };
 
-        for (id, e) in &guild.emojis {
-            replacements.push((format!(":{}:", e.name), format!("<:{}:{}>", e.name, id)));
-        }
+        let b = Arc::clone(self);
+        thread::Builder::new().name(adapter.descriptor()).spawn(move || {
+            run_with_backoff(&format!("connection for {}", adapter.descriptor()), &|| {
+                adapter.connect(&*b, Arc::clone(&sink))
+            });
+        })?;
 
-        replacements
+        Ok(())
     }
 
-    fn str_max_bytes(s: &str, n: usize) -> &str {
-        if s.len() <= n {
-            return s;
+    // Drops a module; if others depend on it, they're dropped first if `cascade` is set, and it's
+    // an error otherwise.
+    pub fn drop_module(&self, name: &str, cascade: bool) -> Result<()> {
+        let dependents = self.dependents(name);
+        if !dependents.is_empty() && !cascade {
+            bail_user!(
+                "{} depend{} on {}; drop {} first, or use drop --cascade",
+                dependents.join(", "),
+                if dependents.len() == 1 { "s" } else { "" },
+                name,
+                if dependents.len() == 1 { "it" } else { "them" },
+            );
+        }
+        for d in dependents {
+            self.drop_one(&d)?;
         }
+        self.drop_one(name)
+    }
 
-        let (last_char_inside, _) = s.char_indices().take_while(|(i, _)| *i <= n).last().unwrap();
-        &s[..last_char_inside]
+    // Runs `f` with the handler for a kind of scheduled job, if a loaded module has registered it.
+    pub fn with_job_handler<R>(&self, handler: &str, f: impl FnOnce(&JobFn) -> R) -> Option<R> {
+        let modules = self.modules.read();
+        let mut f = Some(f);
+        for m in modules.values() {
+            let ran = m.with_meta(|meta| meta.jobs.get(handler).map(|h| (f.take().unwrap())(h.as_ref())));
+            if ran.is_some() {
+                return ran;
+            }
+        }
+        None
+    }
+
+    // The loaded modules that depend on `name`, in the order to drop them in.
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        deps::dependents(&self.module_infos(), name)
+    }
+
+    pub fn module_infos(&self) -> Vec<deps::Info> {
+        self.modules
+            .read()
+            .iter()
+            .map(|(name, m)| m.with_meta(|meta| meta.info(name)))
+            .collect()
     }
 
-    pub fn drop_module(&self, name: &str) -> Result<()> {
-        if let Some(mut m) = self.modules.write().remove(name) {
+    fn drop_one(&self, name: &str) -> Result<()> {
+        // don't hold the lock while the module shuts down, since its threads may need it to finish
+        let removed = self.modules.write().remove(name);
+        if let Some(mut m) = removed {
             info!("drop module: {}", name);
-            let mut db = self.db.lock();
-            db
-                .execute(
-                    "INSERT INTO modules (name, enabled) VALUES ($1, false) ON CONFLICT (name) DO UPDATE SET enabled = false",
-                    &[&name],
-                )?;
-            m.with_meta_mut::<Result<_>>(|meta| {
+            let mut db = self.sql()?;
+            db.execute(
+                "INSERT INTO modules (name, enabled) VALUES ($1, false) ON CONFLICT (name) DO UPDATE SET enabled = false",
+                &[&name],
+            )?;
+            self.invalidate(Some("modules"));
+            m.with_meta(|meta| {
                 let mut commands = self.commands.write();
                 for command in &meta.commands {
                     commands.remove(command.0);
                 }
-                for chan in meta.unload_channels.drain(..) {
-                    chan.send(()).unwrap_or(()); // Err() here means the remote end was dropped before we got here
+            });
+            self.stop_module(&mut m)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Shuts down a module that's already been removed from the bot.
+    fn stop_module(&self, m: &mut Module) -> Result<()> {
+        m.with_meta_mut::<Result<_>>(|meta| {
+            for chan in meta.unload_channels.drain(..) {
+                chan.send(()).unwrap_or(()); // Err() here means the remote end was dropped before we got here
+            }
+            if let Some(f) = &mut meta.deinit {
+                f(self)?;
+            }
+            for thread in meta.threads.drain(..) {
+                thread.join().map_err(|e| Error::msg(format!("{e:?}")))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Replaces a loaded module with a new build of it, without a gap where its commands are
+    // missing: the new one is loaded alongside it, given any state it saved, and swapped in at
+    // once, and only then is the old one shut down and the new one's threads started. An error
+    // means the swap didn't happen, and the old one carries on as it was. Modules that aren't
+    // loaded are just loaded.
+    pub fn reload_module(&self, name: &str) -> Result<()> {
+        if !self.modules.read().contains_key(name) {
+            return match self.load_modules(&[name.to_string()]).pop() {
+                Some((_, e)) => Err(e),
+                None => Ok(()),
+            };
+        }
+
+        info!("reload module: {}", name);
+        let lib = open_library_copy(name)?;
+
+        let mut old = {
+            let mut modules = self.modules.write();
+            let mut old = match modules.remove(name) {
+                Some(m) => m,
+                None => bail!("{} was dropped while reloading it", name),
+            };
+            match self.swap_module(&mut modules, name, lib, &mut old) {
+                Ok(new) => {
+                    modules.insert(name.to_string(), new);
+                    old
                 }
-                if let Some(f) = &mut meta.deinit {
-                    f(self)?;
+                Err(e) => {
+                    modules.insert(name.to_string(), old);
+                    return Err(e);
                 }
-                for thread in meta.threads.drain(..) {
-                    thread.join().map_err(|e| Error::msg(format!("{e:?}")))?;
+            }
+        };
+
+        // the old threads have to finish first, so that e.g. a server can bind the same port; the
+        // new build is in place by now either way, so a failure here is only logged
+        if let Err(e) = self.stop_module(&mut old) {
+            error!("old instance of module {} failed to shut down cleanly: {:?}", name, e);
+        }
+        drop(old);
+        if let Some(m) = self.modules.write().get_mut(name) {
+            m.with_meta_mut(|meta| meta.start_threads());
+        }
+        Ok(())
+    }
+
+    // Loads the new build of a module being reloaded, and moves its commands over from the old
+    // one. The caller holds the module lock throughout, so nothing runs the old module's handlers
+    // after this, and the new one's can't run until it's in place.
+    fn swap_module(
+        &self,
+        modules: &mut BTreeMap<String, Module>,
+        name: &str,
+        lib: Library,
+        old: &mut Module,
+    ) -> Result<Module> {
+        let state = old.with_meta_mut(|meta| match &mut meta.save_state {
+            Some(f) => f().map(Some).context("failed to save state"),
+            None => Ok(None),
+        })?;
+        let new = load_module(name, lib, state)?;
+
+        // the new build has to fit with the modules it depends on, and those depending on it
+        let loaded: Vec<deps::Info> = modules.iter().map(|(n, m)| m.with_meta(|meta| meta.info(n))).collect();
+        let mut with_old = loaded.clone();
+        with_old.push(old.with_meta(|meta| meta.info(name)));
+        let dependents = deps::dependents(&with_old, name);
+        let (others, dependents): (Vec<_>, Vec<_>) = loaded.into_iter().partition(|i| !dependents.contains(&i.name));
+        let mut check = dependents;
+        check.push(new.with_meta(|meta| meta.info(name)));
+        if let Some((m, e)) = deps::load_order(&others, check).1.into_iter().next() {
+            bail!("can't swap in the new build: {}: {}", m, e);
+        }
+
+        let mut commands = self.commands.write();
+        old.with_meta(|meta| {
+            for command in meta.commands.keys() {
+                commands.remove(command);
+            }
+        });
+        new.with_meta(|meta| {
+            for (command, f) in &meta.commands {
+                commands.insert(command.to_string(), (name.to_string(), f.clone()));
+            }
+        });
+        Ok(new)
+    }
+
+    // Loads several modules, in an order that satisfies their dependencies on each other and on
+    // the modules already loaded. Returns the ones that couldn't be loaded, with why.
+    pub fn load_modules(&self, names: &[String]) -> Vec<(String, Error)> {
+        let mut errors = vec![];
+        let mut opened = BTreeMap::new();
+        for name in names {
+            match self.open_module(name) {
+                Ok(m) => {
+                    opened.insert(name.clone(), m);
                 }
-                Ok(())
-            })?;
-            Ok(())
-        } else {
-            Ok(())
+                Err(e) => errors.push((name.clone(), e)),
+            }
+        }
+
+        let infos = opened
+            .iter()
+            .map(|(name, m)| m.with_meta(|meta| meta.info(name)))
+            .collect();
+        let (order, failed) = deps::load_order(&self.module_infos(), infos);
+        errors.extend(failed);
+
+        for name in order {
+            let mut m = opened.remove(&name).unwrap();
+            let mut modules = self.modules.write();
+            let mut commands = self.commands.write();
+            m.with_meta_mut(|meta| {
+                for command in &meta.commands {
+                    commands.insert(command.0.to_string(), (name.to_string(), (*command.1).clone()));
+                }
+                meta.start_threads();
+            });
+            modules.insert(name, m);
         }
+        errors
     }
 
-    pub fn load_module(&self, name: &str) -> Result<()> {
+    // Loads a module's library and collects what it registers, without starting it.
+    fn open_module(&self, name: &str) -> Result<Module> {
         info!("load module: {}", name);
-        let libpath = if cfg!(debug_assertions) {
-            format!("libmod_{name}.so")
-        } else {
-            format!("target/release/libmod_{name}.so")
-        };
-        let lib = Library::new(libpath)?;
+        if self.modules.read().contains_key(name) {
+            bail_user!("{} is already loaded", name);
+        }
+        let lib = Library::new(library_path(name))?;
 
-        self.db.lock().execute(
+        self.sql()?.execute(
             "INSERT INTO modules (name, enabled) VALUES ($1, true) ON CONFLICT (name) DO UPDATE SET enabled = true",
             &[&name],
         )?;
-        let m = load_module(name, lib)?;
-        let mut commands = self.commands.write();
-        m.with_meta::<Result<_>>(|meta| {
-            for command in &meta.commands {
-                commands.insert(command.0.to_string(), (name.to_string(), (*command.1).clone()));
-            }
-            Ok(())
-        })?;
-        self.modules.write().insert(name.to_string(), m);
-        Ok(())
+        self.invalidate(Some("modules"));
+        load_module(name, lib, None)
     }
 
     pub fn set_log_level(&self, level: Level) -> Result<()> {
@@ -464,13 +663,12 @@ impl Rustbot {
 
     pub fn set_module_log_level(&self, module: &str, level: Option<Level>) -> Result<()> {
         if let Some(level) = level {
-            self.db.lock().execute(
-                "UPDATE modules SET log_level = $1::text::log_level WHERE name = $2",
+            self.sql()?.execute(
+                "UPDATE modules SET log_level = $1 WHERE name = $2",
                 &[&level.to_string().to_ascii_lowercase().as_str(), &module],
             )?;
         } else {
-            self.db
-                .lock()
+            self.sql()?
                 .execute("UPDATE modules SET log_level = NULL WHERE name = $1", &[&module])?;
         }
         self.update_logger_spec()
@@ -483,15 +681,11 @@ impl Rustbot {
         builder.default(logger.current_level.to_level_filter());
 
         let modules: Vec<(String, String)> = self
-            .db
-            .lock()
-            .query(
-                "SELECT name, log_level::text FROM modules WHERE log_level IS NOT NULL",
-                &[],
-            )?
+            .sql()?
+            .query("SELECT name, log_level FROM modules WHERE log_level IS NOT NULL", &[])?
             .iter()
-            .map(|row| (row.get(0), row.get(1)))
-            .collect();
+            .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+            .collect::<Result<_>>()?;
 
         for (m, l) in &modules {
             let level = l.parse::<Level>()?;
@@ -507,253 +701,90 @@ impl Rustbot {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
-#[serde(rename_all = "snake_case")]
-enum ArgumentTransform {
-    RegexReplace {
-        find: String,
-        replace: String,
-        #[serde(deserialize_with = "opt_bool_from_int")]
-        global: Option<bool>,
-    },
-    ByIndex(Vec<Index>),
-}
-
-#[derive(Deserialize, Debug, Clone)]
-#[serde(untagged)]
-enum Index {
-    Single(u64),
-    Multi(i64),
-    Literal(String),
-}
-
-fn opt_bool_from_int<'de, D>(deserializer: D) -> std::result::Result<Option<bool>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    match u8::deserialize(deserializer)? {
-        0 => Ok(Some(false)),
-        1 => Ok(Some(true)),
-        other => Err(serde::de::Error::invalid_value(
-            serde::de::Unexpected::Unsigned(other.into()),
-            &"zero or one",
-        )),
-    }
-}
-
-#[derive(Deserialize, Debug)]
-struct ArgumentTransforms(Vec<ArgumentTransform>);
-
-impl std::ops::Deref for ArgumentTransforms {
-    type Target = Vec<ArgumentTransform>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
+// Splits a command line into the commands of its pipeline, without their command characters; a
+// | only starts a new one when the next thing after it is a command character, as in "dice 4d6 | !say".
+pub(crate) fn split_pipeline<'a>(line: &'a str, cmdchars: &str) -> Vec<&'a str> {
+    let mut stages = vec![];
+    let mut start = 0;
+    for (i, _) in line.match_indices('|') {
+        if i < start {
+            continue;
+        }
+        let next = line[i + 1..].trim_start();
+        if let Some(c) = next.chars().next().filter(|c| cmdchars.contains(*c)) {
+            stages.push(line[start..i].trim_end());
+            start = line.len() - next.len() + c.len_utf8();
+        }
     }
+    stages.push(&line[start..]);
+    stages
 }
 
-impl FromSql<'_> for ArgumentTransforms {
-    fn from_sql(
-        ty: &Type,
-        raw: &[u8],
-    ) -> std::result::Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
-        let v = serde_json::Value::from_sql(ty, raw)?;
-        Ok(serde_json::from_value(v)?)
+impl types::Bot for Rustbot {
+    fn sql(&self) -> Result<sql::PooledConnection> {
+        Ok(self.db.get()?)
     }
 
-    fn accepts(ty: &Type) -> bool {
-        serde_json::Value::accepts(ty)
+    fn generation(&self, table: &str) -> u64 {
+        self.generations.get(table)
     }
-}
 
-impl types::Bot for Rustbot {
-    fn sql(&self) -> &Mutex<postgres::Client> {
-        &self.db
+    fn invalidate(&self, table: Option<&str>) {
+        self.generations.bump(table);
     }
 
     fn irc_send_privmsg(&self, cfg: &str, channel: &str, message: &str) -> Result<()> {
-        if let Some(client) = self.clients.read().get(cfg) {
-            let message = Self::str_max_bytes(message, 490);
-            client.send_privmsg(channel, message).map_err(from_irc)?;
-            Ok(())
-        } else {
-            bail!("invalid configid")
-        }
+        self.with_adapter(cfg, |a: &adapter::irc::IrcAdapter| a.send_privmsg(channel, message))
     }
 
     fn irc_send_raw(&self, cfg: &str, line: &str) -> Result<()> {
-        if let Some(client) = self.clients.read().get(cfg) {
-            let line = Self::str_max_bytes(line, 510);
-            client.send(line).map_err(from_irc)?;
-            Ok(())
-        } else {
-            bail!("invalid configid")
-        }
+        self.with_adapter(cfg, |a: &adapter::irc::IrcAdapter| a.send_raw(line))
     }
 
     fn dis_unprocess_message(&self, config: &str, guild: &str, message: &str) -> Result<String> {
-        let cache_and_http = match self.caches.read().get(config) {
-            None => bail!("no cache found for config {:?}", config),
-            Some(c) => Arc::clone(c),
-        };
-
-        let cache = cache_and_http.cache.read();
-
-        let mut message = message.to_string();
-
-        let guildobj = {
-            if let Ok(id) = guild.parse() {
-                cache.guilds.get(&GuildId(id))
-            } else {
-                let mut v = None;
-                for g in cache.guilds.values() {
-                    if g.read().name == guild {
-                        v = Some(g);
-                        break;
-                    }
-                }
-                v
-            }
-        }
-        .ok_or_else(|| Error::msg("guild not found"))?
-        .read();
-
-        let mut replacements = Self::dis_get_replacements(guildobj, true);
-
-        replacements.sort_by(|l, r| {
-            if l.1.len() != r.1.len() {
-                return l.1.len().cmp(&r.1.len()).reverse();
-            }
-
-            l.1.cmp(&r.1)
-        });
-
-        for (replace, find) in replacements {
-            message = message.replace(&find, &replace);
-        }
-
-        Ok(message)
+        self.with_adapter(config, |a: &adapter::discord::DiscordAdapter| {
+            a.unprocess_message(guild, message)
+        })
     }
 
     fn dis_send_message(&self, config: &str, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
-        let cache_and_http = match self.caches.read().get(config) {
-            None => bail!("no cache found for config {:?}", config),
-            Some(c) => Arc::clone(c),
-        };
-
-        let cache = cache_and_http.cache.read();
+        self.with_adapter(config, |a: &adapter::discord::DiscordAdapter| {
+            a.send_message(guild, channel, message, process)
+        })
+    }
 
-        let guildobj = {
-            if let Ok(id) = guild.parse() {
-                cache.guilds.get(&GuildId(id))
-            } else {
-                let mut v = None;
-                for g in cache.guilds.values() {
-                    if g.read().name == guild {
-                        v = Some(g);
-                        break;
-                    }
-                }
-                v
+    fn call_service(&self, name: &str, f: &mut types::ServiceFn) -> Result<()> {
+        // recursive, since services are often used from handlers, which run with this lock held;
+        // dropping the providing module waits for the call to finish
+        let modules = self.modules.read_recursive();
+        for m in modules.values() {
+            if let Some(result) = m.with_meta(|meta| meta.services.get(name).map(|s| f(s.as_ref()))) {
+                return result;
             }
         }
-        .ok_or_else(|| Error::msg("guild not found"))?
-        .read();
+        Err(rustbot::services::unavailable(name))
+    }
 
-        let chanid = {
-            if let Ok(id) = channel.parse() {
-                if guildobj.channels.get(&ChannelId(id)).is_some() {
-                    Some(ChannelId(id))
-                } else {
-                    None
-                }
-            } else {
-                let mut v = None;
-                for (id, c) in &guildobj.channels {
-                    if c.read().name == channel {
-                        v = Some(*id);
-                        break;
-                    }
-                }
-                v
-            }
+    fn send_message(&self, config: &str, source: &str, msg: Message) -> Result<()> {
+        let adapter = self.adapter(config)?;
+        match source.split_once(':') {
+            Some((network, target)) if network == adapter.network() => adapter.send(target, msg),
+            _ => bail!("invalid source"),
         }
-        .ok_or_else(|| Error::msg("channel not found"))?;
-
-        if process {
-            let mut message = message.to_string();
-
-            let mut replacements = Self::dis_get_replacements(guildobj, false);
-
-            replacements.sort_by(|l, r| {
-                if l.0.len() != r.0.len() {
-                    return l.0.len().cmp(&r.0.len()).reverse();
-                }
-
-                l.0.cmp(&r.0)
-            });
-
-            {
-                for (find, replace) in replacements {
-                    let mut need_replace = false;
-
-                    let is_replace_before_ok = |c| {
-                        let cat = unic_ucd::GeneralCategory::of(c);
-
-                        cat.is_separator() || cat.is_punctuation()
-                    };
-
-                    // Check whether we actually need to do anything.
-                    // Most of the time, we don't, so we can avoid allocating.
-                    if message.ends_with(&find) {
-                        need_replace = true;
-                    } else {
-                        for part in message.split(&find).skip(1) {
-                            if part.starts_with(is_replace_before_ok) {
-                                need_replace = true;
-                            }
-                        }
-                    }
-
-                    if need_replace {
-                        let mut parts = message.split(&find);
-                        let mut new_parts = vec![parts.next().unwrap()];
-
-                        for part in parts {
-                            if part.is_empty() || part.starts_with(is_replace_before_ok) {
-                                new_parts.push(&replace);
-                            } else {
-                                new_parts.push(&find);
-                            }
-                            new_parts.push(part);
-                        }
-
-                        message = new_parts.join("");
-                    }
-                }
-            }
+    }
 
-            chanid.say(Arc::clone(&cache_and_http.http), message)?;
-        } else {
-            chanid.say(Arc::clone(&cache_and_http.http), message)?;
-        }
+    fn schedule(&self, job: Job) -> Result<i64> {
+        let id = scheduler::insert(&mut **self.sql()?, &job)?;
+        self.scheduler.wake();
+        Ok(id)
+    }
 
-        Ok(())
+    fn unschedule(&self, id: i64) -> Result<bool> {
+        scheduler::delete(&mut **self.sql()?, id)
     }
 
-    fn send_message(&self, config: &str, source: &str, msg: Message) -> Result<()> {
-        let parts: Vec<_> = source.split(':').collect();
-        if parts[0] == "irc" && parts.len() == 2 {
-            let msg = message::format_irc(msg)?;
-            for line in msg {
-                self.irc_send_privmsg(config, parts[1], &line)?;
-            }
-            Ok(())
-        } else if parts[0] == "dis" && parts.len() == 3 {
-            self.dis_send_message(config, parts[1], parts[2], &message::format_discord(msg)?, true)
-        } else {
-            bail!("invalid source")
-        }
+    fn scheduled(&self, handler: &str) -> Result<Vec<Job>> {
+        scheduler::list(&mut **self.sql()?, Some(handler))
     }
 }
 
@@ -849,10 +880,13 @@ pub fn start() -> Result<()> {
     // Load the config
     let config = config::load()?;
 
-    let b = Arc::new(Rustbot {
-        clients: RwLock::new(BTreeMap::new()),
-        caches: RwLock::new(BTreeMap::new()),
-        db: Mutex::new(db::open(&config.postgres)?),
+    let (pool, listen) = db::open(&config)?;
+
+    let limits = config.limits;
+    let b = Arc::new_cyclic(|this| Rustbot {
+        this: this.clone(),
+        adapters: RwLock::new(BTreeMap::new()),
+        db: pool,
         modules: RwLock::new(BTreeMap::new()),
         core_commands: RwLock::new(core::get_commands()),
         commands: RwLock::new(BTreeMap::new()),
@@ -860,93 +894,68 @@ pub fn start() -> Result<()> {
             logger,
             current_level: Level::Info,
         }),
+        generations: cache::Generations::new(),
+        enabled_modules: Cached::new(&["modules", "enabled_modules"]),
+        cmdchars: Cached::new(&["cmdchars"]),
+        aliases: alias::Aliases::new(),
+        perms: perms::Permissions::new(),
+        limits,
+        cooldowns: ratelimit::Limiter::new(),
         suppress_errors: RwLock::new(BTreeMap::new()),
+        building: AtomicBool::new(false),
+        scheduler: scheduler::Scheduler::new(),
     });
 
     b.update_logger_spec()?;
 
+    if let Some(conn_str) = listen {
+        let b = Arc::clone(&b);
+        thread::spawn(move || {
+            run_with_backoff("listening for database changes", &|| {
+                db::listen(&conn_str, &|table| b.invalidate(table))
+            })
+        });
+    }
+
     {
         let modules: Vec<String> = {
-            let mut db = b.db.lock();
+            let mut db = b.sql()?;
             db.query("SELECT name FROM modules WHERE enabled = true", &[])?
                 .iter()
-                .map(|row| row.get(0))
-                .collect()
+                .map(|row| row.try_get(0))
+                .collect::<Result<_>>()?
         };
-        for m in modules {
-            b.load_module(m.as_str()).unwrap();
+        // one broken module shouldn't keep the rest of the bot from starting
+        for (m, e) in b.load_modules(&modules) {
+            error!("failed to load module {}: {:?}", m, e);
         }
     }
 
-    for c in config.irc {
-        let channels: Vec<String> = {
-            let mut db = b.db.lock();
-            let cid = c.id.clone();
-            db.query("SELECT channel FROM irc_channels WHERE config_id = $1", &[&cid])?
-                .iter()
-                .map(|row| row.get(0))
-                .collect()
-        };
-
-        let b = b.clone();
+    {
+        let b = Arc::clone(&b);
         thread::Builder::new()
-            .name(format!("IRC: {}", irc_descriptor(&c)))
-            .spawn(move || {
-                run_with_backoff(&format!("IRC connection for {}", c.id), &|| {
-                    let client = Arc::new(
-                        irc::IrcClient::from_config(irc::Config {
-                            nickname: Some(c.nick.clone()),
-                            username: Some(c.user.clone()),
-                            realname: Some(c.real.clone()),
-                            server: Some(c.server.clone()),
-                            port: Some(c.port),
-                            use_ssl: Some(c.ssl),
-                            channels: Some(channels.clone()),
-                            password: c.pass.clone(),
-                            ..Default::default()
-                        })
-                        .map_err(from_irc)?,
-                    );
-                    client.send_cap_req(&[irc::Capability::MultiPrefix]).map_err(from_irc)?;
-                    client.identify().map_err(from_irc)?;
-                    b.clients.write().insert(c.id.clone(), client.clone());
-                    info!("connect: {}", irc_descriptor(&c));
-                    client
-                        .for_each_incoming(|irc_msg| {
-                            let b = b.clone();
-                            let id = c.id.clone();
-                            rayon::spawn(move || {
-                                let client = { b.clients.read().get(&id).unwrap().clone() };
-                                b.irc_incoming(id.clone(), client.current_nickname(), irc_msg);
-                            });
-                        })
-                        .map_err(from_irc)?;
-                    Ok(())
-                });
-            })?;
+            .name("scheduler".to_string())
+            .spawn(move || scheduler::run(&b))?;
     }
 
+    let mut adapters: Vec<Arc<dyn Adapter>> = vec![];
+    for c in config.irc {
+        adapters.push(Arc::new(adapter::irc::IrcAdapter::new(c)));
+    }
     for c in config.discord {
-        let b = b.clone();
-        thread::Builder::new()
-            .name(format!("Discord: {}", c.id.clone()))
-            .spawn(move || {
-                run_with_backoff("Discord connection", &|| {
-                    let mut dis = dis::Client::new(
-                        &c.token,
-                        DiscordBot {
-                            id: c.id.clone(),
-                            bot: b.clone(),
-                        },
-                    )?;
+        adapters.push(Arc::new(adapter::discord::DiscordAdapter::new(c)));
+    }
+    for c in config.matrix {
+        adapters.push(Arc::new(adapter::matrix::MatrixAdapter::new(c)?));
+    }
+    for c in config.console {
+        adapters.push(Arc::new(adapter::console::ConsoleAdapter::new(c)));
+    }
 
-                    b.caches.write().insert(c.id.clone(), dis.cache_and_http.clone());
-                    info!("connect: {}", c.id);
-                    dis.start()?;
-                    Ok(())
-                });
-            })?;
+    for adapter in adapters {
+        b.start_adapter(adapter)?;
     }
+
     Ok(())
 }
 
@@ -973,25 +982,6 @@ fn run_with_backoff(desc: &str, f: &dyn Fn() -> Result<()>) {
     }
 }
 
-fn irc_descriptor(c: &config::Irc) -> String {
-    format!("{} ({}:{})", c.id, c.server, c.port,)
-}
-
-struct DiscordBot {
-    id: String,
-    bot: Arc<Rustbot>,
-}
-
-impl dis::EventHandler for DiscordBot {
-    fn message(&self, disctx: dis::Context, msg: channel::Message) {
-        let id = self.id.clone();
-        let bot = self.bot.clone();
-        rayon::spawn(move || {
-            bot.dis_incoming(id, disctx, msg);
-        });
-    }
-}
-
 use ouroboros::self_referencing;
 
 #[self_referencing]
@@ -1001,27 +991,69 @@ pub struct Module {
     meta: Meta,
 }
 
-fn load_module(name: &str, lib: Library) -> Result<Module> {
+fn library_path(name: &str) -> String {
+    if cfg!(debug_assertions) {
+        format!("libmod_{name}.so")
+    } else {
+        format!("target/release/libmod_{name}.so")
+    }
+}
+
+// Where the loader finds a module's library.
+pub fn find_library(name: &str) -> Result<PathBuf> {
+    let path = library_path(name);
+    // like the loader, look for bare file names in LD_LIBRARY_PATH
+    let found = if path.contains('/') {
+        Some(PathBuf::from(&path))
+    } else {
+        env::var_os("LD_LIBRARY_PATH")
+            .and_then(|dirs| env::split_paths(&dirs).map(|dir| dir.join(&path)).find(|p| p.exists()))
+    };
+    match found {
+        Some(p) => Ok(p),
+        None => bail!("couldn't find {}", path),
+    }
+}
+
+// Opens a module's library from a copy, for loading it while the old build is still loaded: the
+// dynamic loader would otherwise just hand back the library it already has open at that path.
+fn open_library_copy(name: &str) -> Result<Library> {
+    static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+    let found = find_library(name)?;
+    let copy = env::temp_dir().join(format!(
+        "rustbot-{}-{}-{}",
+        std::process::id(),
+        COPIES.fetch_add(1, Ordering::SeqCst),
+        found.file_name().unwrap().to_string_lossy()
+    ));
+    fs::copy(&found, &copy).with_context(|| format!("failed to copy {} for reloading", found.display()))?;
+    let lib = Library::new(&copy);
+    // once it's open, the file isn't needed
+    fs::remove_file(&copy)?;
+    Ok(lib?)
+}
+
+fn load_module(name: &str, lib: Library, state: Option<State>) -> Result<Module> {
     let m = Module::try_new(Box::new(lib), |lib| unsafe {
-        match lib.get::<unsafe fn(&mut dyn types::Meta)>(b"get_meta") {
-            Ok(f) => {
-                let mut m = Meta::new();
-                f(&mut m);
-                Ok(m)
+        let decl = match lib.get::<*const abi::ModuleDecl>(abi::DECL_SYMBOL) {
+            Ok(decl) => *decl,
+            Err(_) => bail!(
+                "module {} has no module declaration; it needs rebuilding with rustbot::module!",
+                name
+            ),
+        };
+        let get_meta = abi::ModuleDecl::check(decl)?;
+
+        let mut m = Meta::new();
+        m.restored_state = state;
+        get_meta(&mut m, config::load()?.module.remove(name))?;
+        if let Some(declared) = &m.name {
+            if declared != name {
+                bail!("libmod_{}.so contains module {}", name, declared);
             }
-            Err(e) => match lib.get::<unsafe fn(&mut dyn types::Meta, toml::Value) -> Result<()>>(b"get_meta_conf") {
-                Ok(f) => {
-                    if let Some(c) = config::load()?.module.remove(name) {
-                        let mut m = Meta::new();
-                        f(&mut m, c)?;
-                        Ok(m)
-                    } else {
-                        bail!("required config not passed")
-                    }
-                }
-                Err(e2) => bail!("{}, {}", e, e2),
-            },
         }
+        Ok(m)
     })?;
 
     Ok(m)
@@ -1031,8 +1063,19 @@ pub struct Meta {
     commands: BTreeMap<String, Command>,
     deinit: Option<Box<DeinitFn>>,
     handlers: Vec<(HandleType, Box<MsgHandlerFn>)>,
+    event_handlers: Vec<(EventType, Box<EventHandlerFn>)>,
     unload_channels: Vec<Sender<()>>,
+    // threads are only started once the module's dependencies are known to be loaded
+    pending_threads: Mutex<Vec<Box<ThreadFn>>>,
     threads: Vec<std::thread::JoinHandle<()>>,
+    permissions: BTreeMap<String, String>,
+    name: Option<String>,
+    version: String,
+    depends: Vec<(String, String)>,
+    services: BTreeMap<String, types::Service>,
+    save_state: Option<Box<SaveStateFn>>,
+    restored_state: Option<State>,
+    jobs: BTreeMap<String, Box<JobFn>>,
 }
 
 impl Meta {
@@ -1041,8 +1084,32 @@ impl Meta {
             commands: BTreeMap::new(),
             deinit: None,
             handlers: Vec::new(),
+            event_handlers: Vec::new(),
             unload_channels: Vec::new(),
+            pending_threads: Mutex::new(Vec::new()),
             threads: Vec::new(),
+            permissions: BTreeMap::new(),
+            name: None,
+            version: "0.0.0".to_string(),
+            depends: Vec::new(),
+            services: BTreeMap::new(),
+            save_state: None,
+            restored_state: None,
+            jobs: BTreeMap::new(),
+        }
+    }
+
+    fn start_threads(&mut self) {
+        for f in self.pending_threads.get_mut().drain(..) {
+            self.threads.push(std::thread::spawn(f));
+        }
+    }
+
+    fn info(&self, name: &str) -> deps::Info {
+        deps::Info {
+            name: name.to_string(),
+            version: self.version.clone(),
+            depends: self.depends.clone(),
         }
     }
 }
@@ -1057,6 +1124,9 @@ impl types::Meta for Meta {
     fn handle(&mut self, typ: HandleType, f: Box<MsgHandlerFn>) {
         self.handlers.push((typ, f));
     }
+    fn on_event(&mut self, typ: EventType, f: Box<EventHandlerFn>) {
+        self.event_handlers.push((typ, f));
+    }
     fn on_unload_channel(&mut self) -> Receiver<()> {
         let (send, recv) = oneshot::channel();
 
@@ -1064,10 +1134,28 @@ impl types::Meta for Meta {
         recv
     }
     fn thread(&mut self, f: Box<ThreadFn>) {
-        self.threads.push(std::thread::spawn(f));
+        self.pending_threads.get_mut().push(f);
+    }
+    fn permission(&mut self, name: &str, description: &str) {
+        self.permissions.insert(name.to_string(), description.to_string());
+    }
+    fn module(&mut self, name: &str, version: &str) {
+        self.name = Some(name.to_string());
+        self.version = version.to_string();
+    }
+    fn depends(&mut self, module: &str, version: &str) {
+        self.depends.push((module.to_string(), version.to_string()));
+    }
+    fn provide_service(&mut self, name: &str, service: types::Service) {
+        self.services.insert(name.to_string(), service);
+    }
+    fn save_state(&mut self, f: Box<SaveStateFn>) {
+        self.save_state = Some(f);
+    }
+    fn restored_state(&mut self) -> Option<State> {
+        self.restored_state.clone()
+    }
+    fn schedule(&mut self, handler: &str, f: Box<JobFn>) {
+        self.jobs.insert(handler.to_string(), f);
     }
-}
-
-fn from_irc(e: ::irc::error::IrcError) -> Error {
-    Error::msg(format!("{e}"))
 }
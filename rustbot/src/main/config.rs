@@ -6,12 +6,21 @@ use rustbot::prelude::*;
 
 #[derive(Deserialize)]
 pub struct Config {
-    pub postgres: Postgres,
+    // Exactly one of these should be set.
+    pub postgres: Option<Postgres>,
+    pub sqlite: Option<Sqlite>,
 
     #[serde(default)]
     pub irc: Vec<Irc>,
     #[serde(default)]
     pub discord: Vec<Discord>,
+    #[serde(default)]
+    pub matrix: Vec<Matrix>,
+    #[serde(default)]
+    pub console: Vec<Console>,
+
+    #[serde(default)]
+    pub limits: Limits,
 
     #[serde(default)]
     pub module: BTreeMap<String, toml::Value>,
@@ -25,6 +34,55 @@ pub struct Postgres {
 
     pub host: String,
     pub port: u16,
+
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+
+#[derive(Deserialize)]
+pub struct Sqlite {
+    pub path: String,
+
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+
+fn default_pool_size() -> u32 {
+    8
+}
+
+// A token bucket; see crate::ratelimit.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    // how many can be used at once, after a while idle
+    pub burst: u32,
+    // seconds until another can be used
+    pub every: f64,
+}
+
+impl Limit {
+    // A bucket that never refills would stop whatever it limits for good.
+    pub fn check(&self, setting: &str) -> Result<()> {
+        if self.burst == 0 {
+            bail!("{}.burst must be at least 1", setting);
+        }
+        if !self.every.is_finite() || self.every <= 0.0 {
+            bail!("{}.every must be a number of seconds above 0", setting);
+        }
+        Ok(())
+    }
+}
+
+// Limits on how often users can run commands; there are none unless configured. Anyone with the
+// admin permission is exempt.
+#[derive(Deserialize, Default)]
+pub struct Limits {
+    // for each user, across all commands
+    #[serde(default)]
+    pub user: Option<Limit>,
+    // for each user, for particular commands
+    #[serde(default)]
+    pub command: BTreeMap<String, Limit>,
 }
 
 #[derive(Deserialize)]
@@ -41,6 +99,25 @@ pub struct Irc {
     pub pass: Option<String>,
 
     pub ssl: bool,
+    // a PKCS#12 certificate to identify with, e.g. for SASL EXTERNAL
+    pub client_cert: Option<String>,
+    pub client_cert_pass: Option<String>,
+
+    pub sasl: Option<Sasl>,
+
+    #[serde(default = "default_irc_send_limit")]
+    pub send_limit: Limit,
+}
+
+fn default_irc_send_limit() -> Limit {
+    Limit { burst: 5, every: 2.0 }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "mechanism", rename_all = "lowercase")]
+pub enum Sasl {
+    Plain { user: String, password: String },
+    External,
 }
 
 #[derive(Deserialize)]
@@ -48,8 +125,79 @@ pub struct Discord {
     pub id: String,
 
     pub token: String,
+
+    // for each channel
+    #[serde(default = "default_discord_send_limit")]
+    pub send_limit: Limit,
+}
+
+fn default_discord_send_limit() -> Limit {
+    Limit { burst: 5, every: 1.0 }
+}
+
+#[derive(Deserialize)]
+pub struct Matrix {
+    pub id: String,
+
+    pub homeserver: String,
+    pub user: String,
+    pub access_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct Console {
+    pub id: String,
+
+    // Listen on this Unix socket rather than reading from stdin.
+    pub socket: Option<String>,
+
+    #[serde(default = "default_console_name")]
+    pub user: String,
+    #[serde(default = "default_console_name")]
+    pub channel: String,
+    // Permissions the user has without being granted them, by name; "*" gives every permission.
+    #[serde(default)]
+    pub perms: Vec<String>,
+
+    #[serde(default)]
+    pub format: ConsoleFormat,
+}
+
+fn default_console_name() -> String {
+    "console".to_string()
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleFormat {
+    #[default]
+    Plain,
+    Irc,
 }
 
 pub fn load() -> Result<Config> {
-    Ok(toml::from_str(&fs::read_to_string("Rustbot.toml")?)?)
+    let config: Config = toml::from_str(&fs::read_to_string("Rustbot.toml")?)?;
+    config.check()?;
+    Ok(config)
+}
+
+impl Config {
+    // Checks what serde can't; settings are named as in the file, with adapters by their ID.
+    fn check(&self) -> Result<()> {
+        if let Some(limit) = &self.limits.user {
+            limit.check("limits.user")?;
+        }
+        for (command, limit) in &self.limits.command {
+            limit.check(&format!("limits.command.{command}"))?;
+        }
+        for irc in &self.irc {
+            irc.send_limit.check(&format!("irc.{}.send_limit", irc.id))?;
+        }
+        for discord in &self.discord {
+            discord
+                .send_limit
+                .check(&format!("discord.{}.send_limit", discord.id))?;
+        }
+        Ok(())
+    }
 }
@@ -1,8 +1,9 @@
+use crate::adapter::AdapterSource;
 use crate::bot;
-use crate::message;
+use parking_lot::Mutex;
+use rustbot::perms::ALL;
 use rustbot::prelude::*;
 use rustbot::types;
-use serenity::model::prelude as ser;
 use std::borrow::Cow;
 use std::sync::Arc;
 
@@ -10,40 +11,25 @@ pub struct Context<'a> {
     pub bot: &'a bot::Rustbot,
     pub config: String,
     pub source: Source,
-    pub bot_name: String,
 }
 
 impl<'a> Context<'a> {
-    fn reply_impl(&self, source: &Source, message: Message) -> Result<()> {
+    fn reply_impl(&self, source: &Source, message: Message, capture: bool) -> Result<()> {
         match source {
-            Source::Irc { prefix, channel } => {
-                if let Some(Prefix::User { nick, .. }) = prefix {
-                    match channel {
-                        None => {
-                            for msg in message::format_irc(message)? {
-                                self.bot.irc_send_privmsg(&self.config, nick.as_str(), msg.as_str())?;
-                            }
-                        }
-
-                        Some(ch) => {
-                            for msg in message::format_irc(message)? {
-                                self.bot.irc_send_privmsg(
-                                    &self.config,
-                                    ch.as_str(),
-                                    &format!("{}: {}", nick.as_str(), msg.as_str()),
-                                )?;
-                            }
-                        }
-                    }
-                }
+            Source::Adapter(s) => self.bot.adapter(&self.config)?.reply(s.as_ref(), message),
+            Source::Sub { parent, .. } => self.reply_impl(parent, message, capture),
+            Source::Capture { output, .. } if capture => {
+                output.lock().push(message.to_text());
+                Ok(())
             }
-            Source::Discord { channel, http, .. } => {
-                channel.say(http, message::format_discord(message)?).map(|_| ())?;
-            }
-            Source::Sub { parent, .. } => return self.reply_impl(parent, message),
+            Source::Capture { parent, .. } => self.reply_impl(parent, message, capture),
         }
+    }
 
-        Ok(())
+    // Says something to whoever sent the message even from a stage of a pipeline, whose replies
+    // would otherwise be passed on to the next stage.
+    pub fn say_uncaptured(&self, message: &str) -> Result<()> {
+        self.reply_impl(&self.source, Message::Simple(message.to_string()), false)
     }
 }
 
@@ -65,57 +51,22 @@ impl<'a> types::Context for Context<'a> {
     }
 
     fn reply(&self, message: Message) -> Result<()> {
-        self.reply_impl(&self.source, message)
-    }
-
-    fn perms(&self) -> Result<Perms> {
-        // TODO
-        // self.bot.perms(&self.config, &self.source)
-        match &self.source {
-            Source::Irc {
-                prefix: Some(Prefix::User { nick, user, host }),
-                ..
-            } => {
-                let perms: Perms = match self.bot.sql().lock().query(
-                    "SELECT flags FROM irc_permissions WHERE config_id = $1 AND nick = $2 AND username = $3 AND host = $4",
-                    &[&self.config, &nick, &user, &host],
-                ) {
-                    Err(e) => {
-                        error!("error fetching perms: {}", e);
-                        Perms::None
-                    }
-                    Ok(v) => {
-                        if v.is_empty() {
-                            Perms::None
-                        } else {
-                            v.get(0).unwrap().get(0)
-                        }
-                    }
-                };
-                Ok(perms)
-            }
-            Source::Irc { .. } => Ok(Perms::None),
-            Source::Discord { user, .. } => {
-                let perms: Perms = match self.bot.sql().lock().query(
-                    "SELECT flags FROM dis_permissions WHERE config_id = $1 AND user_id = $2",
-                    &[&self.config, &(*user.id.as_u64() as i64)],
-                ) {
-                    Err(e) => {
-                        error!("error fetching perms: {}", e);
-                        Perms::None
-                    }
-                    Ok(v) => {
-                        if v.is_empty() {
-                            Perms::None
-                        } else {
-                            v.get(0).unwrap().get(0)
-                        }
-                    }
-                };
-                Ok(perms)
-            }
-            Source::Sub { .. } => Ok(Perms::None), // TODO
+        self.reply_impl(&self.source, message, true)
+    }
+
+    fn has_perm(&self, name: &str) -> Result<bool> {
+        if self.source.implicit_perms().iter().any(|p| p == name || p == ALL) {
+            return Ok(true);
         }
+
+        let subjects = self.bot.perms.expand_groups(self.bot, self.source.subjects())?;
+        self.bot.perms.has_perm(
+            self.bot,
+            &subjects,
+            &self.config,
+            &types::Source::channel_string(&self.source),
+            name,
+        )
     }
 
     fn do_sub(&self, name: &str, msg: &str) -> Result<()> {
@@ -127,119 +78,119 @@ impl<'a> types::Context for Context<'a> {
                     parent: Box::new(self.source.clone()),
                     name: name.to_string(),
                 },
-                bot_name: self.bot_name.clone(),
             },
             HandleType::PlainMsg,
             msg,
         )
     }
+
+    fn find_user(&self, user: &Mention) -> Result<Option<String>> {
+        match self.source.adapter_source() {
+            Some(s) => self.bot.adapter(&self.config)?.find_user(s.as_ref(), user),
+            None => Ok(None),
+        }
+    }
+
+    fn find_channel(&self, channel: &ChannelRef) -> Result<Option<String>> {
+        match self.source.adapter_source() {
+            Some(s) => self.bot.adapter(&self.config)?.find_channel(s.as_ref(), channel),
+            None => Ok(None),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum Source {
-    Irc {
-        prefix: Option<Prefix>,
-        channel: Option<String>,
-    },
-    Discord {
-        user: ser::User,
-        channel: ser::ChannelId,
-        guild: Option<ser::GuildId>,
-
-        cache: serenity::cache::CacheRwLock,
-        http: Arc<serenity::http::Http>,
-    },
+    Adapter(Arc<dyn AdapterSource>),
     Sub {
         parent: Box<Source>,
         name: String,
     },
+    // a stage of a pipeline before the last, whose replies are kept to pass on to the next stage
+    // rather than sent; it's otherwise the parent in every way
+    Capture {
+        parent: Box<Source>,
+        output: Arc<Mutex<Vec<String>>>,
+    },
+}
+
+impl Source {
+    pub fn subjects(&self) -> Vec<String> {
+        use types::Source as _;
+        match self {
+            Source::Adapter(s) => s.subjects(),
+            // relayed users get their own grants; they shouldn't inherit the relay's
+            Source::Sub { .. } => vec![format!("sub:{}", self.user_string())],
+            Source::Capture { parent, .. } => parent.subjects(),
+        }
+    }
+
+    fn implicit_perms(&self) -> Vec<String> {
+        match self {
+            Source::Adapter(s) => s.implicit_perms(),
+            Source::Sub { .. } => vec![],
+            Source::Capture { parent, .. } => parent.implicit_perms(),
+        }
+    }
+
+    // The adapter's source behind this one, if names it gives mean what they do on the adapter's
+    // network, which those of a relayed user needn't.
+    fn adapter_source(&self) -> Option<&Arc<dyn AdapterSource>> {
+        match self {
+            Source::Adapter(s) => Some(s),
+            Source::Sub { .. } => None,
+            Source::Capture { parent, .. } => parent.adapter_source(),
+        }
+    }
+
+    // How many pipelines the source is running a stage of; see Rustbot::handle_inner.
+    pub fn pipeline_depth(&self) -> usize {
+        match self {
+            Source::Adapter(_) => 0,
+            Source::Sub { parent, .. } => parent.pipeline_depth(),
+            Source::Capture { parent, .. } => parent.pipeline_depth() + 1,
+        }
+    }
 }
 
 impl types::Source for Source {
     fn user_string(&self) -> Cow<str> {
         match self {
-            Source::Irc { prefix, .. } => {
-                if let Some(prefix) = prefix {
-                    format!("{prefix}").into()
-                } else {
-                    "none".into()
-                }
-            }
-            Source::Discord { user, guild, .. } => {
-                format!("{:?}:{}", guild.map(|g| *g.as_u64()), user.id.as_u64()).into()
-            }
+            Source::Adapter(s) => s.user_string(),
             Source::Sub { parent, name } => format!("{}@{}", parent.user_string(), name).into(),
+            Source::Capture { parent, .. } => parent.user_string(),
         }
     }
 
     fn user_pretty(&self) -> Cow<str> {
         match self {
-            Source::Irc { prefix, .. } => match prefix {
-                Some(Prefix::User { nick, .. }) => nick.into(),
-                Some(Prefix::Server(s)) => s.into(),
-                None => "???".into(),
-            },
-            Source::Discord { user, .. } => (&user.name).into(),
+            Source::Adapter(s) => s.user_pretty(),
             Source::Sub { name, .. } => name.into(),
+            Source::Capture { parent, .. } => parent.user_pretty(),
         }
     }
 
     fn channel_string(&self) -> Cow<str> {
         match self {
-            Source::Irc { channel, .. } => {
-                if let Some(channel) = channel {
-                    format!("irc:{channel}")
-                } else {
-                    "irc:query".to_string()
-                }
-            }
-            Source::Discord { channel, guild, .. } => format!(
-                "dis:{}:{}",
-                guild
-                    .map(|g| format!("{}", *g.as_u64()))
-                    .unwrap_or_else(|| "none".to_string()),
-                channel.as_u64()
-            ),
-            Source::Sub { parent, .. } => parent.channel_string().into_owned(),
+            Source::Adapter(s) => s.channel_string(),
+            Source::Sub { parent, .. } => parent.channel_string().into_owned().into(),
+            Source::Capture { parent, .. } => parent.channel_string(),
         }
-        .into()
     }
 
     fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
-        if let Source::Discord {
-            guild, channel, user, ..
-        } = self
-        {
-            Some((guild.map(|g| *g.as_u64()), *channel.as_u64(), *user.id.as_u64()))
-        } else {
-            None
+        match self {
+            Source::Adapter(s) => s.get_discord_params(),
+            Source::Sub { .. } => None,
+            Source::Capture { parent, .. } => parent.get_discord_params(),
         }
     }
 
     fn get_irc_params(&self) -> Option<(Option<String>, String)> {
-        if let Source::Irc { prefix, channel, .. } = self {
-            match prefix {
-                Some(Prefix::User { nick, .. }) => Some((channel.clone(), nick.clone())),
-                Some(Prefix::Server(s)) => Some((channel.clone(), s.clone())),
-                None => None,
-            }
-        } else {
-            None
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub enum Prefix {
-    Server(String),
-    User { nick: String, user: String, host: String },
-}
-
-impl std::fmt::Display for Prefix {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
         match self {
-            Self::Server(s) => write!(f, "{s}"),
-            Self::User { nick, user, host } => write!(f, "{nick}!{user}@{host}"),
+            Source::Adapter(s) => s.get_irc_params(),
+            Source::Sub { .. } => None,
+            Source::Capture { parent, .. } => parent.get_irc_params(),
         }
     }
 }
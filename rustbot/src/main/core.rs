@@ -1,31 +1,234 @@
 use log::Level;
+use rustbot::perms::{glob, ALL};
 use rustbot::prelude::*;
+use rustbot::schedule;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::process::Command as ProcessCommand;
 use std::str;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::alias;
 use crate::context::Context;
+use crate::ratelimit::SendQueue;
+use crate::recompile;
+use crate::scheduler;
 use rustbot::types::Context as TypesContext; // trait
 
 pub type CoreCommand = dyn Fn(&Context, &str) -> Result<()> + Send + Sync;
 
-pub fn get_commands() -> BTreeMap<String, (Perms, Box<CoreCommand>)> {
-    let mut cmds: BTreeMap<_, (_, Box<CoreCommand>)> = BTreeMap::new();
+pub type CoreCommands = BTreeMap<String, Command<CoreCommand>>;
 
-    cmds.insert("drop".to_string(), (Perms::Modules, Box::new(drop)));
-    cmds.insert("load".to_string(), (Perms::Modules, Box::new(load)));
-    cmds.insert("reload".to_string(), (Perms::Modules, Box::new(reload)));
-    cmds.insert("recompile".to_string(), (Perms::Modules, Box::new(recompile)));
-    cmds.insert("log".to_string(), (Perms::Modules, Box::new(log)));
-    cmds.insert("suppress".to_string(), (Perms::Modules, Box::new(suppress)));
-    cmds.insert(
-        "enable".to_string(),
-        (Perms::Modules, Box::new(move |ctx, args| set_enabled(ctx, args, true))),
+// Command::new for the bot's own commands.
+fn cmd(f: impl Fn(&Context, &str) -> Result<()> + Send + Sync + 'static) -> Command<CoreCommand> {
+    Command {
+        function: Some(Arc::new(f)),
+        ..Command::group()
+    }
+}
+
+pub fn get_commands() -> CoreCommands {
+    let mut cmds = CoreCommands::new();
+
+    let mut add = |name: &str, command: Command<CoreCommand>| {
+        cmds.insert(name.to_string(), command);
+    };
+
+    add(
+        "drop",
+        cmd(drop)
+            .req_perms(Perms::Modules)
+            .usage("[--cascade] <module>...")
+            .summary("unloads modules, and anything needing them too with --cascade"),
+    );
+    add(
+        "load",
+        cmd(load)
+            .req_perms(Perms::Modules)
+            .usage("<module>...")
+            .summary("loads modules"),
+    );
+    add(
+        "reload",
+        cmd(reload)
+            .req_perms(Perms::Modules)
+            .usage("[module...]")
+            .summary("swaps in new builds of modules, or with none, forgets cached data"),
+    );
+    add(
+        "recompile",
+        cmd(recompile)
+            .req_perms(Perms::Modules)
+            .usage("[module...]")
+            .summary("rebuilds modules and reloads those that changed"),
+    );
+    add("modules", cmd(modules).summary("lists the loaded modules"));
+    add(
+        "log",
+        Command::group()
+            .req_perms(Perms::Modules)
+            .summary("sets log levels")
+            .sub(
+                "set",
+                cmd(log_set).usage("<level>").summary("sets the overall log level"),
+            )
+            .sub(
+                "module",
+                cmd(log_module)
+                    .usage("<module> <level|none>")
+                    .summary("sets a module's log level, or with none, goes back to the overall one"),
+            ),
+    );
+    add(
+        "suppress",
+        cmd(suppress)
+            .req_perms(Perms::Modules)
+            .usage("<module> <duration>")
+            .summary("hides a module's errors for a while"),
+    );
+    add(
+        "enable",
+        cmd(move |ctx, args| set_enabled(ctx, args, true))
+            .req_perms(Perms::Modules)
+            .usage("<config_id> <module>...")
+            .summary("enables modules for a connection"),
+    );
+    add(
+        "disable",
+        cmd(move |ctx, args| set_enabled(ctx, args, false))
+            .req_perms(Perms::Modules)
+            .usage("<config_id> <module>...")
+            .summary("disables modules for a connection"),
+    );
+    add(
+        "grant",
+        cmd(grant)
+            .req_perms(Perms::Admin)
+            .usage("<subject> <permission> [config_id [channel]]")
+            .summary("grants a permission"),
+    );
+    add(
+        "revoke",
+        cmd(revoke)
+            .req_perms(Perms::Admin)
+            .usage("<subject> <permission> [config_id [channel]]")
+            .summary("revokes a granted permission"),
+    );
+    add(
+        "grants",
+        cmd(grants)
+            .req_perms(Perms::Admin)
+            .usage("[subject]")
+            .summary("lists granted permissions"),
+    );
+    add(
+        "group",
+        Command::group()
+            .req_perms(Perms::Admin)
+            .summary("manages permission groups")
+            .sub(
+                "add",
+                cmd(group_add)
+                    .usage("<name> <member>")
+                    .summary("adds a member to a group"),
+            )
+            .sub(
+                "remove",
+                cmd(group_remove)
+                    .usage("<name> <member>")
+                    .summary("removes a member from a group"),
+            )
+            .sub(
+                "list",
+                cmd(group_list)
+                    .usage("[name]")
+                    .summary("lists groups and their members"),
+            ),
+    );
+    add(
+        "cmdperm",
+        cmd(cmdperm)
+            .req_perms(Perms::Admin)
+            .usage("<command> <permission|any|off|default> [config_id [channel]]")
+            .summary("overrides a command's permission, or a subcommand's given as e.g. list.add"),
+    );
+    add(
+        "permissions",
+        cmd(permissions).summary("lists the permissions there are"),
+    );
+    add(
+        "ratelimit",
+        Command::group()
+            .req_perms(Perms::Admin)
+            .summary("shows or resets command rate limits")
+            .sub(
+                "list",
+                cmd(ratelimit_list)
+                    .usage("[pattern]")
+                    .summary("lists the rate limit buckets and how full they are"),
+            )
+            .sub(
+                "reset",
+                cmd(ratelimit_reset)
+                    .usage("<pattern>")
+                    .summary("refills the buckets matching a pattern"),
+            ),
+    );
+    add(
+        "jobs",
+        Command::group()
+            .req_perms(Perms::Admin)
+            .summary("shows or cancels scheduled jobs")
+            .sub(
+                "list",
+                cmd(jobs_list).usage("[pattern]").summary("lists scheduled jobs"),
+            )
+            .sub(
+                "cancel",
+                cmd(jobs_cancel).usage("<id>").summary("cancels a scheduled job"),
+            ),
+    );
+    add(
+        "alias",
+        Command::group()
+            .req_perms(Perms::Admin)
+            .summary("manages command aliases")
+            .sub(
+                "add",
+                cmd(alias_add)
+                    .usage("[--config <id>] [--channel <pattern>] <name> <target>[;<target>...] [transform]")
+                    .summary("adds or changes an alias, optionally rewriting its arguments with a JSON transform"),
+            )
+            .sub(
+                "del",
+                cmd(alias_del)
+                    .usage("[--config <id>] [--channel <pattern>] <name>")
+                    .summary("deletes an alias"),
+            )
+            .sub(
+                "list",
+                cmd(|ctx, args| alias_list(ctx, args.trim(), false))
+                    .usage("[pattern]")
+                    .summary("lists aliases"),
+            )
+            .sub(
+                "show",
+                cmd(|ctx, args| alias_list(ctx, args.trim(), true))
+                    .usage("<name>")
+                    .summary("shows an alias, with its transform"),
+            ),
+    );
+    add(
+        "help",
+        cmd(help)
+            .usage("[command]")
+            .summary("describes a command, or lists them"),
     );
-    cmds.insert(
-        "disable".to_string(),
-        (Perms::Modules, Box::new(move |ctx, args| set_enabled(ctx, args, false))),
+    add(
+        "commands",
+        cmd(commands)
+            .usage("[module]")
+            .summary("lists the commands you can use here"),
     );
 
     cmds
@@ -46,48 +249,91 @@ fn exec(ctx: &Context, args: &str, what: fn(&Context, &str) -> Result<()>) -> Re
 }
 
 fn drop(ctx: &Context, args: &str) -> Result<()> {
-    exec(ctx, args, |ctx, m| ctx.bot.drop_module(m))
+    match args.strip_prefix("--cascade") {
+        Some(rest) => exec(ctx, rest.trim_start(), |ctx, m| ctx.bot.drop_module(m, true)),
+        None => exec(ctx, args, |ctx, m| ctx.bot.drop_module(m, false)),
+    }
+}
+
+// Loads modules all at once, so they can be given in any order regardless of dependencies.
+fn load_all(ctx: &Context, names: &[String]) -> Result<()> {
+    for (m, e) in ctx.bot.load_modules(names) {
+        ctx.say(&format!("{m} failed: {e}"))?;
+    }
+    Ok(())
+}
+
+fn modules_in(ctx: &Context, args: &str) -> Result<Vec<String>> {
+    let mut names = vec![];
+    for m in args.split_whitespace() {
+        if m == "core" {
+            ctx.say("skipping core")?;
+        } else {
+            names.push(m.to_string());
+        }
+    }
+    Ok(names)
 }
 
 fn load(ctx: &Context, args: &str) -> Result<()> {
-    exec(ctx, args, |ctx, m| ctx.bot.load_module(m))
+    load_all(ctx, &modules_in(ctx, args)?)?;
+    ctx.say("done")
 }
 
 fn reload(ctx: &Context, args: &str) -> Result<()> {
-    exec(ctx, args, |ctx, m| {
-        ctx.bot.drop_module(m)?;
-        ctx.bot.load_module(m)
-    })
+    if args.is_empty() {
+        // no modules given; forget everything cached from the database instead
+        ctx.bot().invalidate(None);
+        return ctx.say("done");
+    }
+
+    // each is swapped for its new build in place, so anything depending on it carries on as it is
+    for m in modules_in(ctx, args)? {
+        if let Err(e) = ctx.bot.reload_module(&m) {
+            ctx.say(&format!("{m} failed: {e:#}"))?;
+        }
+    }
+    ctx.say("done")
 }
 
 fn recompile(ctx: &Context, args: &str) -> Result<()> {
-    let mut cmd = ProcessCommand::new("cargo");
-    cmd.arg("build");
-    if !cfg!(debug_assertions) {
-        cmd.arg("--release");
-    }
+    recompile::start(ctx, modules_in(ctx, args)?)
+}
 
-    match cmd.output() {
-        Ok(result) => {
-            if result.status.success() {
-                reload(ctx, args)
-            } else {
-                // ctx.say("compile failed:")?;
-                let mut lines: Vec<&str> = vec![];
-                for line in str::from_utf8(&result.stderr).unwrap().split('\n') {
-                    if line.starts_with("   Compiling") {
-                        continue;
-                    }
-                    if line.is_empty() {
-                        break;
-                    }
-                    lines.push(line);
-                }
-                ctx.reply(Message::Code(lines.join("\n")))
+fn modules(ctx: &Context, _args: &str) -> Result<()> {
+    let infos = ctx.bot.module_infos();
+    let mut items: Vec<Cow<str>> = infos
+        .iter()
+        .map(|info| {
+            let mut item = format!("{} {}", info.name, info.version);
+            if !info.depends.is_empty() {
+                let depends: Vec<String> = info.depends.iter().map(|(m, v)| format!("{m} {v}")).collect();
+                item += &format!(" (needs {})", depends.join(", "));
             }
-        }
-        Err(e) => ctx.say(&format!("failed to run build: {e}")),
+            item.into()
+        })
+        .collect();
+
+    // enabled, but failed to load
+    let broken: Vec<String> = ctx
+        .bot()
+        .sql()?
+        .query("SELECT name FROM modules WHERE enabled ORDER BY name", &[])?
+        .iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<Vec<String>>>()?
+        .into_iter()
+        .filter(|name| !infos.iter().any(|i| i.name == *name))
+        .collect();
+    if !broken.is_empty() {
+        items.push(format!("not loaded: {}", broken.join(", ")).into());
     }
+
+    ctx.reply(Message::List {
+        prefix: "modules: ".into(),
+        sep: "; ".into(),
+        items,
+    })
 }
 
 fn parse_log_level(s: &str) -> Result<Option<Level>> {
@@ -102,30 +348,22 @@ fn parse_log_level(s: &str) -> Result<Option<Level>> {
     }))
 }
 
-fn log(ctx: &Context, args: &str) -> Result<()> {
-    let a = args.split(' ').collect::<Vec<_>>();
-    match a.as_slice() {
-        [module, level] => {
-            let level = parse_log_level(level)?;
-            ctx.bot.set_module_log_level(module, level)?;
-        }
-        [level] => match parse_log_level(level)? {
-            Some(level) => ctx.bot.set_log_level(level)?,
-            None => bail_user!("invalid log level specification"),
-        },
-        _ => bail_user!("unknown argument format; try 'log LEVEL' or 'log MODULE LEVEL'"),
+fn log_set(ctx: &Context, args: &str) -> Result<()> {
+    match parse_log_level(args.trim())? {
+        Some(level) => ctx.bot.set_log_level(level)?,
+        None => bail_user!("invalid log level specification"),
     }
     ctx.reply(Message::Simple("Done".to_string()))
 }
 
-fn suppress(ctx: &Context, args: &str) -> Result<()> {
-    let a = args.split(' ').collect::<Vec<&str>>();
-    if a.len() != 2 {
-        bail_user!("Usage: suppress <module> <seconds>");
-    }
+fn log_module(ctx: &Context, args: &str) -> Result<()> {
+    let (Atom(module), Atom(level)) = <(Atom, Atom)>::parse_full(args)?;
+    ctx.bot.set_module_log_level(&module, parse_log_level(&level)?)?;
+    ctx.reply(Message::Simple("Done".to_string()))
+}
 
-    let module = a[0].to_string();
-    let duration = parse_duration(a[1])?;
+fn suppress(ctx: &Context, args: &str) -> Result<()> {
+    let (Atom(module), duration) = <(Atom, Duration)>::parse_full(args)?;
     let ts = Instant::now() + duration;
 
     ctx.bot.suppress_errors.write().insert(module, ts);
@@ -133,28 +371,492 @@ fn suppress(ctx: &Context, args: &str) -> Result<()> {
     ctx.reply(Message::Simple("Done.".to_string()))
 }
 
-fn set_enabled(ctx: &Context, args: &str, target: bool) -> Result<()> {
-    let a = args.split(' ').collect::<Vec<&str>>();
-    if a.len() < 2 {
-        bail_user!("Usage: (enable/disable) config_id module [module [...]]");
-    }
+#[derive(Args)]
+struct SetEnabledArgs {
+    config_id: Atom,
+    modules: Vec<Atom>,
+}
 
-    let config_id = a[0];
+fn set_enabled(ctx: &Context, args: &str, target: bool) -> Result<()> {
+    let SetEnabledArgs { config_id, modules } = SetEnabledArgs::parse_full(args)?;
 
-    for m in &a[1..] {
-        let mut db = ctx.bot().sql().lock();
+    for m in &modules {
+        let mut db = ctx.bot().sql()?;
         if target {
             db.execute(
                 "INSERT INTO enabled_modules (config_id, name) VALUES ($1, $2)",
-                &[&config_id, &m],
+                &[&*config_id, &**m],
             )?;
         } else {
             db.execute(
                 "DELETE FROM enabled_modules WHERE config_id = $1 AND name = $2",
-                &[&config_id, &m],
+                &[&*config_id, &**m],
             )?;
         }
     }
+    ctx.bot().invalidate(Some("enabled_modules"));
 
     ctx.reply(Message::Simple("Done".to_string()))
 }
+
+// Splits off the optional trailing "[config_id [channel]]" of the permission commands.
+fn scope<'a>(args: &[&'a str], usage: &str) -> Result<(&'a str, &'a str)> {
+    match args {
+        [] => Ok((ALL, ALL)),
+        [config_id] => Ok((config_id, ALL)),
+        [config_id, channel] => Ok((config_id, channel)),
+        _ => bail_user!("usage: {}", usage),
+    }
+}
+
+fn grant(ctx: &Context, args: &str) -> Result<()> {
+    const USAGE: &str = "grant <subject> <permission> [config_id [channel]]";
+    let a = args.split_whitespace().collect::<Vec<_>>();
+    if a.len() < 2 {
+        bail_user!("usage: {}", USAGE);
+    }
+    let (config_id, channel) = scope(&a[2..], USAGE)?;
+
+    ctx.bot().sql()?.execute(
+        "INSERT INTO grants (subject, permission, config_id, channel) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+        &[&a[0], &a[1], &config_id, &channel],
+    )?;
+    ctx.bot().invalidate(Some("grants"));
+
+    if a[1] != ALL && !ctx.bot.permissions().contains_key(a[1]) {
+        return ctx.say(&format!("done (note: no loaded module defines {:?})", a[1]));
+    }
+    ctx.say("done")
+}
+
+fn revoke(ctx: &Context, args: &str) -> Result<()> {
+    const USAGE: &str = "revoke <subject> <permission> [config_id [channel]]";
+    let a = args.split_whitespace().collect::<Vec<_>>();
+    if a.len() < 2 {
+        bail_user!("usage: {}", USAGE);
+    }
+    let (config_id, channel) = scope(&a[2..], USAGE)?;
+
+    let n = ctx.bot().sql()?.execute(
+        "DELETE FROM grants WHERE subject = $1 AND permission = $2 AND config_id = $3 AND channel = $4",
+        &[&a[0], &a[1], &config_id, &channel],
+    )?;
+    ctx.bot().invalidate(Some("grants"));
+
+    if n == 0 {
+        bail_user!("no such grant");
+    }
+    ctx.say("done")
+}
+
+fn grants(ctx: &Context, args: &str) -> Result<()> {
+    let filter = args.trim();
+    let items: Vec<Cow<str>> = ctx
+        .bot()
+        .sql()?
+        .query(
+            "SELECT subject, permission, config_id, channel FROM grants ORDER BY subject, permission, config_id, channel",
+            &[],
+        )?
+        .iter()
+        .map(|row| -> Result<(String, String, String, String)> {
+            Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?, row.try_get(3)?))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(subject, ..)| filter.is_empty() || glob(filter, subject) || glob(subject, filter))
+        .map(|(subject, permission, config_id, channel)| format!("{subject}: {permission} in {config_id} {channel}").into())
+        .collect();
+
+    if items.is_empty() {
+        return ctx.say("no grants found");
+    }
+    ctx.reply(Message::List {
+        prefix: "grants: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn group_add(ctx: &Context, args: &str) -> Result<()> {
+    let (Atom(name), Atom(member)) = <(Atom, Atom)>::parse_full(args)?;
+    ctx.bot().sql()?.execute(
+        "INSERT INTO perm_groups (name, member) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        &[&name, &member],
+    )?;
+    ctx.bot().invalidate(Some("perm_groups"));
+    ctx.say("done")
+}
+
+fn group_remove(ctx: &Context, args: &str) -> Result<()> {
+    let (Atom(name), Atom(member)) = <(Atom, Atom)>::parse_full(args)?;
+    let n = ctx.bot().sql()?.execute(
+        "DELETE FROM perm_groups WHERE name = $1 AND member = $2",
+        &[&name, &member],
+    )?;
+    if n == 0 {
+        bail_user!("{} is not a member of {}", member, name);
+    }
+    ctx.bot().invalidate(Some("perm_groups"));
+    ctx.say("done")
+}
+
+fn group_list(ctx: &Context, args: &str) -> Result<()> {
+    let only = args.trim();
+    let items: Vec<Cow<str>> = ctx
+        .bot()
+        .sql()?
+        .query("SELECT name, member FROM perm_groups ORDER BY name, member", &[])?
+        .iter()
+        .map(|row| -> Result<(String, String)> { Ok((row.try_get(0)?, row.try_get(1)?)) })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(name, _)| only.is_empty() || only == name)
+        .map(|(name, member)| format!("{name}: {member}").into())
+        .collect();
+    if items.is_empty() {
+        return ctx.say("no groups found");
+    }
+    ctx.reply(Message::List {
+        prefix: "groups: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn cmdperm(ctx: &Context, args: &str) -> Result<()> {
+    const USAGE: &str = "cmdperm <command> <permission|any|off|default> [config_id [channel]]";
+    let a = args.split_whitespace().collect::<Vec<_>>();
+    if a.len() < 2 {
+        bail_user!("usage: {}", USAGE);
+    }
+    let (config_id, channel) = scope(&a[2..], USAGE)?;
+
+    let (permission, enabled) = match a[1] {
+        "default" => {
+            ctx.bot().sql()?.execute(
+                "DELETE FROM command_perms WHERE command = $1 AND config_id = $2 AND channel = $3",
+                &[&a[0], &config_id, &channel],
+            )?;
+            ctx.bot().invalidate(Some("command_perms"));
+            return ctx.say("done");
+        }
+        "any" => (None, true),
+        "off" => (None, false),
+        p => (Some(p), true),
+    };
+    ctx.bot().sql()?.execute(
+        "INSERT INTO command_perms (command, config_id, channel, permission, enabled) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (command, config_id, channel) DO UPDATE SET permission = $4, enabled = $5",
+        &[&a[0], &config_id, &channel, &permission, &enabled],
+    )?;
+    ctx.bot().invalidate(Some("command_perms"));
+    ctx.say("done")
+}
+
+fn permissions(ctx: &Context, _args: &str) -> Result<()> {
+    let items = ctx
+        .bot
+        .permissions()
+        .into_iter()
+        .map(|(name, (module, desc))| match module {
+            Some(module) => format!("{name} ({module}): {desc}").into(),
+            None => format!("{name}: {desc}").into(),
+        })
+        .collect();
+    ctx.reply(Message::List {
+        prefix: "permissions: ".into(),
+        sep: "; ".into(),
+        items,
+    })
+}
+
+fn send_queues(ctx: &Context) -> Vec<Arc<SendQueue>> {
+    ctx.bot.adapters().iter().flat_map(|a| a.send_queues()).collect()
+}
+
+fn ratelimit_list(ctx: &Context, args: &str) -> Result<()> {
+    let pattern = match args.trim() {
+        "" => ALL,
+        pattern => pattern,
+    };
+    let mut items: Vec<Cow<str>> = vec![];
+    for queue in send_queues(ctx).iter().filter(|q| glob(pattern, q.name())) {
+        let (tokens, limit, queued) = queue.status();
+        items.push(
+            format!(
+                "send {}: {:.1}/{}, {} queued",
+                queue.name(),
+                tokens,
+                limit.burst,
+                queued
+            )
+            .into(),
+        );
+    }
+    for (key, tokens, limit) in ctx.bot.cooldowns.list(Instant::now()) {
+        if glob(pattern, &key) {
+            items.push(format!("{}: {:.1}/{}", key, tokens, limit.burst).into());
+        }
+    }
+
+    if items.is_empty() {
+        return ctx.say("no buckets found");
+    }
+    ctx.reply(Message::List {
+        prefix: "buckets: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn ratelimit_reset(ctx: &Context, args: &str) -> Result<()> {
+    let Atom(pattern) = Atom::parse_full(args)?;
+    let mut n = ctx.bot.cooldowns.reset(&pattern);
+    for queue in send_queues(ctx).iter().filter(|q| glob(&pattern, q.name())) {
+        queue.reset();
+        n += 1;
+    }
+    ctx.say(&format!("reset {} bucket{}", n, if n == 1 { "" } else { "s" }))
+}
+
+fn jobs_list(ctx: &Context, args: &str) -> Result<()> {
+    let pattern = match args.trim() {
+        "" => ALL,
+        pattern => pattern,
+    };
+    let now = schedule::now();
+    let items: Vec<Cow<str>> = scheduler::list(&mut **ctx.bot().sql()?, None)?
+        .into_iter()
+        .filter(|j| glob(pattern, &j.handler))
+        .map(|j| {
+            let due = Duration::from_secs((j.next_run - now).max(0) as u64);
+            let repeats = match j.schedule {
+                Schedule::At(_) => "once".to_string(),
+                s => s.to_string(),
+            };
+            format!(
+                "#{} {} for {} in {} ({})",
+                j.id,
+                j.handler,
+                j.channel,
+                format_duration(due),
+                repeats
+            )
+            .into()
+        })
+        .collect();
+
+    if items.is_empty() {
+        return ctx.say("no jobs found");
+    }
+    ctx.reply(Message::List {
+        prefix: "jobs: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn jobs_cancel(ctx: &Context, args: &str) -> Result<()> {
+    let Atom(id) = Atom::parse_full(args)?;
+    let id = match id.trim_start_matches('#').parse() {
+        Ok(id) => id,
+        Err(_) => bail_user!("invalid job ID {:?}", id),
+    };
+    if !ctx.bot().unschedule(id)? {
+        bail_user!("no job #{}", id);
+    }
+    ctx.say("cancelled")
+}
+
+#[derive(Args)]
+struct AliasAddArgs {
+    #[arg(long, name = "config", default = Atom(ALL.to_string()))]
+    config_id: Atom,
+    #[arg(long, default = Atom(ALL.to_string()))]
+    channel: Atom,
+    name: Atom,
+    target: Atom,
+    // JSON, as parsed by alias::parse_transform
+    transform: Option<Rest>,
+}
+
+#[derive(Args)]
+struct AliasDelArgs {
+    #[arg(long, name = "config", default = Atom(ALL.to_string()))]
+    config_id: Atom,
+    #[arg(long, default = Atom(ALL.to_string()))]
+    channel: Atom,
+    name: Atom,
+}
+
+fn alias_add(ctx: &Context, args: &str) -> Result<()> {
+    let AliasAddArgs {
+        config_id,
+        channel,
+        name,
+        target,
+        transform,
+    } = AliasAddArgs::parse_full(args)?;
+
+    if target.split(';').any(|t| t.trim() == *name) {
+        bail_user!("alias {} can't run itself", *name);
+    }
+    // checked now rather than when it's first used
+    let transform = transform.map(|Rest(t)| t);
+    if let Some(t) = &transform {
+        alias::parse_transform(t)?;
+    }
+
+    ctx.bot().sql()?.execute(
+        "INSERT INTO aliases (name, config_id, channel, target, transform) VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (name, config_id, channel) DO UPDATE SET target = excluded.target, transform = excluded.transform",
+        &[&*name, &*config_id, &*channel, &*target, &transform],
+    )?;
+    ctx.bot().invalidate(Some("aliases"));
+    ctx.say("done")
+}
+
+fn alias_del(ctx: &Context, args: &str) -> Result<()> {
+    let AliasDelArgs {
+        config_id,
+        channel,
+        name,
+    } = AliasDelArgs::parse_full(args)?;
+
+    let n = ctx.bot().sql()?.execute(
+        "DELETE FROM aliases WHERE name = $1 AND config_id = $2 AND channel = $3",
+        &[&*name, &*config_id, &*channel],
+    )?;
+    ctx.bot().invalidate(Some("aliases"));
+
+    if n == 0 {
+        bail_user!("no such alias");
+    }
+    ctx.say("done")
+}
+
+// Lists the aliases whose names match a pattern, or with `show`, everything about one alias.
+fn alias_list(ctx: &Context, pattern: &str, show: bool) -> Result<()> {
+    if show && pattern.is_empty() {
+        bail_user!("usage: alias show <name>");
+    }
+    let items: Vec<Cow<str>> = ctx
+        .bot()
+        .sql()?
+        .query(
+            "SELECT name, config_id, channel, target, transform FROM aliases ORDER BY name, config_id, channel",
+            &[],
+        )?
+        .iter()
+        .map(|row| -> Result<(String, String, String, String, Option<String>)> {
+            Ok((
+                row.try_get(0)?,
+                row.try_get(1)?,
+                row.try_get(2)?,
+                row.try_get(3)?,
+                row.try_get(4)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(name, ..)| {
+            if show {
+                name == pattern
+            } else {
+                pattern.is_empty() || glob(pattern, name)
+            }
+        })
+        .map(|(name, config_id, channel, target, transform)| {
+            let mut item = format!("{name} -> {target}");
+            if config_id != ALL || channel != ALL {
+                item += &format!(" in {config_id} {channel}");
+            }
+            if let (true, Some(t)) = (show, transform) {
+                item += &format!(" with {t}");
+            }
+            item.into()
+        })
+        .collect();
+
+    if items.is_empty() {
+        return ctx.say("no aliases found");
+    }
+    ctx.reply(Message::List {
+        prefix: "aliases: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn help(ctx: &Context, args: &str) -> Result<()> {
+    let mut words = args.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return list_commands(ctx, None, "commands (help <command> for more): "),
+    };
+
+    let resolved = ctx.bot.resolve_alias(ctx, name, "")?;
+    if resolved.len() != 1 {
+        let targets: Vec<&str> = resolved.iter().map(|(cmd, _)| cmd.as_str()).collect();
+        return ctx.say(&format!("{name} is an alias for {}", targets.join("; ")));
+    }
+    let cmd = resolved.into_iter().next().unwrap().0;
+    let available = ctx.bot.available_commands(ctx)?;
+    let (module, mut help) = match available.get(&cmd) {
+        Some((module, help)) => (module, help),
+        None => bail_user!("no command {:?}", name),
+    };
+
+    // e.g. "help list add" for a subcommand
+    let mut path = cmd.clone();
+    for word in words {
+        help = match help.subcommands.get(word) {
+            Some(sub) => sub,
+            None => bail_user!("{} has no subcommand {:?}", path, word),
+        };
+        path = format!("{path} {word}");
+    }
+
+    let mut text = help.describe(&path);
+    if cmd != name {
+        text = format!("{name} is an alias for {cmd}\n{text}");
+    }
+    if module != "core" {
+        text += &format!("\n(from {module})");
+    }
+    ctx.say(&text)
+}
+
+fn commands(ctx: &Context, args: &str) -> Result<()> {
+    match args.trim() {
+        "" => list_commands(ctx, None, "commands: "),
+        module => list_commands(ctx, Some(module), &format!("{module} commands: ")),
+    }
+}
+
+// Lists the commands the caller can run here, by module, or just those from one module.
+fn list_commands(ctx: &Context, only: Option<&str>, prefix: &str) -> Result<()> {
+    let mut by_module: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, (module, _)) in ctx.bot.available_commands(ctx)? {
+        if only.is_none_or(|m| m == module) {
+            by_module.entry(module).or_default().push(name);
+        }
+    }
+
+    let items: Vec<Cow<str>> = match only {
+        Some(module) => match by_module.remove(module) {
+            Some(names) => names.into_iter().map(Cow::Owned).collect(),
+            None => bail_user!("no commands from {:?} are available here", module),
+        },
+        None => by_module
+            .into_iter()
+            .map(|(module, names)| format!("{}: {}", module, names.join(", ")).into())
+            .collect(),
+    };
+    ctx.reply(Message::List {
+        prefix: prefix.to_string().into(),
+        sep: if only.is_some() { ", " } else { "; " }.into(),
+        items,
+    })
+}
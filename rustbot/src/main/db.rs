@@ -1,13 +1,46 @@
 use crate::config;
 use migrant_lib::config::PostgresSettingsBuilder;
-use postgres::{Client, NoTls};
+use postgres::fallible_iterator::FallibleIterator;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
 
 use rustbot::prelude::*;
+use rustbot::sql::{self, pg::PgConnection, sqlite::SqliteConnection, Connection};
 
-pub fn open(pc: &config::Postgres) -> Result<Client> {
-    let conn_str = migrate(pc)?;
-    let conn = Client::connect(&conn_str, NoTls)?;
-    Ok(conn)
+const SQLITE_MIGRATIONS: &str = "migrations-sqlite";
+
+// The channel the notify-changes migration's triggers send to.
+const CHANGES_CHANNEL: &str = "rustbot_changes";
+const LISTEN_KEEPALIVE: Duration = Duration::from_secs(60);
+
+// Opens the configured database, along with the connection string to `listen` on if it's Postgres.
+pub fn open(config: &config::Config) -> Result<(sql::Pool, Option<String>)> {
+    match (&config.postgres, &config.sqlite) {
+        (Some(pc), None) => {
+            let conn_str = migrate(pc)?;
+            let listen = conn_str.clone();
+            let pool = sql::pool(pc.pool_size, move || Ok(Box::new(PgConnection::connect(&conn_str)?)))?;
+            Ok((pool, Some(listen)))
+        }
+        (None, Some(sc)) => {
+            if sc.path == ":memory:" {
+                // every connection would get its own empty database
+                let pool = sql::single_connection_pool(|| Ok(Box::new(SqliteConnection::open_in_memory()?)))?;
+                migrate_sqlite(&mut **pool.get()?, Path::new(SQLITE_MIGRATIONS))?;
+                return Ok((pool, None));
+            }
+
+            let mut conn = SqliteConnection::open(&sc.path)?;
+            migrate_sqlite(&mut conn, Path::new(SQLITE_MIGRATIONS))?;
+            let path = sc.path.clone();
+            Ok((
+                sql::pool(sc.pool_size, move || Ok(Box::new(SqliteConnection::open(&path)?)))?,
+                None,
+            ))
+        }
+        _ => bail!("exactly one of [postgres] and [sqlite] must be configured"),
+    }
 }
 
 fn migrate(pc: &config::Postgres) -> Result<String> {
@@ -39,6 +72,65 @@ fn migrate(pc: &config::Postgres) -> Result<String> {
     config.connect_string().map_err(from_migrant)
 }
 
+// Passes the name of each table that changes to `changed` until the connection fails. None is passed
+// once we're listening, since anything could have changed while we weren't.
+pub fn listen(conn_str: &str, changed: &dyn Fn(Option<&str>)) -> Result<()> {
+    let mut client = postgres::Client::connect(conn_str, postgres::NoTls)?;
+    client.batch_execute(&format!("LISTEN {CHANGES_CHANNEL}"))?;
+    changed(None);
+
+    loop {
+        {
+            let mut notifications = client.notifications();
+            let mut iter = notifications.timeout_iter(LISTEN_KEEPALIVE);
+            while let Some(n) = iter.next()? {
+                changed(Some(n.payload()));
+            }
+        }
+
+        // nothing's happened for a while; make sure the connection is still there
+        client.batch_execute("SELECT 1")?;
+    }
+}
+
 fn from_migrant(e: migrant_lib::Error) -> Error {
     Error::msg(format!("{e}"))
 }
+
+// Applies any migrations in `dir` that haven't been applied yet. These mirror the Postgres
+// migrations, with the same tags, so the two can be kept in step.
+pub fn migrate_sqlite(conn: &mut dyn Connection, dir: &Path) -> Result<()> {
+    conn.batch_execute("CREATE TABLE IF NOT EXISTS __rustbot_migrations (tag TEXT NOT NULL PRIMARY KEY)")?;
+
+    let mut tags = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    tags.retain(|tag| dir.join(tag).join("up.sql").is_file());
+    tags.sort();
+
+    info!("Applying all migrations...");
+    for tag in tags {
+        let applied = conn
+            .query_opt("SELECT tag FROM __rustbot_migrations WHERE tag = $1", &[&tag])?
+            .is_some();
+        if applied {
+            continue;
+        }
+
+        info!("applying migration {}", tag);
+        let up = fs::read_to_string(dir.join(&tag).join("up.sql"))?;
+        let result = conn.batch_execute(&format!(
+            "BEGIN; {} ; INSERT INTO __rustbot_migrations (tag) VALUES ('{}'); COMMIT;",
+            up, tag
+        ));
+        if let Err(e) = result {
+            // the migration's error is the one worth reporting, whether or not this works
+            if let Err(rollback) = conn.batch_execute("ROLLBACK") {
+                error!("failed to roll back migration {}: {:?}", tag, rollback);
+            }
+            return Err(e.context(format!("failed to apply migration {tag}")));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+// Module dependencies. Modules declare their version and the modules they need through
+// `types::Meta`; the bot only loads a module once everything it depends on is loaded, and won't
+// drop a module while others depend on it.
+
+use rustbot::prelude::*;
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug)]
+pub struct Info {
+    pub name: String,
+    pub version: String,
+    // (module, version requirement)
+    pub depends: Vec<(String, String)>,
+}
+
+// Orders modules to be loaded so that each comes after everything it depends on, given the
+// modules already loaded. Modules that can't be loaded (and anything depending on them) are
+// returned separately, with the reason.
+pub fn load_order(loaded: &[Info], new: Vec<Info>) -> (Vec<String>, Vec<(String, Error)>) {
+    let mut available: BTreeMap<String, Version> = loaded
+        .iter()
+        .filter_map(|i| Some((i.name.clone(), Version::parse(&i.version).ok()?)))
+        .collect();
+    let mut order = vec![];
+    let mut failed: Vec<(String, Error)> = vec![];
+    let mut pending: BTreeMap<String, Info> = BTreeMap::new();
+
+    for info in new {
+        match Version::parse(&info.version) {
+            Ok(_) => {
+                pending.insert(info.name.clone(), info);
+            }
+            Err(e) => failed.push((info.name.clone(), anyhow!("invalid version {:?}: {}", info.version, e))),
+        }
+    }
+
+    while let Some(name) = pending
+        .values()
+        .find(|i| i.depends.iter().all(|(dep, _)| available.contains_key(dep)))
+        .map(|i| i.name.clone())
+    {
+        let info = pending.remove(&name).unwrap();
+        match check_versions(&info, &available) {
+            Ok(()) => {
+                available.insert(name.clone(), Version::parse(&info.version).unwrap());
+                order.push(name);
+            }
+            Err(e) => failed.push((name, e)),
+        }
+    }
+
+    // anything left is waiting on something that's missing, failed, or depends on it in turn
+    let stuck: BTreeSet<String> = pending.keys().cloned().collect();
+    for (name, info) in pending {
+        let (dep, _) = info
+            .depends
+            .iter()
+            .find(|(dep, _)| !available.contains_key(dep))
+            .unwrap();
+        let err = if stuck.contains(dep) {
+            anyhow!("needs {}, which can't be loaded before it (circular dependency?)", dep)
+        } else if failed.iter().any(|(f, _)| f == dep) {
+            anyhow!("needs {}, which failed to load", dep)
+        } else {
+            anyhow!("needs {}, which isn't loaded", dep)
+        };
+        failed.push((name, err));
+    }
+
+    (order, failed)
+}
+
+fn check_versions(info: &Info, available: &BTreeMap<String, Version>) -> Result<()> {
+    for (dep, req) in &info.depends {
+        let parsed = VersionReq::parse(req).map_err(|e| anyhow!("invalid requirement {:?} for {}: {}", req, dep, e))?;
+        let version = &available[dep];
+        if !parsed.matches(version) {
+            bail!("needs {} {}, but {} {} is loaded", dep, req, dep, version);
+        }
+    }
+    Ok(())
+}
+
+// The loaded modules that depend on `name`, directly or not, ordered so that each comes before
+// anything it depends on; i.e. the order to drop them in.
+pub fn dependents(loaded: &[Info], name: &str) -> Vec<String> {
+    let mut found: BTreeSet<&str> = BTreeSet::new();
+    let mut queue = vec![name];
+    while let Some(next) = queue.pop() {
+        for info in loaded {
+            if info.depends.iter().any(|(dep, _)| dep == next) && found.insert(&info.name) {
+                queue.push(&info.name);
+            }
+        }
+    }
+
+    let (order, _) = load_order(&[], loaded.to_vec());
+    order.into_iter().rev().filter(|m| found.contains(m.as_str())).collect()
+}
@@ -160,6 +160,22 @@ pub fn format_irc(m: Message) -> Result<Vec<String>> {
     }
 }
 
+// Renders a message as plain text lines, with all formatting stripped.
+pub fn format_plain(m: Message) -> Vec<String> {
+    let msg = match m {
+        Message::Simple(s) | Message::Code(s) => s,
+        Message::Spans(s) => spans_to_raw_string(s),
+        Message::Prefixed(p, s) => {
+            let p = spans_to_raw_string(p);
+            let s = spans_to_raw_string(s);
+            return s.split('\n').map(|line| p.clone() + line).collect();
+        }
+        Message::List { prefix, sep, items } => format!("{}{}", prefix, items.join(&sep)),
+    };
+
+    msg.split('\n').map(str::to_string).collect()
+}
+
 fn render_dis<'a>(s: &'a Span) -> Cow<'a, str> {
     match s {
         Span::Text { text, format, .. } => {
@@ -229,3 +245,112 @@ pub fn format_discord(m: Message) -> Result<String> {
         }
     }
 }
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// The usual mIRC palette, so colours look the same as they do on IRC.
+fn html_color(c: Color) -> Option<&'static str> {
+    Some(match c {
+        Color::None => return None,
+        Color::BrightWhite => "#FFFFFF",
+        Color::Black => "#000000",
+        Color::Blue => "#00007F",
+        Color::Green => "#009300",
+        Color::BrightRed => "#FF0000",
+        Color::Red => "#7F0000",
+        Color::Magenta => "#9C009C",
+        Color::Yellow => "#FC7F00",
+        Color::BrightYellow => "#FFFF00",
+        Color::BrightGreen => "#00FC00",
+        Color::Cyan => "#009393",
+        Color::BrightCyan => "#00FFFF",
+        Color::BrightBlue => "#0000FC",
+        Color::BrightMagenta => "#FF00FF",
+        Color::BrightBlack => "#7F7F7F",
+        Color::White => "#D2D2D2",
+    })
+}
+
+fn render_matrix(s: &Span) -> String {
+    match s {
+        Span::Text {
+            text, format, color, ..
+        } => {
+            let mut open = String::new();
+            let mut close = String::new();
+            if let Some(c) = html_color(*color) {
+                open += &format!("<font color=\"{c}\">");
+                close.insert_str(0, "</font>");
+            }
+            for (f, tag) in &[(Format::Bold, "b"), (Format::Italic, "i"), (Format::Underline, "u")] {
+                if format.contains(*f) {
+                    open += &format!("<{tag}>");
+                    close.insert_str(0, &format!("</{tag}>"));
+                }
+            }
+            format!("{}{}{}", open, escape_html(text), close)
+        }
+        Span::DiscordEmoji(name, _) => format!(":{}:", escape_html(name)),
+    }
+}
+
+fn render_matrix_spans(s: &[Span]) -> (String, String) {
+    (
+        spans_to_raw_string(s.to_vec()),
+        s.iter().map(render_matrix).collect::<Vec<_>>().join(""),
+    )
+}
+
+// Returns the plain-text body and the HTML formatted body of a Matrix message.
+pub fn format_matrix(m: Message) -> Result<(String, String)> {
+    match m {
+        Message::Simple(s) => {
+            let html = escape_html(&s);
+            Ok((s, html))
+        }
+        Message::Code(s) => {
+            let html = if s.contains('\n') {
+                format!("<pre><code>{}</code></pre>", escape_html(&s).replace("<br>", "\n"))
+            } else {
+                format!("<code>{}</code>", escape_html(&s))
+            };
+            Ok((s, html))
+        }
+        Message::Spans(s) => Ok(render_matrix_spans(&s)),
+        Message::Prefixed(p, s) => {
+            let (p, p_html) = render_matrix_spans(&p);
+            let (s, s_html) = render_matrix_spans(&s);
+
+            let (lines, url) = paste_max_lines(&s, 11)?;
+            let mut body = lines.iter().map(|line| p.clone() + line).collect::<Vec<_>>();
+            let mut html = s_html
+                .split("<br>")
+                .take(lines.len())
+                .map(|line| p_html.clone() + line)
+                .collect::<Vec<_>>();
+            if let Some(u) = url {
+                html.push(escape_html(&u));
+                body.push(u);
+            }
+            Ok((body.join("\n"), html.join("<br>")))
+        }
+        Message::List { prefix, sep, items } => {
+            let s = format!("{}{}", prefix, items.join(&sep));
+            let html = escape_html(&s);
+            Ok((s, html))
+        }
+    }
+}
@@ -1,9 +1,16 @@
+mod adapter;
+mod alias;
 mod bot;
 mod config;
 mod context;
 mod core;
 mod db;
+mod deps;
 mod message;
+mod perms;
+mod ratelimit;
+mod recompile;
+mod scheduler;
 
 #[cfg(test)]
 mod test;
@@ -0,0 +1,146 @@
+// Permission lookups. Each source identifies itself by one or more subjects, such as
+// "irc:nick!user@host", "irc-account:<account>" or "dis-role:<guild>:<role>"; groups add
+// "group:<name>" subjects to anyone matching one of their members. Grants give a permission to
+// every subject matching a glob pattern, within the configs and channels matching two more.
+
+use rustbot::cache::Cached;
+use rustbot::perms::{glob, specificity, ALL};
+use rustbot::prelude::*;
+
+// Groups can contain other groups, but there's no point going much deeper than this.
+const MAX_GROUP_DEPTH: usize = 8;
+
+pub struct Grant {
+    pub subject: String,
+    pub permission: String,
+    pub config_id: String,
+    pub channel: String,
+}
+
+// Replaces the permissions a command normally needs within some configs and channels.
+pub struct Override {
+    pub command: String,
+    pub config_id: String,
+    pub channel: String,
+    // None lets anyone run the command
+    pub permission: Option<String>,
+    pub enabled: bool,
+}
+
+impl Override {
+    fn matches(&self, config_id: &str, channel: &str) -> bool {
+        glob(&self.config_id, config_id) && glob(&self.channel, channel)
+    }
+}
+
+pub struct Permissions {
+    grants: Cached<Vec<Grant>>,
+    // (group, member pattern)
+    groups: Cached<Vec<(String, String)>>,
+    overrides: Cached<Vec<Override>>,
+}
+
+impl Permissions {
+    pub fn new() -> Self {
+        Self {
+            grants: Cached::new(&["grants"]),
+            groups: Cached::new(&["perm_groups"]),
+            overrides: Cached::new(&["command_perms"]),
+        }
+    }
+
+    // Adds the groups the subjects are in, directly or through other groups.
+    pub fn expand_groups(&self, bot: &dyn Bot, mut subjects: Vec<String>) -> Result<Vec<String>> {
+        let groups = self.groups.get(bot, |db| {
+            db.query("SELECT name, member FROM perm_groups", &[])?
+                .iter()
+                .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+                .collect()
+        })?;
+
+        for _ in 0..MAX_GROUP_DEPTH {
+            let new: Vec<String> = groups
+                .iter()
+                .filter(|(_, member)| subjects.iter().any(|s| glob(member, s)))
+                .map(|(name, _)| format!("group:{name}"))
+                .filter(|g| !subjects.contains(g))
+                .collect();
+            if new.is_empty() {
+                break;
+            }
+            subjects.extend(new);
+        }
+
+        Ok(subjects)
+    }
+
+    pub fn has_perm(
+        &self,
+        bot: &dyn Bot,
+        subjects: &[String],
+        config_id: &str,
+        channel: &str,
+        name: &str,
+    ) -> Result<bool> {
+        let grants = self.grants.get(bot, |db| {
+            db.query("SELECT subject, permission, config_id, channel FROM grants", &[])?
+                .iter()
+                .map(|row| {
+                    Ok(Grant {
+                        subject: row.try_get(0)?,
+                        permission: row.try_get(1)?,
+                        config_id: row.try_get(2)?,
+                        channel: row.try_get(3)?,
+                    })
+                })
+                .collect()
+        })?;
+
+        Ok(grants.iter().any(|g| {
+            (g.permission == name || g.permission == ALL)
+                && glob(&g.config_id, config_id)
+                && glob(&g.channel, channel)
+                && subjects.iter().any(|s| glob(&g.subject, s))
+        }))
+    }
+
+    // The override for a command that applies in the given channel, if any; when several match,
+    // the one with the most specific channel pattern wins, then the most specific config.
+    pub fn command_override(
+        &self,
+        bot: &dyn Bot,
+        command: &str,
+        config_id: &str,
+        channel: &str,
+    ) -> Result<Option<(Option<String>, bool)>> {
+        let overrides = self.overrides.get(bot, |db| {
+            db.query(
+                "SELECT command, config_id, channel, permission, enabled FROM command_perms",
+                &[],
+            )?
+            .iter()
+            .map(|row| {
+                Ok(Override {
+                    command: row.try_get(0)?,
+                    config_id: row.try_get(1)?,
+                    channel: row.try_get(2)?,
+                    permission: row.try_get(3)?,
+                    enabled: row.try_get(4)?,
+                })
+            })
+            .collect()
+        })?;
+
+        Ok(overrides
+            .iter()
+            .filter(|o| o.command == command && o.matches(config_id, channel))
+            .max_by_key(|o| (specificity(&o.channel), specificity(&o.config_id)))
+            .map(|o| (o.permission.clone(), o.enabled)))
+    }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
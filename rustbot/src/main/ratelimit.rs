@@ -0,0 +1,257 @@
+// Token buckets, to keep the bot from flooding the networks it's on, and users from flooding the
+// bot. A bucket holds up to `burst` tokens and gains one every `every` seconds; each message or
+// command takes one.
+
+use parking_lot::Mutex;
+use rustbot::perms::glob;
+use rustbot::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Limit;
+
+// Messages beyond this many waiting on one queue are dropped, so a runaway module can't queue up
+// hours of output.
+const MAX_QUEUED: usize = 200;
+
+// Queue threads exit after this long with nothing to send, and are started again when needed.
+const QUEUE_IDLE: Duration = Duration::from_secs(60);
+
+pub struct Bucket {
+    limit: Limit,
+    tokens: f64,
+    updated: Instant,
+    // whether anyone has been told about this bucket being empty since it last wasn't
+    warned: bool,
+}
+
+impl Bucket {
+    pub fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated: now,
+            warned: false,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = if self.limit.every > 0.0 {
+            (self.tokens + elapsed / self.limit.every).min(self.limit.burst as f64)
+        } else {
+            self.limit.burst as f64
+        };
+        self.updated = now;
+    }
+
+    // Takes a token if there is one; otherwise returns how long until there will be.
+    pub fn take(&mut self, now: Instant) -> std::result::Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.warned = false;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) * self.limit.every))
+        }
+    }
+
+    pub fn tokens(&mut self, now: Instant) -> f64 {
+        self.refill(now);
+        self.tokens
+    }
+
+    pub fn limit(&self) -> Limit {
+        self.limit
+    }
+
+    pub fn reset(&mut self, now: Instant) {
+        *self = Self::new(self.limit, now);
+    }
+}
+
+#[derive(Debug)]
+pub struct Denied {
+    pub wait: Duration,
+    // false if an earlier attempt was already denied since the bucket was last used, so callers
+    // can avoid complaining about every message in a flood
+    pub first: bool,
+}
+
+// A bucket for each of a set of keys, e.g. one per user. Buckets are created full when first
+// needed, and forgotten once they're full again, since a new one would be the same.
+pub struct Limiter {
+    buckets: Mutex<BTreeMap<String, Bucket>>,
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn check(&self, key: &str, limit: Limit, now: Instant) -> std::result::Result<(), Denied> {
+        let mut buckets = self.buckets.lock();
+        buckets.retain(|_, b| b.tokens(now) < b.limit.burst as f64);
+
+        let bucket = buckets
+            .entry(key.to_string())
+            .or_insert_with(|| Bucket::new(limit, now));
+        bucket.take(now).map_err(|wait| {
+            let first = !bucket.warned;
+            bucket.warned = true;
+            Denied { wait, first }
+        })
+    }
+
+    // Every bucket that isn't full, with its tokens and limit.
+    pub fn list(&self, now: Instant) -> Vec<(String, f64, Limit)> {
+        let mut buckets = self.buckets.lock();
+        buckets.retain(|_, b| b.tokens(now) < b.limit.burst as f64);
+        buckets
+            .iter_mut()
+            .map(|(key, b)| (key.clone(), b.tokens(now), b.limit))
+            .collect()
+    }
+
+    // Refills every bucket with a key matching the glob pattern, returning how many there were.
+    pub fn reset(&self, pattern: &str) -> usize {
+        let mut buckets = self.buckets.lock();
+        let before = buckets.len();
+        buckets.retain(|key, _| !glob(pattern, key));
+        before - buckets.len()
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type Job = Box<dyn FnOnce() -> Result<()> + Send>;
+
+// Sends messages one at a time from a thread of its own, taking a token from its bucket for each,
+// so callers never wait for the limit themselves.
+pub struct SendQueue {
+    name: String,
+    bucket: Arc<Mutex<Bucket>>,
+    sender: Arc<Mutex<Option<SyncSender<Job>>>>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl SendQueue {
+    pub fn new(name: &str, limit: Limit) -> Self {
+        Self {
+            name: name.to_string(),
+            bucket: Arc::new(Mutex::new(Bucket::new(limit, Instant::now()))),
+            sender: Arc::new(Mutex::new(None)),
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Queues a job to run once the limit allows; errors from the job itself can only be logged.
+    pub fn push(&self, job: Job) -> Result<()> {
+        let mut sender = self.sender.lock();
+
+        let job = match &*sender {
+            Some(tx) => match tx.try_send(job) {
+                Ok(()) => {
+                    self.queued.fetch_add(1, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Err(TrySendError::Full(_)) => bail!("send queue {} is full; dropping message", self.name),
+                Err(TrySendError::Disconnected(job)) => job,
+            },
+            None => job,
+        };
+
+        // the thread isn't running, so start it
+        let (tx, rx) = mpsc::sync_channel(MAX_QUEUED);
+        tx.try_send(job).unwrap();
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        *sender = Some(tx);
+
+        let name = self.name.clone();
+        let bucket = Arc::clone(&self.bucket);
+        let sender = Arc::clone(&self.sender);
+        let queued = Arc::clone(&self.queued);
+        thread::Builder::new()
+            .name(format!("send: {}", self.name))
+            .spawn(move || run_queue(&name, &rx, &bucket, &sender, &queued))?;
+        Ok(())
+    }
+
+    // (tokens, limit, messages waiting)
+    pub fn status(&self) -> (f64, Limit, usize) {
+        let mut bucket = self.bucket.lock();
+        (
+            bucket.tokens(Instant::now()),
+            bucket.limit(),
+            self.queued.load(Ordering::SeqCst),
+        )
+    }
+
+    pub fn reset(&self) {
+        self.bucket.lock().reset(Instant::now());
+    }
+}
+
+fn run_queue(
+    name: &str,
+    rx: &Receiver<Job>,
+    bucket: &Mutex<Bucket>,
+    sender: &Mutex<Option<SyncSender<Job>>>,
+    queued: &AtomicUsize,
+) {
+    loop {
+        let job = match rx.recv_timeout(QUEUE_IDLE) {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => {
+                // pushes happen with the sender locked, so nothing can sneak in after this check
+                let mut sender = sender.lock();
+                match rx.try_recv() {
+                    Ok(job) => job,
+                    Err(_) => {
+                        *sender = None;
+                        return;
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        loop {
+            let wait = bucket.lock().take(Instant::now());
+            match wait {
+                Ok(()) => break,
+                Err(wait) => thread::sleep(wait),
+            }
+        }
+
+        queued.fetch_sub(1, Ordering::SeqCst);
+        if let Err(e) = job() {
+            warn!("{}: failed to send: {}", name, e);
+        }
+    }
+}
+
+// Formats a duration for "try again in ..." messages, rounding up to the next second.
+pub fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    match secs {
+        0 | 1 => "a second".to_string(),
+        s if s < 120 => format!("{s} seconds"),
+        s => format!("{} minutes", s.div_ceil(60)),
+    }
+}
@@ -0,0 +1,265 @@
+// Rebuilding modules while the bot runs. `recompile` runs cargo in the background, reporting its
+// progress and any errors, and then reloads the loaded modules whose libraries were rebuilt. Each
+// library is backed up first, so if a new build then fails to load, the old one is put back in
+// place for next time, as well as staying loaded.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bot;
+use crate::context::Context;
+use rustbot::prelude::*;
+use rustbot::types::Context as _;
+
+// How often to say a build is still going.
+const PROGRESS_EVERY: Duration = Duration::from_secs(15);
+
+// Errors shown in full; any more are just counted.
+const MAX_ERRORS: usize = 3;
+
+// The parts of cargo's --message-format=json output we use.
+#[derive(Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CargoMessage {
+    CompilerArtifact {
+        target: Target,
+        fresh: bool,
+    },
+    CompilerMessage {
+        message: Diagnostic,
+    },
+    BuildFinished {
+        success: bool,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    level: String,
+    rendered: Option<String>,
+}
+
+#[derive(Default, Debug)]
+pub struct Build {
+    // crates actually compiled, rather than already up to date, in order
+    pub compiled: Vec<String>,
+    // rendered as rustc would print them
+    pub errors: Vec<String>,
+    pub warnings: usize,
+    pub success: bool,
+}
+
+impl Build {
+    // Takes in a line of cargo's output; anything that isn't a message we use is ignored.
+    pub fn feed(&mut self, line: &str) {
+        match serde_json::from_str(line) {
+            Ok(CargoMessage::CompilerArtifact { target, fresh: false }) => self.compiled.push(target.name),
+            Ok(CargoMessage::CompilerMessage { message }) => match message.level.as_str() {
+                "error" => self.errors.extend(message.rendered),
+                "warning" => self.warnings += 1,
+                _ => (),
+            },
+            Ok(CargoMessage::BuildFinished { success }) => self.success = success,
+            _ => (),
+        }
+    }
+
+    // The modules that were rebuilt.
+    pub fn changed(&self) -> Vec<String> {
+        self.compiled
+            .iter()
+            .filter_map(|c| c.strip_prefix("mod_"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn progress(&self, started: Instant) -> String {
+        match self.compiled.last() {
+            Some(last) => format!(
+                "still building after {}s; compiled {} crate{} so far, most recently {}",
+                started.elapsed().as_secs(),
+                self.compiled.len(),
+                if self.compiled.len() == 1 { "" } else { "s" },
+                last
+            ),
+            None => format!("still building after {}s", started.elapsed().as_secs()),
+        }
+    }
+}
+
+// Starts a build, returning once it's running. Only the named modules are reloaded afterwards, if
+// any are given.
+pub fn start(ctx: &Context, only: Vec<String>) -> Result<()> {
+    let bot = ctx.bot.arc();
+    if bot.building.swap(true, Ordering::SeqCst) {
+        bail_user!("a build is already running");
+    }
+    let building = Building(bot);
+
+    // if the thread can't be started, the closure and so the guard are dropped along with it
+    let (config, source) = (ctx.config.clone(), ctx.source.clone());
+    thread::Builder::new().name("recompile".to_string()).spawn(move || {
+        let ctx = Context {
+            bot: &building.0,
+            config,
+            source,
+        };
+        if let Err(e) = run(&ctx, &only) {
+            error!("{:?}", e);
+            ctx.say(&format!("recompile failed: {e}")).unwrap_or(());
+        }
+    })?;
+
+    ctx.say("building...")
+}
+
+// Marks the build finished when dropped, however it ends, even by panicking.
+struct Building(Arc<bot::Rustbot>);
+
+impl Drop for Building {
+    fn drop(&mut self) {
+        self.0.building.store(false, Ordering::SeqCst);
+    }
+}
+
+fn run(ctx: &Context, only: &[String]) -> Result<()> {
+    let loaded: Vec<String> = ctx
+        .bot
+        .module_infos()
+        .into_iter()
+        .map(|i| i.name)
+        .filter(|m| only.is_empty() || only.contains(m))
+        .collect();
+    let backups = Backups::take(&loaded)?;
+
+    let mut cmd = ProcessCommand::new("cargo");
+    cmd.args(["build", "--message-format=json"]);
+    if !cfg!(debug_assertions) {
+        cmd.arg("--release");
+    }
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    // read stderr alongside, so cargo can't block on it filling up; it's only needed if the build
+    // fails without saying why in its JSON, e.g. for a broken Cargo.toml
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr = thread::spawn(move || {
+        let mut s = String::new();
+        stderr.read_to_string(&mut s).map(|_| s)
+    });
+
+    let started = Instant::now();
+    let mut reported = started;
+    let mut build = Build::default();
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        build.feed(&line?);
+        if reported.elapsed() >= PROGRESS_EVERY {
+            ctx.say(&build.progress(started))?;
+            reported = Instant::now();
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr.join().map_err(|e| anyhow!("{:?}", e))??;
+
+    if !status.success() || !build.success {
+        if build.errors.is_empty() {
+            let lines: Vec<&str> = stderr
+                .lines()
+                .filter(|l| !l.trim_start().starts_with("Compiling"))
+                .collect();
+            return ctx.reply(Message::Code(lines.join("\n")));
+        }
+        ctx.say(&format!(
+            "build failed with {} error{}:",
+            build.errors.len(),
+            if build.errors.len() == 1 { "" } else { "s" }
+        ))?;
+        ctx.reply(Message::Code(
+            build.errors[..build.errors.len().min(MAX_ERRORS)].join("\n"),
+        ))?;
+        if build.errors.len() > MAX_ERRORS {
+            ctx.say(&format!("...and {} more", build.errors.len() - MAX_ERRORS))?;
+        }
+        return Ok(());
+    }
+
+    let changed: Vec<String> = build.changed().into_iter().filter(|m| loaded.contains(m)).collect();
+    ctx.say(&format!(
+        "built in {}s with {} warning{}; {}",
+        started.elapsed().as_secs(),
+        build.warnings,
+        if build.warnings == 1 { "" } else { "s" },
+        if changed.is_empty() {
+            "no loaded modules changed".to_string()
+        } else {
+            format!("reloading {}", changed.join(", "))
+        }
+    ))?;
+    if build.compiled.iter().any(|c| c == "rustbot") {
+        ctx.say("rustbot itself was rebuilt; modules built against it won't load until the bot is restarted")?;
+    }
+
+    for m in &changed {
+        if let Err(e) = ctx.bot.reload_module(m) {
+            backups.restore(m)?;
+            ctx.say(&format!(
+                "{m} failed to load, so its previous build was put back: {e:#}"
+            ))?;
+        }
+    }
+    ctx.say("done")
+}
+
+// Copies of modules' libraries from before a build, removed once it's done.
+struct Backups {
+    dir: PathBuf,
+    // module -> (library, copy)
+    files: BTreeMap<String, (PathBuf, PathBuf)>,
+}
+
+impl Backups {
+    fn take(modules: &[String]) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("rustbot-{}-backup", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let mut backups = Self {
+            dir,
+            files: BTreeMap::new(),
+        };
+        for m in modules {
+            let lib = bot::find_library(m)?;
+            let copy = backups.dir.join(lib.file_name().unwrap());
+            fs::copy(&lib, &copy).with_context(|| format!("failed to back up {}", lib.display()))?;
+            backups.files.insert(m.clone(), (lib, copy));
+        }
+        Ok(backups)
+    }
+
+    fn restore(&self, module: &str) -> Result<()> {
+        if let Some((lib, copy)) = self.files.get(module) {
+            // cargo hard-links the library to its own copy, which has to be left alone
+            fs::remove_file(lib)?;
+            fs::copy(copy, lib).with_context(|| format!("failed to restore {}", lib.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Backups {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).unwrap_or(());
+    }
+}
@@ -0,0 +1,156 @@
+// Runs scheduled jobs (see rustbot::schedule) when they're due. Jobs live in the scheduled_jobs
+// table; one thread waits for the next to come due, or to be woken by a new job being scheduled.
+
+use parking_lot::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::bot::Rustbot;
+use rustbot::prelude::*;
+use rustbot::schedule;
+use rustbot::sql::{Connection, Row};
+
+// The longest to wait between checks, so that jobs whose module has just been loaded, or that
+// were added to the database by hand, aren't left waiting long.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+const COLUMNS: &str = "id, handler, config_id, channel, payload, schedule, next_run";
+
+#[derive(Default)]
+pub struct Scheduler {
+    woken: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Has the runner check again for the next job due, e.g. because one was just scheduled.
+    pub fn wake(&self) {
+        *self.woken.lock() = true;
+        self.wake.notify_all();
+    }
+
+    fn wait(&self, timeout: Duration) {
+        let mut woken = self.woken.lock();
+        if !*woken {
+            self.wake.wait_for(&mut woken, timeout);
+        }
+        *woken = false;
+    }
+}
+
+pub fn run(bot: &Rustbot) {
+    loop {
+        let wait = run_due(bot).unwrap_or_else(|e| {
+            error!("failed to run scheduled jobs: {:?}", e);
+            MAX_WAIT
+        });
+        bot.scheduler.wait(wait);
+    }
+}
+
+// Runs the jobs that are due, returning how long until the next one.
+fn run_due(bot: &Rustbot) -> Result<Duration> {
+    let now = schedule::now();
+    let due = bot.sql()?.query(
+        &format!("SELECT {COLUMNS} FROM scheduled_jobs WHERE next_run <= $1 ORDER BY next_run"),
+        &[&now],
+    )?;
+
+    for row in due {
+        let job = match job_from_row(&row) {
+            Ok(job) => job,
+            Err(e) => {
+                error!("skipping unreadable scheduled job {}: {:?}", row.values()[0], e);
+                continue;
+            }
+        };
+
+        // moved on before it's run, so that one that fails, or takes the bot down with it,
+        // isn't run over and over
+        let ran = bot.with_job_handler(&job.handler, |f| {
+            advance(&mut **bot.sql()?, &job, now)?;
+            f(bot, &job)
+        });
+        match ran {
+            // its module isn't loaded; it'll be run once it is
+            None => continue,
+            Some(Ok(Some(message))) => {
+                if let Err(e) = bot.send_message(&job.config_id, &job.channel, message) {
+                    warn!(
+                        "failed to send result of scheduled job {} to {}: {:?}",
+                        job.id, job.channel, e
+                    );
+                }
+            }
+            Some(Ok(None)) => (),
+            Some(Err(e)) => error!("scheduled job {} ({}) failed: {:?}", job.id, job.handler, e),
+        }
+    }
+
+    let next: Option<i64> = bot
+        .sql()?
+        .query_one("SELECT MIN(next_run) FROM scheduled_jobs WHERE next_run > $1", &[&now])?
+        .try_get(0)?;
+    Ok(match next {
+        Some(t) => MAX_WAIT.min(Duration::from_secs((t - schedule::now()).max(0) as u64)),
+        None => MAX_WAIT,
+    })
+}
+
+// Reschedules a job that's being run, or removes it if it won't be due again.
+fn advance(db: &mut dyn Connection, job: &Job, now: i64) -> Result<()> {
+    match job.schedule.next(now) {
+        Some(t) => db.execute("UPDATE scheduled_jobs SET next_run = $1 WHERE id = $2", &[&t, &job.id])?,
+        None => db.execute("DELETE FROM scheduled_jobs WHERE id = $1", &[&job.id])?,
+    };
+    Ok(())
+}
+
+fn job_from_row(row: &Row) -> Result<Job> {
+    Ok(Job {
+        id: row.try_get(0)?,
+        handler: row.try_get(1)?,
+        config_id: row.try_get(2)?,
+        channel: row.try_get(3)?,
+        payload: row.try_get(4)?,
+        schedule: Schedule::parse(&row.try_get::<_, String>(5)?, 0)?,
+        next_run: row.try_get(6)?,
+    })
+}
+
+pub fn insert(db: &mut dyn Connection, job: &Job) -> Result<i64> {
+    db.query_one(
+            "INSERT INTO scheduled_jobs (handler, config_id, channel, payload, schedule, next_run) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+            &[
+                &job.handler,
+                &job.config_id,
+                &job.channel,
+                &job.payload,
+                &job.schedule.to_string(),
+                &job.next_run,
+            ],
+        )?
+        .try_get(0)
+}
+
+pub fn delete(db: &mut dyn Connection, id: i64) -> Result<bool> {
+    Ok(db.execute("DELETE FROM scheduled_jobs WHERE id = $1", &[&id])? > 0)
+}
+
+// Jobs for a handler, or all of them, soonest first.
+pub fn list(db: &mut dyn Connection, handler: Option<&str>) -> Result<Vec<Job>> {
+    let rows = match handler {
+        Some(h) => db.query(
+            &format!("SELECT {COLUMNS} FROM scheduled_jobs WHERE handler = $1 ORDER BY next_run, id"),
+            &[&h],
+        )?,
+        None => db.query(
+            &format!("SELECT {COLUMNS} FROM scheduled_jobs ORDER BY next_run, id"),
+            &[],
+        )?,
+    };
+    rows.iter().map(job_from_row).collect()
+}
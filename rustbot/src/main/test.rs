@@ -1,4 +1,28 @@
+use crate::adapter::console::{ConsoleAdapter, Output};
+use crate::adapter::irc::{self, IrcAdapter};
+use crate::adapter::matrix::MatrixAdapter;
+use crate::adapter::{Adapter, EventKind, EventSink};
+use crate::alias::{parse_transform, Aliases};
 use crate::bot;
+use crate::config;
+use crate::context::Source;
+use crate::db;
+use crate::deps;
+use crate::perms::Permissions;
+use crate::ratelimit::{Bucket, Limiter, SendQueue};
+use crate::recompile::Build;
+use crate::scheduler;
+use parking_lot::Mutex;
+use rustbot::prelude::*;
+use rustbot::sql::{sqlite::SqliteConnection, Connection};
+use rustbot::testing::MockBot;
+use rustbot::types::Source as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_truncate_module_path() {
@@ -33,3 +57,752 @@ fn test_truncate_module_path() {
         assert_eq!(bot::truncate_module_path(test_path, i), expected[i]);
     }
 }
+
+// A minimal homeserver that answers each request with the next canned response, and records the
+// requests it receives.
+fn mock_homeserver(responses: Vec<&'static str>) -> (String, mpsc::Receiver<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+
+            let request_line = request_line.trim_end().to_string();
+            tx.send((request_line, String::from_utf8(body).unwrap())).unwrap();
+        }
+    });
+
+    (url, rx)
+}
+
+#[test]
+fn test_matrix_sync_and_send() {
+    let (url, requests) = mock_homeserver(vec![
+        r#"{
+            "next_batch": "s2",
+            "rooms": {"join": {"!room:server": {"timeline": {"events": [
+                {"type": "m.room.message", "sender": "@alice:server", "content": {"msgtype": "m.text", "body": "!dice 4d6"}},
+                {"type": "m.room.message", "sender": "@bot:server", "content": {"msgtype": "m.text", "body": "own message"}},
+                {"type": "m.room.message", "sender": "@otherbot:server", "content": {"msgtype": "m.notice", "body": "a notice"}},
+                {"type": "m.room.member", "sender": "@bob:server", "content": {}}
+            ]}}}}
+        }"#,
+        r#"{"event_id": "$1"}"#,
+    ]);
+
+    let adapter = MatrixAdapter::new(config::Matrix {
+        id: "matrix".to_string(),
+        homeserver: url,
+        user: "@bot:server".to_string(),
+        access_token: "token".to_string(),
+    })
+    .unwrap();
+
+    let (tx, events) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let sink: EventSink = Arc::new(move |event| tx.lock().send(event).unwrap());
+
+    let next = adapter.sync_once(Some("s1"), Some(&sink)).unwrap();
+    assert_eq!(next, "s2");
+
+    let (request, _) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(
+        request.starts_with("GET /_matrix/client/v3/sync?since=s1&"),
+        "{}",
+        request
+    );
+
+    let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+    match &event.kind {
+        EventKind::Message { typ, message } => {
+            assert_eq!(message, "!dice 4d6");
+            assert_eq!(*typ, HandleType::PlainMsg | HandleType::Public);
+        }
+        EventKind::Other(_) => panic!("expected a message"),
+    }
+    assert_eq!(event.source.channel_string(), "mx:!room:server");
+    assert_eq!(event.source.user_string(), "@alice:server");
+    assert_eq!(event.source.user_pretty(), "alice");
+    assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+
+    adapter
+        .send(
+            "!room:server",
+            Message::Spans(spans!(span!(Format::Bold; "4d6"), ": ", span!(Color::Red; "<14>"))),
+        )
+        .unwrap();
+
+    let (request, body) = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(
+        request.starts_with("PUT /_matrix/client/v3/rooms/!room:server/send/m.room.message/"),
+        "{}",
+        request
+    );
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["body"], "4d6: <14>");
+    assert_eq!(body["format"], "org.matrix.custom.html");
+    assert_eq!(
+        body["formatted_body"],
+        "<b>4d6</b>: <font color=\"#7F0000\">&lt;14&gt;</font>"
+    );
+}
+
+#[test]
+fn test_console_replies_in_order() {
+    let adapter = Arc::new(ConsoleAdapter::new(config::Console {
+        id: "console".to_string(),
+        socket: None,
+        user: "tester".to_string(),
+        channel: "testing".to_string(),
+        perms: vec!["admin".to_string(), "modules".to_string()],
+        format: config::ConsoleFormat::Plain,
+    }));
+
+    let sink: EventSink = {
+        let adapter = Arc::clone(&adapter);
+        Arc::new(move |event| {
+            let source = match &event.source {
+                Source::Adapter(s) => s,
+                Source::Sub { .. } | Source::Capture { .. } => unreachable!(),
+            };
+            let perms = source.implicit_perms().join(", ");
+            let message = match &event.kind {
+                EventKind::Message { message, .. } => message,
+                EventKind::Other(_) => unreachable!(),
+            };
+            let reply = spans!(
+                span!(Format::Bold; event.source.user_pretty().into_owned()),
+                format!(" in {} ({}): {}", event.source.channel_string(), perms, message)
+            );
+            adapter.reply(source.as_ref(), Message::Spans(reply)).unwrap();
+        })
+    };
+
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let output: Output = Arc::new(Mutex::new(SharedBuf(Arc::clone(&buf))));
+    adapter.serve(&b"first\n\nsecond\n"[..], output, &sink).unwrap();
+
+    assert_eq!(
+        String::from_utf8(buf.lock().clone()).unwrap(),
+        "tester in con:testing (admin, modules): first\ntester in con:testing (admin, modules): second\n"
+    );
+}
+
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sqlite_migrations() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations-sqlite");
+    let mut conn = SqliteConnection::open_in_memory().unwrap();
+    db::migrate_sqlite(&mut conn, &dir).unwrap();
+    // applying again is a no-op
+    db::migrate_sqlite(&mut conn, &dir).unwrap();
+
+    conn.execute(
+        "INSERT INTO cmdchars (config_id, channel, cmdchars) VALUES ('irc', 'irc:#test%', '.')",
+        &[],
+    )
+    .unwrap();
+    let mut cmdchars = |channel: &str| -> String {
+        conn.query_one(
+            "SELECT cmdchars FROM cmdchars WHERE config_id = $1 AND $2 LIKE channel ORDER BY channel DESC LIMIT 1",
+            &[&"irc", &channel],
+        )
+        .unwrap()
+        .get(0)
+    };
+    assert_eq!(cmdchars("irc:#test-channel"), ".");
+    assert_eq!(cmdchars("irc:#other"), "!");
+
+    conn.execute(
+        "INSERT INTO modules (name, enabled) VALUES ($1, true) ON CONFLICT (name) DO UPDATE SET enabled = true",
+        &[&"dice"],
+    )
+    .unwrap();
+    conn.execute(
+        "UPDATE modules SET log_level = $1 WHERE name = $2",
+        &[&"debug", &"dice"],
+    )
+    .unwrap();
+    let row = conn
+        .query_one("SELECT name, log_level FROM modules WHERE log_level IS NOT NULL", &[])
+        .unwrap();
+    assert_eq!(row.get::<_, String>(1), "debug");
+    assert!(conn
+        .execute("UPDATE modules SET log_level = $1 WHERE name = $2", &[&"loud", &"dice"])
+        .is_err());
+}
+
+#[test]
+fn test_scheduled_jobs_table() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations-sqlite");
+    let mut conn = SqliteConnection::open_in_memory().unwrap();
+    db::migrate_sqlite(&mut conn, &dir).unwrap();
+
+    let every = Job::new(
+        "remind.fire",
+        "irc",
+        "irc:#chan",
+        "{}",
+        Schedule::parse("every 1h", 0).unwrap(),
+    )
+    .unwrap();
+    let once = Job::new(
+        "remind.fire",
+        "irc",
+        "irc:#chan",
+        "",
+        Schedule::parse("in 10m", rustbot::schedule::now()).unwrap(),
+    )
+    .unwrap();
+    let other = Job::new(
+        "other.job",
+        "dis",
+        "dis:1:2",
+        "",
+        Schedule::parse("cron 0 9 * * *", 0).unwrap(),
+    )
+    .unwrap();
+    let ids: Vec<i64> = [&every, &once, &other]
+        .iter()
+        .map(|j| scheduler::insert(&mut conn, j).unwrap())
+        .collect();
+    assert_eq!(ids, [1, 2, 3]);
+
+    let jobs = scheduler::list(&mut conn, Some("remind.fire")).unwrap();
+    assert_eq!(jobs.len(), 2);
+    // soonest first, and read back as they were stored
+    assert_eq!(jobs[0], Job { id: 2, ..once });
+    assert_eq!(jobs[1], Job { id: 1, ..every });
+    assert_eq!(scheduler::list(&mut conn, None).unwrap().len(), 3);
+
+    assert!(scheduler::delete(&mut conn, 2).unwrap());
+    assert!(!scheduler::delete(&mut conn, 2).unwrap());
+    assert_eq!(scheduler::list(&mut conn, Some("remind.fire")).unwrap().len(), 1);
+}
+
+#[test]
+fn test_scoped_permissions() {
+    let bot = MockBot::new().with_sqlite();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations-sqlite");
+    db::migrate_sqlite(&mut **bot.sql().unwrap(), &dir).unwrap();
+
+    let perms = Permissions::new();
+    let has = |subjects: &[&str], config_id: &str, channel: &str, name: &str| -> bool {
+        let subjects = subjects.iter().map(|s| s.to_string()).collect();
+        let subjects = perms.expand_groups(&bot, subjects).unwrap();
+        perms.has_perm(&bot, &subjects, config_id, channel, name).unwrap()
+    };
+
+    // the old flags were carried over
+    let owner = ["irc:GinjaNinja32!nyx@gn32.uk"];
+    assert!(has(&owner, "irc", "irc:#anywhere", "eval"));
+    assert!(!has(&owner, "discord", "dis:1:2", "eval"));
+
+    bot.sql()
+        .unwrap()
+        .batch_execute(
+            "INSERT INTO grants (subject, permission, config_id, channel) VALUES
+                ('irc:*!*@staff.example', 'raw', 'irc', '*'),
+                ('group:ops', 'ss13.admin', '*', '*'),
+                ('dis-role:1:5', 'eval', 'discord', 'dis:1:*'),
+                ('mx:@root:example.org', '*', '*', '*');
+             INSERT INTO perm_groups (name, member) VALUES ('ops', 'group:staff'), ('staff', 'irc:*!*@staff.example');
+             INSERT INTO command_perms (command, config_id, channel, permission, enabled) VALUES
+                ('bash', '*', '*', NULL, false),
+                ('bash', 'irc', 'irc:#sandbox', 'eval', true),
+                ('roll', 'irc', 'irc:#quiet', NULL, true);",
+        )
+        .unwrap();
+    bot.invalidate(None);
+
+    let staff = ["irc:someone!user@STAFF.example"];
+    assert!(has(&staff, "irc", "irc:#foo", "raw"));
+    assert!(!has(&staff, "irc2", "irc:#foo", "raw"));
+    // through staff -> ops
+    assert!(has(&staff, "irc2", "irc:#foo", "ss13.admin"));
+    assert!(!has(&["irc:someone!user@elsewhere"], "irc", "irc:#foo", "raw"));
+
+    let member = ["dis:42", "dis-role:1:5"];
+    assert!(has(&member, "discord", "dis:1:100", "eval"));
+    assert!(!has(&member, "discord", "dis:2:100", "eval"));
+    assert!(!has(&["dis:42"], "discord", "dis:1:100", "eval"));
+
+    assert!(has(&["mx:@root:example.org"], "matrix", "mx:!room", "anything"));
+
+    // relayed users don't get the relay's permissions
+    assert!(!has(
+        &["sub:GinjaNinja32!nyx@gn32.uk@someone"],
+        "irc",
+        "irc:#foo",
+        "eval"
+    ));
+
+    let over = |command: &str, channel: &str| perms.command_override(&bot, command, "irc", channel).unwrap();
+    assert_eq!(over("bash", "irc:#foo"), Some((None, false)));
+    assert_eq!(over("bash", "irc:#sandbox"), Some((Some("eval".to_string()), true)));
+    assert_eq!(over("roll", "irc:#quiet"), Some((None, true)));
+    assert_eq!(over("roll", "irc:#foo"), None);
+}
+
+#[test]
+fn test_aliases() {
+    let bot = MockBot::new().with_sqlite();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations-sqlite");
+    db::migrate_sqlite(&mut **bot.sql().unwrap(), &dir).unwrap();
+
+    bot.sql()
+        .unwrap()
+        .batch_execute(
+            r#"INSERT INTO aliases (name, config_id, channel, target, transform) VALUES
+                ('r', '*', '*', 'roll', '{"defaults": ["1d20"]}'),
+                ('r', 'irc', 'irc:#dnd', 'roll', '{"defaults": ["4d6"]}'),
+                ('both', '*', '*', 'r;coin', NULL),
+                ('give', '*', '*', 'say', '{"template": {"params": ["who", "what"], "text": "gives {who} {what=a cookie}"}}'),
+                ('a', '*', '*', 'b', NULL),
+                ('b', '*', '*', 'c;a', NULL);"#,
+        )
+        .unwrap();
+    bot.invalidate(None);
+
+    let aliases = Aliases::new();
+    let resolve = |channel: &str, cmd: &str, args: &str| aliases.resolve(&bot, "irc", channel, cmd, args);
+    let one = |cmd: &str, args: &str| vec![(cmd.to_string(), args.to_string())];
+
+    assert_eq!(resolve("irc:#foo", "roll", "2d6").unwrap(), one("roll", "2d6"));
+    assert_eq!(resolve("irc:#foo", "r", "").unwrap(), one("roll", "1d20"));
+    assert_eq!(resolve("irc:#foo", "r", "2d6").unwrap(), one("roll", "2d6"));
+    // the more specific alias wins
+    assert_eq!(resolve("irc:#dnd", "r", "").unwrap(), one("roll", "4d6"));
+
+    assert_eq!(
+        resolve("irc:#foo", "both", "").unwrap(),
+        vec![
+            ("roll".to_string(), "1d20".to_string()),
+            ("coin".to_string(), String::new())
+        ]
+    );
+    assert_eq!(
+        resolve("irc:#foo", "give", "bob").unwrap(),
+        one("say", "gives bob a cookie")
+    );
+    assert_eq!(
+        resolve("irc:#foo", "give", "bob two  biscuits").unwrap(),
+        one("say", "gives bob two  biscuits")
+    );
+
+    let err = resolve("irc:#foo", "a", "").unwrap_err();
+    assert_eq!(err.to_string(), "alias loop: a -> b -> a");
+}
+
+#[test]
+fn test_alias_transforms() {
+    let apply = |t: &str, args: &str| parse_transform(t).unwrap().apply(args).unwrap();
+
+    assert_eq!(apply(r#"{"by_index": [2, 1, "x", -3]}"#, "a b c d"), "b a x c d");
+    assert_eq!(
+        apply(
+            r#"{"regex_replace": {"find": "o", "replace": "0", "global": 1}}"#,
+            "foo"
+        ),
+        "f00"
+    );
+    assert_eq!(apply(r#"{"defaults": ["1", "2", "3"]}"#, "a"), "a 2 3");
+    assert_eq!(
+        apply(
+            r#"{"template": {"params": ["to", "msg"], "text": "{msg=hi} -> {to}"}}"#,
+            "bob"
+        ),
+        "hi -> bob"
+    );
+    assert_eq!(
+        apply(r#"{"chain": [{"defaults": ["x"]}, {"by_index": ["pre", 0]}]}"#, ""),
+        "pre x"
+    );
+
+    assert!(parse_transform(r#"{"nonsense": 1}"#).is_err());
+    // regexes are compiled when the transform is parsed
+    assert!(parse_transform(r#"{"regex_replace": {"find": "(", "replace": "", "global": 1}}"#).is_err());
+    let t = parse_transform(r#"{"template": {"params": [], "text": "{who}"}}"#).unwrap();
+    assert!(t.apply("").is_err());
+}
+
+#[test]
+fn test_irc_sasl_and_accounts() {
+    let adapter = IrcAdapter::new(config::Irc {
+        id: "irc".to_string(),
+        nick: "testbot".to_string(),
+        user: "testbot".to_string(),
+        real: "testbot".to_string(),
+        server: "irc.example".to_string(),
+        port: 6697,
+        pass: None,
+        ssl: true,
+        client_cert: None,
+        client_cert_pass: None,
+        sasl: Some(config::Sasl::Plain {
+            user: "testbot".to_string(),
+            password: "hunter2".to_string(),
+        }),
+        send_limit: config::Limit { burst: 5, every: 2.0 },
+    });
+    let reply = |line: &str| -> Vec<String> {
+        let msg: ::irc::proto::Message = line.parse().unwrap();
+        adapter.negotiate(&msg.command).iter().map(String::from).collect()
+    };
+
+    assert!(reply(":irc.example CAP * LS * :multi-prefix sasl=PLAIN,EXTERNAL away-notify").is_empty());
+    assert_eq!(
+        reply(":irc.example CAP * LS :extended-join account-notify"),
+        ["CAP REQ :multi-prefix account-notify extended-join sasl"]
+    );
+    assert_eq!(
+        reply(":irc.example CAP testbot ACK :multi-prefix account-notify extended-join sasl"),
+        ["AUTHENTICATE PLAIN"]
+    );
+    // base64 of "testbot\0testbot\0hunter2"
+    assert_eq!(
+        reply("AUTHENTICATE +"),
+        ["AUTHENTICATE dGVzdGJvdAB0ZXN0Ym90AGh1bnRlcjI="]
+    );
+    assert_eq!(
+        reply(":irc.example 903 testbot :SASL authentication successful"),
+        ["CAP END"]
+    );
+
+    let see = |line: &str| -> Option<String> {
+        let msg: ::irc::proto::Message = line.parse().unwrap();
+        let account = adapter.account_for(&msg);
+        adapter.track_account(&msg);
+        adapter.track_user(&msg);
+        account
+    };
+
+    see(":alice!a@host JOIN #chan alice_acct :Alice");
+    see(":bob!b@host JOIN #chan * :Bob");
+    assert_eq!(see(":alice!a@host PRIVMSG #chan :hi").as_deref(), Some("alice_acct"));
+    assert_eq!(see(":bob!b@host PRIVMSG #chan :hi"), None);
+
+    see(":bob!b@host ACCOUNT bob_acct");
+    see(":alice!a@host NICK alice2");
+    see(":alice2!a@host ACCOUNT *");
+    assert_eq!(see(":bob!b@host PRIVMSG #chan :hi").as_deref(), Some("bob_acct"));
+    assert_eq!(see(":alice2!a@host PRIVMSG #chan :hi"), None);
+
+    let msg: ::irc::proto::Message = ":bob!b@host PRIVMSG #chan :!roll".parse().unwrap();
+    let event = irc::incoming("testbot", msg, Some("bob_acct".to_string())).unwrap();
+    let source = match &event.source {
+        Source::Adapter(s) => Arc::clone(s),
+        Source::Sub { .. } | Source::Capture { .. } => unreachable!(),
+    };
+    assert_eq!(source.subjects(), ["irc:bob!b@host", "irc-account:bob_acct"]);
+
+    // users are found by the nick they were last seen with
+    let find = |arg: &str| {
+        adapter
+            .find_user(source.as_ref(), &Mention::parse_full(arg).unwrap())
+            .unwrap()
+    };
+    assert_eq!(find("@Bob").as_deref(), Some("bob!b@host"));
+    assert_eq!(find("alice2").as_deref(), Some("alice2!a@host"));
+    assert_eq!(find("alice"), None);
+}
+
+#[test]
+fn test_irc_events() {
+    let see = |line: &str| {
+        let msg: ::irc::proto::Message = line.parse().unwrap();
+        irc::incoming("testbot", msg, None).map(|event| {
+            let other = match event.kind {
+                EventKind::Other(e) => e,
+                EventKind::Message { .. } => panic!("expected an event, got a message"),
+            };
+            (
+                event.source.user_pretty().into_owned(),
+                event.source.channel_string().into_owned(),
+                other,
+            )
+        })
+    };
+    let event = |nick: &str, channel: &str, e| Some((nick.to_string(), channel.to_string(), e));
+
+    assert_eq!(
+        see(":alice!a@host JOIN #chan"),
+        event("alice", "irc:#chan", Event::Join)
+    );
+    assert_eq!(
+        see(":alice!a@host PART #chan :bye"),
+        event(
+            "alice",
+            "irc:#chan",
+            Event::Part {
+                reason: Some("bye".to_string())
+            }
+        )
+    );
+    assert_eq!(
+        see(":alice!a@host QUIT :gone"),
+        event(
+            "alice",
+            "irc:query",
+            Event::Quit {
+                reason: Some("gone".to_string())
+            }
+        )
+    );
+    assert_eq!(
+        see(":op!o@host KICK #chan alice :spam"),
+        event(
+            "op",
+            "irc:#chan",
+            Event::Kick {
+                target: "alice".to_string(),
+                reason: Some("spam".to_string())
+            }
+        )
+    );
+    assert_eq!(
+        see(":alice!a@host NICK alice2"),
+        event(
+            "alice2",
+            "irc:query",
+            Event::Nick {
+                old: "alice".to_string()
+            }
+        )
+    );
+    assert_eq!(
+        see(":op!o@host TOPIC #chan :new topic"),
+        event(
+            "op",
+            "irc:#chan",
+            Event::Topic {
+                topic: "new topic".to_string()
+            }
+        )
+    );
+
+    // the bot's own, and things that aren't events, are ignored
+    assert_eq!(see(":testbot!t@host JOIN #chan"), None);
+    assert_eq!(see(":alice!a@host MODE #chan +o bob"), None);
+}
+
+#[test]
+fn test_rate_limits() {
+    // users aren't limited unless the config says so
+    assert!(config::Limits::default().user.is_none());
+
+    // limits that would never let anything through are rejected, naming the setting
+    for (limit, err) in [
+        ((0, 1.0), "limits.user.burst must be at least 1"),
+        ((1, 0.0), "limits.user.every must be a number of seconds above 0"),
+        ((1, -1.0), "limits.user.every must be a number of seconds above 0"),
+        ((1, f64::NAN), "limits.user.every must be a number of seconds above 0"),
+    ] {
+        let limit = config::Limit {
+            burst: limit.0,
+            every: limit.1,
+        };
+        assert_eq!(limit.check("limits.user").unwrap_err().to_string(), err);
+    }
+
+    let limit = config::Limit { burst: 2, every: 10.0 };
+    assert!(limit.check("limits.user").is_ok());
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+
+    let mut bucket = Bucket::new(limit, start);
+    assert!(bucket.take(at(0)).is_ok());
+    assert!(bucket.take(at(0)).is_ok());
+    assert_eq!(bucket.take(at(0)), Err(Duration::from_secs(10)));
+    assert_eq!(bucket.take(at(4)), Err(Duration::from_secs(6)));
+    assert!(bucket.take(at(10)).is_ok());
+    // never more than the burst, however long it's idle
+    assert_eq!(bucket.tokens(at(1000)), 2.0);
+
+    let limiter = Limiter::new();
+    assert!(limiter.check("irc:alice", limit, at(0)).is_ok());
+    assert!(limiter.check("irc:alice", limit, at(0)).is_ok());
+    let denied = limiter.check("irc:alice", limit, at(1)).unwrap_err();
+    assert_eq!(denied.wait, Duration::from_secs(9));
+    assert!(denied.first);
+    assert!(!limiter.check("irc:alice", limit, at(2)).unwrap_err().first);
+    assert!(limiter.check("irc:bob", limit, at(2)).is_ok());
+
+    let keys: Vec<String> = limiter.list(at(2)).into_iter().map(|(key, ..)| key).collect();
+    assert_eq!(keys, ["irc:alice", "irc:bob"]);
+    // full buckets are forgotten
+    assert_eq!(limiter.list(at(30)).len(), 0);
+
+    assert!(limiter.check("irc:alice", limit, at(30)).is_ok());
+    assert!(limiter.check("irc:alice", limit, at(30)).is_ok());
+    assert_eq!(limiter.reset("IRC:*"), 1);
+    assert!(limiter.check("irc:alice", limit, at(30)).is_ok());
+}
+
+#[test]
+fn test_send_queue_paces_messages() {
+    let queue = SendQueue::new("test", config::Limit { burst: 2, every: 0.1 });
+    let (tx, rx) = mpsc::channel();
+    let start = Instant::now();
+    for i in 0..4 {
+        let tx = tx.clone();
+        queue
+            .push(Box::new(move || {
+                tx.send((i, start.elapsed())).unwrap();
+                Ok(())
+            }))
+            .unwrap();
+    }
+
+    let sent: Vec<(i32, Duration)> = (0..4)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    assert_eq!(sent.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [0, 1, 2, 3]);
+    // the first two go straight away, then one per tenth of a second
+    assert!(sent[1].1 < Duration::from_millis(90), "{:?}", sent);
+    assert!(sent[3].1 >= Duration::from_millis(190), "{:?}", sent);
+}
+
+#[test]
+fn test_module_load_order() {
+    let info = |name: &str, version: &str, depends: &[(&str, &str)]| deps::Info {
+        name: name.to_string(),
+        version: version.to_string(),
+        depends: depends.iter().map(|(m, v)| (m.to_string(), v.to_string())).collect(),
+    };
+    let errors = |failed: Vec<(String, Error)>| -> Vec<String> {
+        failed.into_iter().map(|(m, e)| format!("{m}: {e}")).collect()
+    };
+
+    let loaded = [info("bridge", "0.1.3", &[])];
+    let (order, failed) = deps::load_order(
+        &loaded,
+        vec![
+            info("alpha", "1.0.0", &[("debridge", "0.1"), ("beta", ">=2")]),
+            info("beta", "2.1.0", &[]),
+            info("debridge", "0.1.0", &[("bridge", "0.1")]),
+        ],
+    );
+    assert_eq!(order, ["beta", "debridge", "alpha"]);
+    assert!(failed.is_empty());
+
+    let (order, failed) = deps::load_order(
+        &loaded,
+        vec![
+            info("new", "0.1.0", &[("bridge", "0.2")]),
+            info("newer", "0.1.0", &[("new", "*")]),
+            info("lonely", "0.1.0", &[("absent", "*")]),
+            info("egg", "0.1.0", &[("chicken", "*")]),
+            info("chicken", "0.1.0", &[("egg", "*")]),
+            info("bad", "one", &[]),
+        ],
+    );
+    assert!(order.is_empty());
+    assert_eq!(
+        errors(failed),
+        [
+            "bad: invalid version \"one\": Error parsing major identifier",
+            "new: needs bridge 0.2, but bridge 0.1.3 is loaded",
+            "chicken: needs egg, which can't be loaded before it (circular dependency?)",
+            "egg: needs chicken, which can't be loaded before it (circular dependency?)",
+            "lonely: needs absent, which isn't loaded",
+            "newer: needs new, which failed to load",
+        ]
+    );
+
+    let loaded = [
+        info("a", "0.1.0", &[]),
+        info("b", "0.1.0", &[("a", "*")]),
+        info("c", "0.1.0", &[("b", "*"), ("a", "*")]),
+        info("d", "0.1.0", &[]),
+    ];
+    assert_eq!(deps::dependents(&loaded, "a"), ["c", "b"]);
+    assert_eq!(deps::dependents(&loaded, "c"), Vec::<String>::new());
+}
+
+#[test]
+fn test_build_output() {
+    let mut build = Build::default();
+    for line in [
+        r#"{"reason":"compiler-artifact","target":{"name":"rustbot","kind":["dylib"]},"fresh":true}"#,
+        r#"{"reason":"compiler-message","message":{"level":"warning","rendered":"warning: unused variable"}}"#,
+        r#"{"reason":"compiler-artifact","target":{"name":"mod_dice","kind":["dylib"]},"fresh":false}"#,
+        r#"{"reason":"compiler-artifact","target":{"name":"mod_time","kind":["dylib"]},"fresh":true}"#,
+        r#"{"reason":"build-script-executed","package_id":"rustbot"}"#,
+        "not json at all",
+        r#"{"reason":"build-finished","success":true}"#,
+    ] {
+        build.feed(line);
+    }
+    assert!(build.success);
+    assert_eq!(build.warnings, 1);
+    assert!(build.errors.is_empty());
+    assert_eq!(build.changed(), ["dice"]);
+
+    let mut build = Build::default();
+    for line in [
+        r#"{"reason":"compiler-message","message":{"level":"error","rendered":"error[E0308]: mismatched types"}}"#,
+        r#"{"reason":"compiler-message","message":{"level":"error","rendered":"error: aborting due to 1 previous error"}}"#,
+        r#"{"reason":"build-finished","success":false}"#,
+    ] {
+        build.feed(line);
+    }
+    assert!(!build.success);
+    assert_eq!(build.errors.len(), 2);
+    assert!(build.changed().is_empty());
+}
+
+#[test]
+fn test_split_pipeline() {
+    assert_eq!(bot::split_pipeline("dice 4d6", "!"), ["dice 4d6"]);
+    assert_eq!(bot::split_pipeline("dice 4d6 | !say", "!"), ["dice 4d6", "say"]);
+    assert_eq!(
+        bot::split_pipeline("players|  .grep bob | !say", "!."),
+        ["players", "grep bob", "say"]
+    );
+    // a | not followed by a command is just part of the arguments
+    assert_eq!(bot::split_pipeline("say a | b || c", "!"), ["say a | b || c"]);
+    assert_eq!(bot::split_pipeline("say a |", "!"), ["say a |"]);
+}
@@ -0,0 +1,252 @@
+// #[derive(Args)], which implements rustbot::args::Arg for a struct of command arguments or an
+// enum of keywords. See rustbot::args for what the attributes do.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, LitStr, Result};
+
+#[proc_macro_derive(Args, attributes(arg))]
+pub fn derive_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let res = match &input.data {
+        Data::Struct(data) => derive_struct(&input, &data.fields),
+        Data::Enum(data) => derive_enum(&input, data.variants.iter()),
+        Data::Union(_) => Err(Error::new_spanned(&input, "Args can't be derived for unions")),
+    };
+    res.unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[derive(Default)]
+struct Attrs {
+    flag: bool,
+    long: bool,
+    // Some(None) for a bare `default`, which uses Default::default()
+    default: Option<Option<Expr>>,
+    name: Option<String>,
+}
+
+fn parse_attrs(attrs: &[syn::Attribute]) -> Result<Attrs> {
+    let mut res = Attrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("arg")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("flag") {
+                res.flag = true;
+            } else if meta.path.is_ident("long") {
+                res.long = true;
+            } else if meta.path.is_ident("default") {
+                res.default = Some(if meta.input.peek(syn::Token![=]) {
+                    Some(meta.value()?.parse()?)
+                } else {
+                    None
+                });
+            } else if meta.path.is_ident("name") {
+                res.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error("expected one of flag, long, default, name"));
+            }
+            Ok(())
+        })?;
+    }
+    if res.flag && (res.long || res.default.is_some()) {
+        return Err(Error::new_spanned(
+            &attrs[0],
+            "flags are always optional, and can't also be long or have a default",
+        ));
+    }
+    Ok(res)
+}
+
+// snake_case and CamelCase identifiers as they're typed in commands, e.g. --dry-run or warn-all.
+fn kebab(ident: &syn::Ident) -> String {
+    let mut s = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c == '_' {
+            s.push('-');
+        } else if c.is_uppercase() {
+            if i != 0 {
+                s.push('-');
+            }
+            s.extend(c.to_lowercase());
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+fn derive_struct(input: &DeriveInput, fields: &Fields) -> Result<TokenStream2> {
+    let fields = match fields {
+        Fields::Named(fields) => &fields.named,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "Args can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    // Flags and long options are taken before each positional argument and at the end, so they can
+    // go anywhere except inside a positional argument that takes more than one word.
+    let mut named_decls = vec![];
+    let mut named_arms = vec![];
+    let mut named_inits = vec![];
+
+    let mut positional = vec![];
+    let mut describe = vec![];
+    let mut idents = vec![];
+
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let attrs = parse_attrs(&field.attrs)?;
+        let default = match &attrs.default {
+            Some(Some(expr)) => quote!(#expr),
+            _ => quote!(::std::default::Default::default()),
+        };
+        idents.push(ident);
+
+        if attrs.flag || attrs.long {
+            let name = attrs.name.clone().unwrap_or_else(|| kebab(ident));
+            let flag = format!("--{name}");
+            named_decls.push(quote!(let mut #ident: ::std::option::Option<#ty> = ::std::option::Option::None;));
+
+            if attrs.flag {
+                named_arms.push(quote!(#name => {
+                    #ident = ::std::option::Option::Some(true);
+                    __rest
+                }));
+                named_inits.push(quote!(let #ident = #ident.unwrap_or(false);));
+                describe.push(quote!(::std::format!("[{}]", #flag)));
+            } else {
+                named_arms.push(quote!(#name => {
+                    let (__v, __rest) = ::rustbot::args::derive::field::<#ty>(#flag, __rest)?;
+                    #ident = ::std::option::Option::Some(__v);
+                    __rest
+                }));
+                if attrs.default.is_some() {
+                    named_inits.push(quote!(let #ident = #ident.unwrap_or_else(|| #default);));
+                    describe.push(quote!(::std::format!(
+                        "[{} {}]",
+                        #flag,
+                        <#ty as ::rustbot::args::Arg>::describe_expected()
+                    )));
+                } else {
+                    named_inits.push(quote!(let #ident = match #ident {
+                        ::std::option::Option::Some(__v) => __v,
+                        ::std::option::Option::None => ::rustbot::bail_user!("missing {}", #flag),
+                    };));
+                    describe.push(quote!(::std::format!(
+                        "{} {}",
+                        #flag,
+                        <#ty as ::rustbot::args::Arg>::describe_expected()
+                    )));
+                }
+            }
+            continue;
+        }
+
+        let name = attrs.name.clone().unwrap_or_else(|| ident.to_string());
+        if attrs.default.is_some() {
+            positional.push(quote! {
+                let (#ident, __rest) = match __input {
+                    ::std::option::Option::None => (#default, ::std::option::Option::None),
+                    __input => ::rustbot::args::derive::field::<#ty>(#name, __input)?,
+                };
+                __input = __rest;
+            });
+            describe.push(quote!(::std::format!(
+                "[{}: {}]",
+                #name,
+                <#ty as ::rustbot::args::Arg>::describe_expected()
+            )));
+        } else {
+            positional.push(quote! {
+                let (#ident, __rest) = ::rustbot::args::derive::field::<#ty>(#name, __input)?;
+                __input = __rest;
+            });
+            describe.push(quote!(::std::format!(
+                "{}: {}",
+                #name,
+                <#ty as ::rustbot::args::Arg>::describe_expected()
+            )));
+        }
+    }
+
+    let take_named = if named_arms.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            while let ::std::option::Option::Some((__name, __rest)) = __input.and_then(::rustbot::args::derive::flag) {
+                __input = match __name {
+                    #(#named_arms)*
+                    _ => ::rustbot::bail_user!("unknown flag --{}", __name),
+                };
+            }
+        }
+    };
+    let positional = positional.iter().map(|p| quote!(#take_named #p));
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rustbot::args::Arg for #name #ty_generics #where_clause {
+            #[allow(unused_mut)]
+            fn parse_from(__input: &str) -> ::rustbot::error::Result<(Self, ::std::option::Option<&str>)> {
+                let mut __input = ::rustbot::args::derive::rest(::std::option::Option::Some(__input));
+                #(#named_decls)*
+                #(#positional)*
+                #take_named
+                #(#named_inits)*
+                ::std::result::Result::Ok((Self { #(#idents),* }, __input))
+            }
+
+            fn describe_expected() -> ::std::borrow::Cow<'static, str> {
+                let __parts: ::std::vec::Vec<::std::string::String> = ::std::vec![#(#describe),*];
+                ::std::borrow::Cow::Owned(::std::format!("({})", __parts.join(", ")))
+            }
+        }
+    })
+}
+
+fn derive_enum<'a>(input: &DeriveInput, variants: impl Iterator<Item = &'a syn::Variant>) -> Result<TokenStream2> {
+    let mut names = vec![];
+    let mut arms = vec![];
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new_spanned(
+                variant,
+                "Args can only be derived for enums whose variants have no fields",
+            ));
+        }
+        let attrs = parse_attrs(&variant.attrs)?;
+        let name = attrs.name.unwrap_or_else(|| kebab(&variant.ident));
+        let ident = &variant.ident;
+        arms.push(quote!(#name => Self::#ident,));
+        names.push(name);
+    }
+    let expected = names.join("|");
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rustbot::args::Arg for #name #ty_generics #where_clause {
+            fn parse_from(input: &str) -> ::rustbot::error::Result<(Self, ::std::option::Option<&str>)> {
+                let (atom, rest) = <::rustbot::args::Atom as ::rustbot::args::Arg>::parse_from(input)?;
+                let v = match atom.to_lowercase().as_str() {
+                    #(#arms)*
+                    _ => ::rustbot::bail_user!(
+                        "expected {}, got {:?}",
+                        <Self as ::rustbot::args::Arg>::describe_expected(),
+                        atom.as_str()
+                    ),
+                };
+                ::std::result::Result::Ok((v, rest))
+            }
+
+            fn describe_expected() -> ::std::borrow::Cow<'static, str> {
+                ::std::borrow::Cow::Borrowed(#expected)
+            }
+        }
+    })
+}